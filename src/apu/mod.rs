@@ -0,0 +1,256 @@
+mod dmc;
+mod envelope;
+mod length_counter;
+mod noise;
+mod pulse;
+mod triangle;
+
+use dmc::Dmc;
+use noise::Noise;
+use pulse::Pulse;
+use triangle::Triangle;
+
+use crate::{
+    audio::Resampler,
+    savestate::{SaveStateError, Snapshot, StateReader, StateWriter},
+};
+
+pub const CPU_CLOCK_HZ: f64 = 1_789_773.0;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+pub const APU_STATUS: u16 = 0x4015;
+pub const APU_FRAME_COUNTER: u16 = 0x4017;
+
+// frame counter steps, in CPU cycles since the sequence started
+const QUARTER_FRAME_1: u32 = 7457;
+const HALF_FRAME_1: u32 = 14913;
+const QUARTER_FRAME_3: u32 = 22371;
+const FOUR_STEP_HALF_FRAME_2: u32 = 29829;
+const FOUR_STEP_LENGTH: u32 = 29830;
+const FIVE_STEP_HALF_FRAME_2: u32 = 37281;
+const FIVE_STEP_LENGTH: u32 = 37282;
+
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+
+    five_step_mode: bool,
+    frame_irq_inhibit: bool,
+    frame_irq_flag: bool,
+    frame_cycle: u32,
+    odd_cycle: bool, // pulse timers only run on every other CPU cycle
+
+    resampler: Resampler,
+}
+
+impl Apu {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            five_step_mode: false,
+            frame_irq_inhibit: false,
+            frame_irq_flag: false,
+            frame_cycle: 0,
+            odd_cycle: false,
+            resampler: Resampler::new(CPU_CLOCK_HZ, sample_rate),
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler.set_sample_rate(sample_rate);
+    }
+
+    /// See `audio::dynamic_rate_adjustment`.
+    pub fn set_rate_adjust(&mut self, rate_adjust: f64) {
+        self.resampler.set_rate_adjust(rate_adjust);
+    }
+
+    /// Hands over the samples produced since the last call.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.resampler.take_samples()
+    }
+
+    pub fn frame_irq_pending(&self) -> bool {
+        self.frame_irq_flag
+    }
+
+    pub fn dmc_irq_pending(&self) -> bool {
+        self.dmc.irq_flag
+    }
+
+    pub fn dmc_sample_request(&self) -> Option<u16> {
+        self.dmc.sample_request()
+    }
+
+    pub fn dmc_load_sample(&mut self, data: u8) {
+        self.dmc.load_sample(data);
+    }
+
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = 0;
+        status |= self.pulse1.length_counter.is_active() as u8;
+        status |= (self.pulse2.length_counter.is_active() as u8) << 1;
+        status |= (self.triangle.length_counter.is_active() as u8) << 2;
+        status |= (self.noise.length_counter.is_active() as u8) << 3;
+        status |= (self.dmc.is_active() as u8) << 4;
+        status |= (self.frame_irq_flag as u8) << 6;
+        status |= (self.dmc.irq_flag as u8) << 7;
+
+        // reading the status acknowledges the frame interrupt
+        self.frame_irq_flag = false;
+        status
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write_register(addr & 0b11, data),
+            0x4004..=0x4007 => self.pulse2.write_register(addr & 0b11, data),
+            0x4008..=0x400B => self.triangle.write_register(addr & 0b11, data),
+            0x400C..=0x400F => self.noise.write_register(addr & 0b11, data),
+            0x4010..=0x4013 => self.dmc.write_register(addr & 0b11, data),
+            APU_STATUS => {
+                self.pulse1.length_counter.set_enabled(data & 0x01 != 0);
+                self.pulse2.length_counter.set_enabled(data & 0x02 != 0);
+                self.triangle.length_counter.set_enabled(data & 0x04 != 0);
+                self.noise.length_counter.set_enabled(data & 0x08 != 0);
+                self.dmc.set_enabled(data & 0x10 != 0);
+            }
+            APU_FRAME_COUNTER => {
+                self.five_step_mode = data & 0x80 != 0;
+                self.frame_irq_inhibit = data & 0x40 != 0;
+                if self.frame_irq_inhibit {
+                    self.frame_irq_flag = false;
+                }
+
+                self.frame_cycle = 0;
+                if self.five_step_mode {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Advances the APU by one CPU cycle.
+    pub fn tick(&mut self) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        self.clock_frame_counter();
+
+        self.resampler.add_sample(self.mix());
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+
+        match self.frame_cycle {
+            QUARTER_FRAME_1 | QUARTER_FRAME_3 => self.clock_quarter_frame(),
+            HALF_FRAME_1 => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            FOUR_STEP_HALF_FRAME_2 if !self.five_step_mode => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                if !self.frame_irq_inhibit {
+                    self.frame_irq_flag = true;
+                }
+            }
+            FOUR_STEP_LENGTH if !self.five_step_mode => self.frame_cycle = 0,
+            FIVE_STEP_HALF_FRAME_2 => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            FIVE_STEP_LENGTH => self.frame_cycle = 0,
+            _ => (),
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear_counter();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.length_counter.clock();
+        self.pulse2.length_counter.clock();
+        self.triangle.length_counter.clock();
+        self.noise.length_counter.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    /*
+    The 2A03 mixes its channels through two resistor networks, which gives a nonlinear
+    response: https://www.nesdev.org/wiki/APU_Mixer
+    */
+    fn mix(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let tnd = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.output() as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse_out + tnd_out
+    }
+}
+
+/*
+The resampler is not part of the machine, so a loaded state keeps playing through the
+current one.
+*/
+impl Snapshot for Apu {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.pulse1.save_state(state);
+        self.pulse2.save_state(state);
+        self.triangle.save_state(state);
+        self.noise.save_state(state);
+        self.dmc.save_state(state);
+        state.write_bool(self.five_step_mode);
+        state.write_bool(self.frame_irq_inhibit);
+        state.write_bool(self.frame_irq_flag);
+        state.write_u32(self.frame_cycle);
+        state.write_bool(self.odd_cycle);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.pulse1.load_state(state)?;
+        self.pulse2.load_state(state)?;
+        self.triangle.load_state(state)?;
+        self.noise.load_state(state)?;
+        self.dmc.load_state(state)?;
+        self.five_step_mode = state.read_bool()?;
+        self.frame_irq_inhibit = state.read_bool()?;
+        self.frame_irq_flag = state.read_bool()?;
+        self.frame_cycle = state.read_u32()?;
+        self.odd_cycle = state.read_bool()?;
+        Ok(())
+    }
+}
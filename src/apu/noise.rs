@@ -0,0 +1,102 @@
+use crate::{
+    apu::{envelope::Envelope, length_counter::LengthCounter},
+    savestate::{SaveStateError, Snapshot, StateReader, StateWriter},
+};
+
+const PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+/*
+Noise channel ($400C-$400F).
+
+A 15 bit linear feedback shift register whose feedback taps bit 1 normally, or bit 6 in
+"short" mode, which produces a 93 step metallic sequence.
+*/
+pub struct Noise {
+    short_mode: bool,
+    shift_register: u16,
+    timer_period: u16,
+    timer: u16,
+
+    pub envelope: Envelope,
+    pub length_counter: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Self {
+            short_mode: false,
+            shift_register: 1,
+            timer_period: PERIOD_TABLE[0],
+            timer: 0,
+            envelope: Envelope::default(),
+            length_counter: LengthCounter::default(),
+        }
+    }
+
+    pub fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.length_counter.set_halted(data & 0x20 != 0);
+                self.envelope.write_control(data);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = data & 0x80 != 0;
+                self.timer_period = PERIOD_TABLE[(data & 0x0f) as usize];
+            }
+            3 => {
+                self.length_counter.load(data >> 3);
+                self.envelope.restart();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocked every CPU cycle; the period table is in CPU cycles.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.shift_register & 1 != 0 || !self.length_counter.is_active() {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl Snapshot for Noise {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.short_mode);
+        state.write_u16(self.shift_register);
+        state.write_u16(self.timer_period);
+        state.write_u16(self.timer);
+        self.envelope.save_state(state);
+        self.length_counter.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.short_mode = state.read_bool()?;
+        self.shift_register = state.read_u16()?;
+        self.timer_period = state.read_u16()?;
+        self.timer = state.read_u16()?;
+        self.envelope.load_state(state)?;
+        self.length_counter.load_state(state)?;
+
+        if self.timer_period == 0 {
+            return Err(SaveStateError::Corrupt);
+        }
+        Ok(())
+    }
+}
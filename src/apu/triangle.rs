@@ -0,0 +1,103 @@
+use crate::{
+    apu::length_counter::LengthCounter,
+    savestate::{SaveStateError, Snapshot, StateReader, StateWriter},
+};
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+/*
+Triangle channel ($4008-$400B).
+
+Has no volume control; instead a linear counter gates the sequencer in addition to the
+length counter. Its timer runs at the full CPU clock.
+*/
+#[derive(Default)]
+pub struct Triangle {
+    control: bool, // doubles as the length counter halt flag
+    linear_counter_reload_value: u8,
+    linear_counter: u8,
+    linear_counter_reload: bool,
+
+    timer_period: u16,
+    timer: u16,
+    sequence_step: u8,
+
+    pub length_counter: LengthCounter,
+}
+
+impl Triangle {
+    pub fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.control = data & 0x80 != 0;
+                self.length_counter.set_halted(self.control);
+                self.linear_counter_reload_value = data & 0x7f;
+            }
+            1 => {}
+            2 => {
+                self.timer_period = (self.timer_period & 0x700) | data as u16;
+            }
+            3 => {
+                self.timer_period = (self.timer_period & 0xff) | ((data as u16 & 0b111) << 8);
+                self.length_counter.load(data >> 3);
+                self.linear_counter_reload = true;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.length_counter.is_active() && self.linear_counter > 0 {
+                self.sequence_step = (self.sequence_step + 1) & 0x1f;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_linear_counter(&mut self) {
+        if self.linear_counter_reload {
+            self.linear_counter = self.linear_counter_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.control {
+            self.linear_counter_reload = false;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.sequence_step as usize]
+    }
+}
+
+impl Snapshot for Triangle {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.control);
+        state.write_u8(self.linear_counter_reload_value);
+        state.write_u8(self.linear_counter);
+        state.write_bool(self.linear_counter_reload);
+        state.write_u16(self.timer_period);
+        state.write_u16(self.timer);
+        state.write_u8(self.sequence_step);
+        self.length_counter.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.control = state.read_bool()?;
+        self.linear_counter_reload_value = state.read_u8()?;
+        self.linear_counter = state.read_u8()?;
+        self.linear_counter_reload = state.read_bool()?;
+        self.timer_period = state.read_u16()?;
+        self.timer = state.read_u16()?;
+        self.sequence_step = state.read_u8()? & 0x1f;
+        self.length_counter.load_state(state)
+    }
+}
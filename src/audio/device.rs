@@ -0,0 +1,71 @@
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use crate::audio::{AudioSink, RingBuffer};
+
+// ~100ms of queued audio at 48kHz
+const QUEUE_CAPACITY: usize = 4800;
+
+/*
+Plays the stream on the default output device. The cpal callback runs on its own thread
+and drains the shared ring buffer, duplicating the mono signal to every channel.
+*/
+pub struct DeviceSink {
+    queue: Arc<Mutex<RingBuffer>>,
+    sample_rate: u32,
+    _stream: cpal::Stream,
+}
+
+impl DeviceSink {
+    pub fn open() -> Result<Self, Box<dyn std::error::Error>> {
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .ok_or("no audio output device")?;
+        let config = device.default_output_config()?;
+        if config.sample_format() != cpal::SampleFormat::F32 {
+            return Err(format!("unsupported sample format {}", config.sample_format()).into());
+        }
+
+        let sample_rate = config.sample_rate().0;
+        let channels = config.channels() as usize;
+        let queue = Arc::new(Mutex::new(RingBuffer::new(QUEUE_CAPACITY)));
+
+        let callback_queue = queue.clone();
+        let stream = device.build_output_stream(
+            &config.into(),
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                let mut queue = callback_queue.lock().unwrap();
+                for frame in data.chunks_mut(channels) {
+                    let sample = queue.pop();
+                    frame.fill(sample);
+                }
+            },
+            |err| eprintln!("audio stream error: {err}"),
+            None,
+        )?;
+        stream.play()?;
+
+        Ok(Self {
+            queue,
+            sample_rate,
+            _stream: stream,
+        })
+    }
+}
+
+impl AudioSink for DeviceSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) {
+        self.queue.lock().unwrap().push_slice(samples);
+    }
+
+    fn fill_level(&self) -> f32 {
+        let queue = self.queue.lock().unwrap();
+        queue.len() as f32 / queue.capacity() as f32
+    }
+}
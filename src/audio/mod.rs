@@ -0,0 +1,54 @@
+#[cfg(feature = "audio-device")]
+mod device;
+mod resampler;
+mod ring_buffer;
+mod wav;
+
+#[cfg(feature = "audio-device")]
+pub use device::DeviceSink;
+pub use resampler::Resampler;
+pub use ring_buffer::RingBuffer;
+pub use wav::WavSink;
+
+// how far the output rate may drift from nominal to keep the queue half full (0.5%)
+const MAX_RATE_DELTA: f64 = 0.005;
+
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+
+    fn write(&mut self, samples: &[f32]);
+
+    /// How full the playback queue is, from 0.0 to 1.0. Sinks that aren't paced by a
+    /// sound card are never ahead or behind, so they report half full.
+    fn fill_level(&self) -> f32 {
+        0.5
+    }
+}
+
+/*
+Dynamic rate control: video is paced by vsync, audio by the sound card, and the two
+clocks never agree exactly. Instead of letting the queue drain or overflow (crackles),
+slightly speed up or slow down the resampler depending on how full the queue is.
+*/
+pub fn dynamic_rate_adjustment(fill_level: f32) -> f64 {
+    1.0 + MAX_RATE_DELTA * (1.0 - 2.0 * fill_level.clamp(0.0, 1.0) as f64)
+}
+
+/// Discards everything, for running without a sound device.
+pub struct NullSink {
+    sample_rate: u32,
+}
+
+impl NullSink {
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sample_rate }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, _samples: &[f32]) {}
+}
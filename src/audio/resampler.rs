@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+
+use lazy_static::lazy_static;
+
+// taps of the band-limited impulse, and how many sub-sample positions it is tabulated at
+const KERNEL_WIDTH: usize = 16;
+const KERNEL_PHASES: usize = 64;
+
+// cutoff of the low-pass, relative to the output Nyquist frequency
+const CUTOFF: f64 = 0.9;
+
+// removes the DC offset of the APU output (the NES itself has a ~90Hz high-pass)
+const HIGH_PASS_HZ: f64 = 90.0;
+
+lazy_static! {
+    /*
+    Blackman windowed sinc impulses, one per phase, each normalized to a sum of 1 so a step
+    of amplitude d always settles at exactly d.
+    */
+    static ref KERNEL: [[f32; KERNEL_WIDTH]; KERNEL_PHASES] = {
+        let mut kernel = [[0f32; KERNEL_WIDTH]; KERNEL_PHASES];
+        for (phase, taps) in kernel.iter_mut().enumerate() {
+            let offset = phase as f64 / KERNEL_PHASES as f64;
+            let mut sum = 0.0;
+            let mut values = [0f64; KERNEL_WIDTH];
+
+            for (i, value) in values.iter_mut().enumerate() {
+                let x = i as f64 - (KERNEL_WIDTH / 2) as f64 - offset;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    let t = std::f64::consts::PI * CUTOFF * x;
+                    t.sin() / t
+                };
+                let window_x = (x + KERNEL_WIDTH as f64 / 2.0) / KERNEL_WIDTH as f64;
+                let window = 0.42 - 0.5 * (2.0 * std::f64::consts::PI * window_x).cos()
+                    + 0.08 * (4.0 * std::f64::consts::PI * window_x).cos();
+                *value = sinc * window.max(0.0);
+                sum += *value;
+            }
+
+            for (tap, value) in taps.iter_mut().zip(values) {
+                *tap = (value / sum) as f32;
+            }
+        }
+        kernel
+    };
+}
+
+/*
+Band-limited resampler from the CPU clock down to an audio rate.
+
+The APU output is a step function that changes on a handful of cycles, so instead of
+filtering every input sample, each change in amplitude is added to the output as a
+band-limited step (blip_buf style). The resulting impulses are integrated back into a
+signal as output samples complete.
+*/
+pub struct Resampler {
+    clock_rate: f64,
+    sample_rate: f64,
+    rate_adjust: f64,
+    step: f64, // output samples per input clock
+
+    time: f64, // position of the current clock within the current output sample
+    last_amplitude: f32,
+    pending: VecDeque<f32>,
+    integrator: f32,
+
+    high_pass_factor: f32,
+    high_pass_input: f32,
+    high_pass_output: f32,
+
+    output: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        let mut resampler = Self {
+            clock_rate,
+            sample_rate: sample_rate as f64,
+            rate_adjust: 1.0,
+            step: 0.0,
+            time: 0.0,
+            last_amplitude: 0.0,
+            pending: VecDeque::from([0.0; KERNEL_WIDTH]),
+            integrator: 0.0,
+            high_pass_factor: 0.0,
+            high_pass_input: 0.0,
+            high_pass_output: 0.0,
+            output: Vec::new(),
+        };
+        resampler.update_step();
+        resampler
+    }
+
+    fn update_step(&mut self) {
+        self.step = self.sample_rate * self.rate_adjust / self.clock_rate;
+
+        let rc = 1.0 / (2.0 * std::f64::consts::PI * HIGH_PASS_HZ);
+        let dt = 1.0 / self.sample_rate;
+        self.high_pass_factor = (rc / (rc + dt)) as f32;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f64;
+        self.update_step();
+    }
+
+    /// Nudges the effective output rate (1.0 = nominal), used to keep the audio queue level.
+    pub fn set_rate_adjust(&mut self, rate_adjust: f64) {
+        self.rate_adjust = rate_adjust;
+        self.update_step();
+    }
+
+    /// Feeds the amplitude for one input clock.
+    pub fn add_sample(&mut self, amplitude: f32) {
+        let delta = amplitude - self.last_amplitude;
+        if delta != 0.0 {
+            self.last_amplitude = amplitude;
+            let phase = ((self.time * KERNEL_PHASES as f64) as usize).min(KERNEL_PHASES - 1);
+            for (pending, tap) in self.pending.iter_mut().zip(KERNEL[phase].iter()) {
+                *pending += delta * tap;
+            }
+        }
+
+        self.time += self.step;
+        while self.time >= 1.0 {
+            self.time -= 1.0;
+            self.emit_sample();
+        }
+    }
+
+    fn emit_sample(&mut self) {
+        let impulse = self.pending.pop_front().unwrap_or(0.0);
+        self.pending.push_back(0.0);
+        self.integrator += impulse;
+
+        self.high_pass_output = self.high_pass_factor
+            * (self.high_pass_output + self.integrator - self.high_pass_input);
+        self.high_pass_input = self.integrator;
+
+        self.output.push(self.high_pass_output);
+    }
+
+    /// Hands over the output samples completed since the last call.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.output)
+    }
+}
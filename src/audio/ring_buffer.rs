@@ -0,0 +1,56 @@
+/*
+Fixed capacity FIFO of samples between the emulator and an audio callback. Writes that
+don't fit are dropped, and reads past the end repeat the last sample, so over/underruns
+degrade into a short glitch rather than a pop.
+*/
+pub struct RingBuffer {
+    data: Vec<f32>,
+    read_index: usize,
+    len: usize,
+    last_sample: f32,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            data: vec![0.0; capacity],
+            read_index: 0,
+            len: 0,
+            last_sample: 0.0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns how many samples were actually queued.
+    pub fn push_slice(&mut self, samples: &[f32]) -> usize {
+        let count = samples.len().min(self.capacity() - self.len);
+        for &sample in &samples[..count] {
+            let write_index = (self.read_index + self.len) % self.capacity();
+            self.data[write_index] = sample;
+            self.len += 1;
+        }
+        count
+    }
+
+    pub fn pop(&mut self) -> f32 {
+        if self.is_empty() {
+            return self.last_sample;
+        }
+
+        self.last_sample = self.data[self.read_index];
+        self.read_index = (self.read_index + 1) % self.capacity();
+        self.len -= 1;
+        self.last_sample
+    }
+}
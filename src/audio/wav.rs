@@ -0,0 +1,89 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::audio::AudioSink;
+
+const HEADER_SIZE: u32 = 44;
+
+/*
+Writes the audio stream to a 16 bit mono PCM .wav file. The sizes in the header are
+patched when the sink is finished or dropped.
+*/
+pub struct WavSink {
+    writer: BufWriter<File>,
+    sample_rate: u32,
+    data_size: u32,
+    finished: bool,
+}
+
+impl WavSink {
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> io::Result<Self> {
+        let mut sink = Self {
+            writer: BufWriter::new(File::create(path)?),
+            sample_rate,
+            data_size: 0,
+            finished: false,
+        };
+        sink.write_header()?;
+        Ok(sink)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let byte_rate = self.sample_rate * 2;
+
+        self.writer.write_all(b"RIFF")?;
+        self.writer
+            .write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.writer.write_all(b"WAVE")?;
+
+        self.writer.write_all(b"fmt ")?;
+        self.writer.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+        self.writer.write_all(&1u16.to_le_bytes())?; // PCM
+        self.writer.write_all(&1u16.to_le_bytes())?; // mono
+        self.writer.write_all(&self.sample_rate.to_le_bytes())?;
+        self.writer.write_all(&byte_rate.to_le_bytes())?;
+        self.writer.write_all(&2u16.to_le_bytes())?; // block align
+        self.writer.write_all(&16u16.to_le_bytes())?; // bits per sample
+
+        self.writer.write_all(b"data")?;
+        self.writer.write_all(&self.data_size.to_le_bytes())
+    }
+
+    /// Flushes the samples and fixes up the header sizes.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+impl AudioSink for WavSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            if self.writer.write_all(&pcm.to_le_bytes()).is_err() {
+                return;
+            }
+            self.data_size += 2;
+        }
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
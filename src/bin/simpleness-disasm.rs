@@ -0,0 +1,450 @@
+/*
+Disassembles a .nes file into ca65 source.
+
+    simpleness-disasm <rom.nes> [--mapper N] [--output game.s]
+
+Code is found by following the reset, NMI and IRQ vectors through branches, jumps and
+subroutine calls, everything else is written as data. Switchable banks are assumed to sit
+in the mapper's switchable window, and jumps out of a bank are only followed into the
+fixed banks. Unofficial opcodes are written as `.byte` with the instruction in a comment,
+since ca65 doesn't produce the same encoding for all of them.
+
+The header, each PRG bank (BANK_00 onwards) and the CHR ROM get their own segment. Linked
+back to back in that order, they give the original file.
+*/
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    fs,
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use simpleness::{
+    RomInfo,
+    cpu::{
+        disassembler::{DisassembledInstruction, disassemble_one},
+        instructions::{AddressingMode, Instruction},
+    },
+    memory::rom_info::HEADER_SIZE,
+};
+
+const PRG_UNIT: usize = 0x4000;
+
+const VECTORS: [(&str, u16); 3] = [("nmi", 0xfffa), ("reset", 0xfffc), ("irq", 0xfffe)];
+const DATA_ROW_BYTES: usize = 16;
+
+struct Options {
+    rom_path: PathBuf,
+    mapper: Option<u16>,
+    output: Option<PathBuf>,
+}
+
+struct RomImage {
+    header: Vec<u8>,
+    trainer: Vec<u8>,
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    mapper: u16,
+}
+
+struct Bank<'a> {
+    data: &'a [u8],
+    origin: u16,
+    fixed: bool, // always mapped at `origin`, so other banks can jump into it
+}
+
+impl Bank<'_> {
+    fn contains(&self, address: u16) -> bool {
+        (address as usize) >= self.origin as usize
+            && (address as usize) < self.origin as usize + self.data.len()
+    }
+
+    fn offset(&self, address: u16) -> usize {
+        (address - self.origin) as usize
+    }
+}
+
+/// What the code walk found: instruction starts and labels, by bank and address.
+#[derive(Default)]
+struct Analysis {
+    instructions: BTreeSet<(usize, u16)>,
+    labels: BTreeMap<(usize, u16), String>,
+}
+
+fn usage() -> String {
+    String::from("usage: simpleness-disasm <rom.nes> [--mapper N] [--output game.s]")
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut mapper = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {arg}"))
+        };
+
+        match arg.as_str() {
+            "--mapper" => {
+                mapper = Some(
+                    value()?
+                        .parse()
+                        .map_err(|_| String::from("--mapper expects a number"))?,
+                )
+            }
+            "--output" => output = Some(PathBuf::from(value()?)),
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {arg}")),
+            _ => rom_path = Some(PathBuf::from(arg)),
+        }
+    }
+
+    Ok(Options {
+        rom_path: rom_path.ok_or_else(usage)?,
+        mapper,
+        output,
+    })
+}
+
+fn split_rom(content: &[u8]) -> Result<RomImage, String> {
+    let header = content
+        .first_chunk::<HEADER_SIZE>()
+        .ok_or_else(|| String::from("not an iNES file"))?;
+    let info = RomInfo::parse(header).ok_or_else(|| String::from("not an iNES file"))?;
+    if info.file_size().is_none_or(|size| content.len() < size) {
+        return Err(String::from("the file is shorter than its header says"));
+    }
+
+    Ok(RomImage {
+        header: header.to_vec(),
+        trainer: content[info.trainer_range()].to_vec(),
+        prg_rom: content[info.prg_rom_range()].to_vec(),
+        chr_rom: content[info.chr_rom_range()].to_vec(),
+        mapper: info.mapper,
+    })
+}
+
+/*
+Where each PRG bank shows up in the CPU address space by default: the fixed banks at
+their window, the switchable ones at the start of the switchable window.
+*/
+fn map_banks(prg_rom: &[u8], mapper: u16) -> Result<Vec<Bank<'_>>, String> {
+    let (bank_size, fixed): (usize, Vec<u16>) = match mapper {
+        0 | 3 if prg_rom.len() == PRG_UNIT => (PRG_UNIT, vec![0xc000]),
+        0 | 3 => (prg_rom.len(), vec![0x8000]),
+        1 | 2 => (PRG_UNIT, vec![0xc000]), // last bank fixed at $C000
+        4 => (0x2000, vec![0xc000, 0xe000]), // last two banks fixed
+        7 | 34 | 66 => (2 * PRG_UNIT, Vec::new()), // the whole 32K switches
+        _ => return Err(format!("mapper {mapper} isn't supported")),
+    };
+    if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(bank_size) {
+        return Err(String::from("the PRG ROM doesn't split into whole banks"));
+    }
+
+    let bank_count = prg_rom.len() / bank_size;
+    let first_fixed = bank_count.saturating_sub(fixed.len());
+    Ok(prg_rom
+        .chunks(bank_size)
+        .enumerate()
+        .map(|(index, data)| match index.checked_sub(first_fixed) {
+            Some(fixed_index) => Bank {
+                data,
+                origin: fixed[fixed_index],
+                fixed: true,
+            },
+            None => Bank {
+                data,
+                origin: 0x8000,
+                fixed: false,
+            },
+        })
+        .collect())
+}
+
+/// The bank an address refers to, seen from code running in `from`.
+fn resolve(banks: &[Bank], from: usize, address: u16) -> Option<usize> {
+    if banks[from].contains(address) {
+        return Some(from);
+    }
+    banks
+        .iter()
+        .position(|bank| bank.fixed && bank.contains(address))
+}
+
+fn decode(banks: &[Bank], bank: usize, address: u16) -> Option<DisassembledInstruction> {
+    let bank_data = &banks[bank];
+    let instruction = disassemble_one(&bank_data.data[bank_data.offset(address)..], address)?;
+    (!instruction.is_truncated()).then_some(instruction)
+}
+
+fn label_name(banks: &[Bank], bank: usize, address: u16) -> String {
+    if banks[bank].fixed {
+        format!("L_{address:04X}")
+    } else {
+        format!("B{bank:02}_{address:04X}")
+    }
+}
+
+fn analyze(banks: &[Bank]) -> Analysis {
+    let mut analysis = Analysis::default();
+    let mut pending = Vec::new();
+
+    for (name, vector) in VECTORS {
+        let Some(bank) = resolve(banks, banks.len() - 1, vector) else {
+            continue;
+        };
+        let offset = banks[bank].offset(vector);
+        let Some(entry) = banks[bank].data.get(offset..offset + 2) else {
+            continue;
+        };
+        let entry = u16::from_le_bytes([entry[0], entry[1]]);
+
+        if let Some(entry_bank) = resolve(banks, bank, entry) {
+            analysis
+                .labels
+                .entry((entry_bank, entry))
+                .or_insert_with(|| name.to_string());
+            pending.push((entry_bank, entry));
+        }
+    }
+
+    while let Some((bank, mut address)) = pending.pop() {
+        while banks[bank].contains(address) && !analysis.instructions.contains(&(bank, address)) {
+            let Some(instruction) = decode(banks, bank, address) else {
+                break;
+            };
+            analysis.instructions.insert((bank, address));
+
+            let instr = instruction.opcode.instr;
+            let follows_target = instruction.opcode.mode == AddressingMode::Rel
+                || (matches!(instr, Instruction::JMP | Instruction::JSR)
+                    && instruction.opcode.mode == AddressingMode::Abs);
+            if follows_target
+                && let Some(target) = instruction.target()
+                && let Some(target_bank) = resolve(banks, bank, target)
+            {
+                analysis
+                    .labels
+                    .entry((target_bank, target))
+                    .or_insert_with(|| label_name(banks, target_bank, target));
+                pending.push((target_bank, target));
+            }
+
+            let ends_flow = matches!(
+                instr,
+                Instruction::JMP
+                    | Instruction::RTS
+                    | Instruction::RTI
+                    | Instruction::BRK
+                    | Instruction::JAM
+            );
+            if ends_flow {
+                break;
+            }
+            address = address.wrapping_add(instruction.len());
+        }
+    }
+
+    analysis
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("${byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn write_data(source: &mut String, data: &[u8]) {
+    for row in data.chunks(DATA_ROW_BYTES) {
+        writeln!(source, "    .byte {}", hex_bytes(row)).unwrap();
+    }
+}
+
+fn format_instruction(
+    banks: &[Bank],
+    analysis: &Analysis,
+    bank: usize,
+    instruction: &DisassembledInstruction,
+) -> String {
+    if instruction.opcode.is_unofficial() {
+        return format!(
+            "    .byte {} ; {instruction}",
+            hex_bytes(&instruction.bytes)
+        );
+    }
+
+    let mode = instruction.opcode.mode;
+    let label = instruction
+        .target()
+        .filter(|_| mode != AddressingMode::Imm)
+        .and_then(|target| {
+            let target_bank = resolve(banks, bank, target)?;
+            analysis.labels.get(&(target_bank, target))
+        });
+
+    let operand = match label {
+        Some(label) => mode.syntax(label),
+        // ca65 picks zero page addressing for small addresses unless told otherwise
+        None if matches!(
+            mode,
+            AddressingMode::Abs | AddressingMode::AbsX | AddressingMode::AbsY
+        ) && instruction.operand() < 0x100 =>
+        {
+            mode.syntax(&format!("a:${:04X}", instruction.operand()))
+        }
+        None => mode.format(instruction.operand(), instruction.address),
+    };
+
+    format!("    {} {operand}", instruction.opcode.instr)
+        .trim_end()
+        .to_string()
+}
+
+fn write_bank(source: &mut String, banks: &[Bank], analysis: &Analysis, bank: usize) {
+    let bank_data = &banks[bank];
+    writeln!(source, "\n.segment \"BANK_{bank:02}\"").unwrap();
+    writeln!(source, ".org ${:04X}", bank_data.origin).unwrap();
+
+    let label = |address: u16| analysis.labels.get(&(bank, address));
+    let is_code = |address: u16| analysis.instructions.contains(&(bank, address));
+
+    let mut offset = 0;
+    while offset < bank_data.data.len() {
+        let address = bank_data.origin + offset as u16;
+        if let Some(label) = label(address) {
+            writeln!(source, "{label}:").unwrap();
+        }
+
+        if is_code(address) {
+            let instruction = decode(banks, bank, address).unwrap();
+            // jumps into the middle of an instruction get a label relative to it
+            for inner in 1..instruction.len() {
+                if let Some(label) = label(address + inner) {
+                    writeln!(source, "{label} = * + {inner}").unwrap();
+                }
+            }
+            writeln!(
+                source,
+                "{}",
+                format_instruction(banks, analysis, bank, &instruction)
+            )
+            .unwrap();
+            offset += instruction.bytes.len();
+            continue;
+        }
+
+        let vectors_here = address == VECTORS[0].1
+            && offset + 6 <= bank_data.data.len()
+            && (1..6).all(|inner| !is_code(address + inner) && label(address + inner).is_none());
+        if vectors_here {
+            let entries: Vec<String> = VECTORS
+                .iter()
+                .map(|(_, vector)| {
+                    let offset = bank_data.offset(*vector);
+                    let entry =
+                        u16::from_le_bytes([bank_data.data[offset], bank_data.data[offset + 1]]);
+                    resolve(banks, bank, entry)
+                        .and_then(|entry_bank| analysis.labels.get(&(entry_bank, entry)))
+                        .cloned()
+                        .unwrap_or_else(|| format!("${entry:04X}"))
+                })
+                .collect();
+            writeln!(source, "    .word {}", entries.join(", ")).unwrap();
+            offset += 6;
+            continue;
+        }
+
+        // data runs until the next row, label or instruction
+        let mut end = offset + 1;
+        while end < bank_data.data.len()
+            && end - offset < DATA_ROW_BYTES
+            && !is_code(bank_data.origin + end as u16)
+            && label(bank_data.origin + end as u16).is_none()
+            && bank_data.origin as usize + end != VECTORS[0].1 as usize
+        {
+            end += 1;
+        }
+        write_data(source, &bank_data.data[offset..end]);
+        offset = end;
+    }
+}
+
+fn disassemble_rom(rom: &RomImage, mapper: u16, rom_name: &str) -> Result<String, String> {
+    let banks = map_banks(&rom.prg_rom, mapper)?;
+    let analysis = analyze(&banks);
+
+    let mut source = String::new();
+    writeln!(source, "; {rom_name}, disassembled by simpleness-disasm").unwrap();
+    writeln!(
+        source,
+        "; mapper {mapper}, {} PRG banks of {}K",
+        banks.len(),
+        banks[0].data.len() / 1024
+    )
+    .unwrap();
+    writeln!(source, "\n.setcpu \"6502\"").unwrap();
+
+    writeln!(source, "\n.segment \"HEADER\"").unwrap();
+    write_data(&mut source, &rom.header);
+    if !rom.trainer.is_empty() {
+        writeln!(source, "\n.segment \"TRAINER\"").unwrap();
+        write_data(&mut source, &rom.trainer);
+    }
+
+    for bank in 0..banks.len() {
+        write_bank(&mut source, &banks, &analysis, bank);
+    }
+
+    if !rom.chr_rom.is_empty() {
+        writeln!(source, "\n.segment \"CHR\"").unwrap();
+        write_data(&mut source, &rom.chr_rom);
+    }
+    Ok(source)
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let content = fs::read(&options.rom_path)
+        .map_err(|err| format!("failed to read {}: {err}", options.rom_path.display()))?;
+    let rom = split_rom(&content)?;
+    let mapper = options.mapper.unwrap_or(rom.mapper);
+
+    let rom_name = options
+        .rom_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let source = disassemble_rom(&rom, mapper, &rom_name)?;
+
+    match &options.output {
+        Some(path) => fs::write(path, source)
+            .map_err(|err| format!("failed to write {}: {err}", path.display())),
+        None => {
+            print!("{source}");
+            Ok(())
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
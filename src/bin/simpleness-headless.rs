@@ -0,0 +1,340 @@
+/*
+Runs a ROM without a window, for CI and regression pipelines.
+
+    simpleness-headless <rom.nes> [--info] [--frames N] [--input script.txt]
+                        [--dump 60,120,...] [--dump-dir DIR] [--format png|rgba]
+                        [--wav audio.wav] [--gdb 127.0.0.1:2345]
+                        [--trace trace.log [--trace-range C000-CFFF] [--trace-from "pc == C000"]]
+
+Frames are numbered from 1. The input script has one `<frame> [p1|p2] <buttons>` entry per
+line, where buttons is a `+` separated list of a, b, select, start, up, down, left, right,
+or `-` to release everything. A state holds until the next entry for the same port, and
+`#` starts a comment.
+
+`--trace` logs every instruction in the nestest.log format. It can be limited to a range
+of addresses, and to start once a condition on a register holds, as in the debugger.
+
+`--info` prints what the ROM's iNES or NES 2.0 header says and exits without running it.
+
+`--gdb` waits for a GDB remote protocol client on the given address, and lets it drive the
+console instead of running frames; the runner exits once the client detaches.
+
+Exits with 0 once all frames ran, 1 on a runtime error (including a CPU stopped by a
+KIL/JAM opcode) and 2 on bad arguments.
+*/
+use std::{
+    fs, io::BufWriter, net::TcpListener, ops::RangeInclusive, path::PathBuf, process::ExitCode,
+};
+
+use simpleness::{
+    JoypadState, Nes, Port, RomInfo, SCREEN_HEIGHT, SCREEN_WIDTH, apu,
+    audio::{AudioSink, NullSink, WavSink},
+    cpu::trace::TraceLogger,
+    debugger::{Condition, gdb, repl},
+    memory::rom_info::HEADER_SIZE,
+};
+
+const DEFAULT_FRAMES: u64 = 60;
+
+#[derive(Clone, Copy, PartialEq)]
+enum DumpFormat {
+    Png,
+    Rgba,
+}
+
+struct Options {
+    rom_path: PathBuf,
+    info: bool,
+    frames: u64,
+    input_script: Option<PathBuf>,
+    dump_frames: Vec<u64>,
+    dump_dir: PathBuf,
+    format: DumpFormat,
+    wav_path: Option<PathBuf>,
+    gdb_address: Option<String>,
+    trace_path: Option<PathBuf>,
+    trace_range: Option<RangeInclusive<u16>>,
+    trace_from: Option<Condition>,
+}
+
+struct InputEvent {
+    frame: u64,
+    port: Port,
+    state: JoypadState,
+}
+
+fn usage() -> String {
+    String::from(
+        "usage: simpleness-headless <rom.nes> [--info] [--frames N] [--input script.txt] \
+         [--dump 60,120,...] [--dump-dir DIR] [--format png|rgba] [--wav audio.wav] \
+         [--gdb 127.0.0.1:2345] [--trace trace.log] [--trace-range C000-CFFF] \
+         [--trace-from \"pc == C000\"]",
+    )
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom_path: PathBuf::new(),
+        info: false,
+        frames: DEFAULT_FRAMES,
+        input_script: None,
+        dump_frames: Vec::new(),
+        dump_dir: PathBuf::from("."),
+        format: DumpFormat::Png,
+        wav_path: None,
+        gdb_address: None,
+        trace_path: None,
+        trace_range: None,
+        trace_from: None,
+    };
+    let mut rom_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {arg}"))
+        };
+
+        match arg.as_str() {
+            "--info" => options.info = true,
+            "--frames" => {
+                options.frames = value()?
+                    .parse()
+                    .map_err(|_| String::from("--frames expects a number"))?;
+            }
+            "--input" => options.input_script = Some(PathBuf::from(value()?)),
+            "--dump" => {
+                for frame in value()?.split(',') {
+                    let frame = frame
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("bad frame number in --dump: {frame}"))?;
+                    options.dump_frames.push(frame);
+                }
+            }
+            "--dump-dir" => options.dump_dir = PathBuf::from(value()?),
+            "--format" => {
+                options.format = match value()?.as_str() {
+                    "png" => DumpFormat::Png,
+                    "rgba" => DumpFormat::Rgba,
+                    other => return Err(format!("unknown format: {other}")),
+                }
+            }
+            "--wav" => options.wav_path = Some(PathBuf::from(value()?)),
+            "--gdb" => options.gdb_address = Some(value()?.clone()),
+            "--trace" => options.trace_path = Some(PathBuf::from(value()?)),
+            "--trace-range" => options.trace_range = Some(repl::parse_range(value()?)?),
+            "--trace-from" => options.trace_from = Some(repl::parse_condition(value()?)?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {arg}")),
+            _ => rom_path = Some(PathBuf::from(arg)),
+        }
+    }
+
+    options.rom_path = rom_path.ok_or_else(usage)?;
+    Ok(options)
+}
+
+fn set_button(state: &mut JoypadState, name: &str) -> Result<(), String> {
+    match name.to_ascii_lowercase().as_str() {
+        "a" => state.set_a(1),
+        "b" => state.set_b(1),
+        "select" => state.set_select(1),
+        "start" => state.set_start(1),
+        "up" => state.set_up(1),
+        "down" => state.set_down(1),
+        "left" => state.set_left(1),
+        "right" => state.set_right(1),
+        _ => return Err(format!("unknown button: {name}")),
+    }
+    Ok(())
+}
+
+fn parse_input_script(script: &str) -> Result<Vec<InputEvent>, String> {
+    let mut events = Vec::new();
+
+    for (line_number, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("input script line {}: {message}", line_number + 1);
+
+        let mut tokens = line.split_whitespace();
+        let frame = tokens
+            .next()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| error("expected a frame number"))?;
+
+        let mut port = Port::One;
+        let mut buttons = tokens.next().ok_or_else(|| error("expected buttons"))?;
+        if let Some(port_token) = buttons.strip_prefix(['p', 'P']) {
+            port = match port_token {
+                "1" => Port::One,
+                "2" => Port::Two,
+                _ => return Err(error("port must be p1 or p2")),
+            };
+            buttons = tokens.next().ok_or_else(|| error("expected buttons"))?;
+        }
+
+        let mut state = JoypadState::new();
+        if buttons != "-" {
+            for button in buttons.split('+') {
+                set_button(&mut state, button).map_err(|message| error(&message))?;
+            }
+        }
+
+        events.push(InputEvent { frame, port, state });
+    }
+
+    events.sort_by_key(|event| event.frame);
+    Ok(events)
+}
+
+fn dump_frame(options: &Options, frame: u64, pixels: &[u8]) -> Result<(), String> {
+    let extension = match options.format {
+        DumpFormat::Png => "png",
+        DumpFormat::Rgba => "rgba",
+    };
+    let path = options
+        .dump_dir
+        .join(format!("frame_{frame:06}.{extension}"));
+    let error = |err: &dyn std::fmt::Display| format!("failed to write {}: {err}", path.display());
+
+    match options.format {
+        DumpFormat::Rgba => fs::write(&path, pixels).map_err(|err| error(&err)),
+        DumpFormat::Png => {
+            let file = fs::File::create(&path).map_err(|err| error(&err))?;
+            let mut encoder = png::Encoder::new(BufWriter::new(file), SCREEN_WIDTH, SCREEN_HEIGHT);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+
+            let mut writer = encoder.write_header().map_err(|err| error(&err))?;
+            writer.write_image_data(pixels).map_err(|err| error(&err))
+        }
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let rom_content = fs::read(&options.rom_path)
+        .map_err(|err| format!("failed to read {}: {err}", options.rom_path.display()))?;
+
+    if options.info {
+        let info = rom_content
+            .first_chunk::<HEADER_SIZE>()
+            .and_then(RomInfo::parse)
+            .ok_or_else(|| format!("{} is not an iNES file", options.rom_path.display()))?;
+        println!("{info}");
+        return Ok(());
+    }
+
+    let input_events = match &options.input_script {
+        Some(path) => {
+            let script = fs::read_to_string(path)
+                .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+            parse_input_script(&script)?
+        }
+        None => Vec::new(),
+    };
+
+    if !options.dump_frames.is_empty() {
+        fs::create_dir_all(&options.dump_dir)
+            .map_err(|err| format!("failed to create {}: {err}", options.dump_dir.display()))?;
+    }
+
+    let mut audio: Box<dyn AudioSink> = match &options.wav_path {
+        Some(path) => Box::new(
+            WavSink::create(path, apu::DEFAULT_SAMPLE_RATE)
+                .map_err(|err| format!("failed to create {}: {err}", path.display()))?,
+        ),
+        None => Box::new(NullSink::new(apu::DEFAULT_SAMPLE_RATE)),
+    };
+
+    let mut nes = Nes::new();
+    nes.set_sample_rate(audio.sample_rate());
+    nes.load_rom(rom_content)
+        .map_err(|err| format!("failed to load {}: {err}", options.rom_path.display()))?;
+
+    if let Some(path) = &options.trace_path {
+        let mut tracer = TraceLogger::create(path)
+            .map_err(|err| format!("failed to create {}: {err}", path.display()))?;
+        tracer.set_pc_range(options.trace_range.clone());
+        tracer.set_trigger(options.trace_from);
+        nes.cpu_mut().start_trace(tracer);
+    }
+
+    let result = match &options.gdb_address {
+        Some(address) => serve_gdb(&mut nes, address),
+        None => run_frames(options, &mut nes, &input_events, audio.as_mut()),
+    };
+
+    // the trace is flushed even when the run failed, it's most useful then
+    if let Some(tracer) = nes.cpu_mut().stop_trace() {
+        tracer
+            .finish()
+            .map_err(|err| format!("failed to write the trace: {err}"))?;
+    }
+    result
+}
+
+fn run_frames(
+    options: &Options,
+    nes: &mut Nes,
+    input_events: &[InputEvent],
+    audio: &mut dyn AudioSink,
+) -> Result<(), String> {
+    let mut next_event = input_events.iter().peekable();
+    for frame in 1..=options.frames {
+        while let Some(event) = next_event.next_if(|event| event.frame <= frame) {
+            nes.set_buttons(event.port, event.state);
+        }
+
+        nes.run_frame();
+        audio.write(&nes.audio_samples());
+
+        if nes.cpu().is_jammed() {
+            return Err(format!(
+                "CPU jammed at ${:04X} on frame {frame}",
+                nes.cpu().pc
+            ));
+        }
+
+        if options.dump_frames.contains(&frame) {
+            dump_frame(options, frame, nes.framebuffer())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn serve_gdb(nes: &mut Nes, address: &str) -> Result<(), String> {
+    let listener = TcpListener::bind(address)
+        .map_err(|err| format!("failed to listen on {address}: {err}"))?;
+    println!("Waiting for a gdb client on {address}");
+
+    let (stream, client) = listener
+        .accept()
+        .map_err(|err| format!("failed to accept a gdb client: {err}"))?;
+    println!("gdb client connected from {client}");
+
+    gdb::serve(nes, stream).map_err(|err| format!("gdb session failed: {err}"))
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
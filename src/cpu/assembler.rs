@@ -0,0 +1,789 @@
+use std::collections::HashMap;
+use std::{error::Error, fmt};
+
+use lazy_static::lazy_static;
+
+use crate::cpu::instructions::{AddressingMode, Instruction, OPCODE_MAP};
+
+const NROM_ORIGIN: u16 = 0xc000;
+const NROM_PRG_SIZE: usize = 0x4000;
+const NROM_VECTORS: usize = 0x3ffa; // offset of $FFFA in the bank
+
+/*
+A small two pass assembler for test programs and patches, using ca65 syntax:
+
+    .org $C000
+    reset:  LDX #$00
+    loop:   STA buffer,X        ; comments run to the end of the line
+            INX
+            BNE loop
+            JMP (vector)
+    buffer = $0200
+    vector: .word reset, <buffer + 2
+            .byte "hi", $0D, %1010, 'x'
+
+Mnemonics and directives are case insensitive, labels aren't. Expressions are numbers
+($hex, %binary, decimal, 'c'), labels, `*` for the current address, the operators
++ - * / & | ^ << >> and the unary - ~ < (low byte) > (high byte), with parentheses.
+
+Operands pick the zero page form when the value is known to fit by the time the line is
+reached; forward references get the absolute form, as does an `a:` prefix. BRK is two
+bytes, its padding byte defaulting to 0. Unofficial opcodes assemble under the names the
+disassembler shows.
+*/
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblyError {
+    pub line: usize, // 1 based, 0 for errors about the program as a whole
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl Error for AssemblyError {}
+
+lazy_static! {
+    static ref MNEMONICS: HashMap<String, Instruction> = OPCODE_MAP
+        .values()
+        .map(|opcode| (opcode.instr.to_string(), opcode.instr))
+        .collect();
+
+    // several unofficial opcodes duplicate another one, the documented or lowest one wins
+    static ref ENCODINGS: HashMap<(Instruction, AddressingMode), u8> = {
+        let mut m = HashMap::new();
+        for code in (0..=u8::MAX).rev() {
+            let opcode = OPCODE_MAP[&code];
+            let key = (opcode.instr, opcode.mode);
+            let documented = |code: &u8| !OPCODE_MAP[code].is_unofficial();
+            if !m.get(&key).is_some_and(|existing| documented(existing) && !documented(&code)) {
+                m.insert(key, code);
+            }
+        }
+        m
+    };
+}
+
+/// Assembles `source` into the bytes from `origin` up to the last one written. Gaps left
+/// by `.org` are filled with zeros.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AssemblyError> {
+    Ok(run(source, origin)?.output)
+}
+
+/*
+Builds an iNES image for tests out of a program: NROM with 16K of PRG ROM, mirrored at
+$8000 and $C000, and 8K of CHR RAM. The program is assembled at $C000 and the vectors
+point at its `reset`, `nmi` and `irq` labels, the last two falling back to `reset`.
+*/
+pub fn assemble_nrom(source: &str) -> Result<Vec<u8>, AssemblyError> {
+    let assembler = run(source, NROM_ORIGIN)?;
+    let whole_program = |message: &str| AssemblyError {
+        line: 0,
+        message: message.to_string(),
+    };
+
+    let mut prg = assembler.output;
+    if prg.len() > NROM_VECTORS {
+        return Err(whole_program("the program runs into the vectors at $FFFA"));
+    }
+    prg.resize(NROM_PRG_SIZE, 0);
+
+    let label = |name: &str| assembler.symbols.get(name).map(|&value| value as u16);
+    let reset = label("reset").ok_or_else(|| whole_program("no reset label"))?;
+    let vectors = [
+        label("nmi").unwrap_or(reset),
+        reset,
+        label("irq").unwrap_or(reset),
+    ];
+    prg[NROM_VECTORS..].copy_from_slice(&vectors.map(u16::to_le_bytes).concat());
+
+    // one 16K PRG ROM bank, no CHR ROM so the board has CHR RAM
+    let mut rom = vec![b'N', b'E', b'S', 0x1a, 1, 0];
+    rom.resize(16, 0);
+    rom.extend(prg);
+    Ok(rom)
+}
+
+fn run(source: &str, origin: u16) -> Result<Assembler, AssemblyError> {
+    let lines = source
+        .lines()
+        .enumerate()
+        .map(|(index, text)| {
+            parse_line(text).map_err(|message| AssemblyError {
+                line: index + 1,
+                message,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut assembler = Assembler {
+        symbols: HashMap::new(),
+        modes: Vec::with_capacity(lines.len()),
+        origin: origin as u32,
+        pc: origin as u32,
+        output: Vec::new(),
+    };
+
+    for (index, line) in lines.iter().enumerate() {
+        assembler.lay_out(line).map_err(|message| AssemblyError {
+            line: index + 1,
+            message,
+        })?;
+    }
+
+    assembler.pc = assembler.origin;
+    for (index, line) in lines.iter().enumerate() {
+        assembler
+            .emit(line, index)
+            .map_err(|message| AssemblyError {
+                line: index + 1,
+                message,
+            })?;
+    }
+    Ok(assembler)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(i64),
+    Symbol(String),
+    Here,
+    Unary(char, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Index {
+    X,
+    Y,
+}
+
+#[derive(Debug)]
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(Expr),
+    Direct {
+        value: Expr,
+        index: Option<Index>,
+        absolute: bool, // `a:` prefix
+    },
+    Parenthesized(Expr), // JMP's indirect form, a plain expression for everything else
+    XIndirect(Expr),
+    IndirectY(Expr),
+}
+
+#[derive(Debug)]
+enum ByteItem {
+    Value(Expr),
+    Text(Vec<u8>),
+}
+
+#[derive(Debug)]
+enum Statement {
+    Org(Expr),
+    Byte(Vec<ByteItem>),
+    Word(Vec<Expr>),
+    Constant(String, Expr),
+    Instruction(Instruction, Operand),
+}
+
+#[derive(Debug, Default)]
+struct Line {
+    labels: Vec<String>,
+    statement: Option<Statement>,
+}
+
+struct Assembler {
+    symbols: HashMap<String, i64>,
+    modes: Vec<Option<AddressingMode>>, // picked in the first pass, by line
+    origin: u32,
+    pc: u32, // can reach 0x10000 right after the last byte
+    output: Vec<u8>,
+}
+
+impl Assembler {
+    fn evaluate(&self, expr: &Expr) -> Result<Option<i64>, String> {
+        let value = match expr {
+            Expr::Number(value) => *value,
+            Expr::Symbol(name) => match self.symbols.get(name) {
+                Some(value) => *value,
+                None => return Ok(None),
+            },
+            Expr::Here => self.pc as i64,
+            Expr::Unary(op, operand) => {
+                let Some(operand) = self.evaluate(operand)? else {
+                    return Ok(None);
+                };
+                match op {
+                    '-' => -operand,
+                    '~' => !operand,
+                    '<' => operand & 0xff,
+                    _ => (operand >> 8) & 0xff,
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let (Some(left), Some(right)) = (self.evaluate(left)?, self.evaluate(right)?)
+                else {
+                    return Ok(None);
+                };
+                let shift = |value: i64, amount: i64, op: fn(i64, u32) -> Option<i64>| {
+                    u32::try_from(amount)
+                        .ok()
+                        .and_then(|amount| op(value, amount))
+                        .unwrap_or(0)
+                };
+                match *op {
+                    "+" => left.wrapping_add(right),
+                    "-" => left.wrapping_sub(right),
+                    "*" => left.wrapping_mul(right),
+                    "/" => left
+                        .checked_div(right)
+                        .ok_or_else(|| String::from("Division by zero"))?,
+                    "&" => left & right,
+                    "|" => left | right,
+                    "^" => left ^ right,
+                    "<<" => shift(left, right, i64::checked_shl),
+                    _ => shift(left, right, i64::checked_shr),
+                }
+            }
+        };
+        Ok(Some(value))
+    }
+
+    fn resolve(&self, expr: &Expr) -> Result<i64, String> {
+        self.evaluate(expr)?.ok_or_else(|| {
+            let mut names = Vec::new();
+            unknown_symbols(expr, &self.symbols, &mut names);
+            format!("Unknown label: {}", names.join(", "))
+        })
+    }
+
+    fn define(&mut self, name: &str, value: i64) -> Result<(), String> {
+        match self.symbols.insert(name.to_string(), value) {
+            Some(_) => Err(format!("{name} is defined twice")),
+            None => Ok(()),
+        }
+    }
+
+    fn advance(&mut self, size: usize) -> Result<(), String> {
+        self.pc += size as u32;
+        match self.pc <= 0x10000 {
+            true => Ok(()),
+            false => Err(String::from("The program runs past $FFFF")),
+        }
+    }
+
+    /// First pass: defines labels and works out how big every line is.
+    fn lay_out(&mut self, line: &Line) -> Result<(), String> {
+        for label in &line.labels {
+            self.define(label, self.pc as i64)?;
+        }
+
+        let mut mode = None;
+        match &line.statement {
+            None => {}
+            Some(Statement::Org(address)) => {
+                let address = self.resolve(address)?;
+                if !(self.pc as i64..=0xffff).contains(&address) {
+                    return Err(format!(
+                        ".org ${address:04X} would move back from ${:04X}",
+                        self.pc
+                    ));
+                }
+                self.pc = address as u32;
+            }
+            Some(Statement::Byte(items)) => {
+                let size = items
+                    .iter()
+                    .map(|item| match item {
+                        ByteItem::Value(_) => 1,
+                        ByteItem::Text(text) => text.len(),
+                    })
+                    .sum();
+                self.advance(size)?;
+            }
+            Some(Statement::Word(values)) => self.advance(values.len() * 2)?,
+            Some(Statement::Constant(name, value)) => {
+                let value = self.resolve(value)?;
+                self.define(name, value)?;
+            }
+            Some(Statement::Instruction(instr, operand)) => {
+                let picked = self.pick_mode(*instr, operand)?;
+                self.advance(picked.size() as usize)?;
+                mode = Some(picked);
+            }
+        }
+        self.modes.push(mode);
+        Ok(())
+    }
+
+    fn pick_mode(&self, instr: Instruction, operand: &Operand) -> Result<AddressingMode, String> {
+        let has = |mode: AddressingMode| ENCODINGS.contains_key(&(instr, mode));
+
+        let mode = match operand {
+            Operand::None if has(AddressingMode::Impl) => AddressingMode::Impl,
+            Operand::None if has(AddressingMode::Acc) => AddressingMode::Acc,
+            Operand::None if instr == Instruction::BRK => AddressingMode::Imm,
+            Operand::None => return Err(format!("{instr} needs an operand")),
+            Operand::Accumulator => AddressingMode::Acc,
+            Operand::Immediate(_) => AddressingMode::Imm,
+            Operand::XIndirect(_) => AddressingMode::XInd,
+            Operand::IndirectY(_) => AddressingMode::IndY,
+            Operand::Parenthesized(_) if has(AddressingMode::Ind) => AddressingMode::Ind,
+            Operand::Parenthesized(value) => self.pick_direct_mode(instr, value, None, false)?,
+            Operand::Direct {
+                value,
+                index,
+                absolute,
+            } => self.pick_direct_mode(instr, value, *index, *absolute)?,
+        };
+
+        match has(mode) {
+            true => Ok(mode),
+            false => Err(format!("{instr} doesn't support this addressing mode")),
+        }
+    }
+
+    fn pick_direct_mode(
+        &self,
+        instr: Instruction,
+        value: &Expr,
+        index: Option<Index>,
+        absolute: bool,
+    ) -> Result<AddressingMode, String> {
+        let has = |mode: AddressingMode| ENCODINGS.contains_key(&(instr, mode));
+        if index.is_none() && has(AddressingMode::Rel) {
+            return Ok(AddressingMode::Rel);
+        }
+
+        let (zero_page, full) = match index {
+            None => (AddressingMode::Zpg, AddressingMode::Abs),
+            Some(Index::X) => (AddressingMode::ZpgX, AddressingMode::AbsX),
+            Some(Index::Y) => (AddressingMode::ZpgY, AddressingMode::AbsY),
+        };
+        let fits_zero_page = !absolute
+            && self
+                .evaluate(value)?
+                .is_some_and(|value| (0..0x100).contains(&value));
+
+        // some indexed modes only exist in one size, e.g. STX zp,Y and LDA abs,Y
+        Ok(match (has(zero_page), has(full)) {
+            (true, true) if fits_zero_page => zero_page,
+            (true, false) if !absolute => zero_page,
+            _ => full,
+        })
+    }
+
+    /// Second pass: writes the bytes, now that every label is known.
+    fn emit(&mut self, line: &Line, index: usize) -> Result<(), String> {
+        match &line.statement {
+            None | Some(Statement::Constant(..)) => {}
+            Some(Statement::Org(address)) => {
+                self.pc = self.resolve(address)? as u32;
+                let length = (self.pc - self.origin) as usize;
+                self.output.resize(length, 0);
+            }
+            Some(Statement::Byte(items)) => {
+                for item in items {
+                    match item {
+                        ByteItem::Value(value) => {
+                            let value = self.resolve(value)?;
+                            self.push(&[byte(value)?]);
+                        }
+                        ByteItem::Text(text) => self.push(text),
+                    }
+                }
+            }
+            Some(Statement::Word(values)) => {
+                for value in values {
+                    let value = word(self.resolve(value)?)?;
+                    self.push(&value.to_le_bytes());
+                }
+            }
+            Some(Statement::Instruction(instr, operand)) => {
+                let mode = self.modes[index].unwrap();
+                let code = ENCODINGS[&(*instr, mode)];
+
+                let value = match operand {
+                    Operand::None => Some(0), // BRK's padding byte
+                    Operand::Accumulator => None,
+                    Operand::Immediate(value)
+                    | Operand::Direct { value, .. }
+                    | Operand::Parenthesized(value)
+                    | Operand::XIndirect(value)
+                    | Operand::IndirectY(value) => Some(self.resolve(value)?),
+                };
+
+                let mut bytes = vec![code];
+                match (mode.size(), value) {
+                    (1, _) | (_, None) => {}
+                    (2, Some(target)) if mode == AddressingMode::Rel => {
+                        let offset = target - (self.pc as i64 + 2);
+                        if !(-128..=127).contains(&offset) {
+                            return Err(format!("Branch to ${target:04X} is out of range"));
+                        }
+                        bytes.push(offset as u8);
+                    }
+                    (2, Some(value)) if mode == AddressingMode::Imm => bytes.push(byte(value)?),
+                    (2, Some(value)) => match u8::try_from(value) {
+                        Ok(value) => bytes.push(value),
+                        Err(_) => return Err(format!("${value:04X} isn't a zero page address")),
+                    },
+                    (_, Some(value)) => match u16::try_from(value) {
+                        Ok(value) => bytes.extend(value.to_le_bytes()),
+                        Err(_) => return Err(format!("{value} isn't an address")),
+                    },
+                }
+                self.push(&bytes);
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.output.extend_from_slice(bytes);
+        self.pc += bytes.len() as u32;
+    }
+}
+
+fn byte(value: i64) -> Result<u8, String> {
+    match (-0x80..=0xff).contains(&value) {
+        true => Ok(value as u8),
+        false => Err(format!("{value} doesn't fit in a byte")),
+    }
+}
+
+fn word(value: i64) -> Result<u16, String> {
+    match (-0x8000..=0xffff).contains(&value) {
+        true => Ok(value as u16),
+        false => Err(format!("{value} doesn't fit in a word")),
+    }
+}
+
+fn unknown_symbols(expr: &Expr, symbols: &HashMap<String, i64>, names: &mut Vec<String>) {
+    match expr {
+        Expr::Symbol(name) if !symbols.contains_key(name) => names.push(name.clone()),
+        Expr::Unary(_, operand) => unknown_symbols(operand, symbols, names),
+        Expr::Binary(_, left, right) => {
+            unknown_symbols(left, symbols, names);
+            unknown_symbols(right, symbols, names);
+        }
+        _ => {}
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Splits an identifier off the start of `text`.
+fn take_identifier(text: &str) -> Option<(&str, &str)> {
+    if !text.starts_with(is_identifier_start) {
+        return None;
+    }
+    let end = text.find(|c| !is_identifier_char(c)).unwrap_or(text.len());
+    Some(text.split_at(end))
+}
+
+/// Cuts a line at its comment, leaving `;` inside quotes alone.
+fn strip_comment(text: &str) -> &str {
+    let mut quote = None;
+    for (position, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, ';') => return &text[..position],
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Splits on commas outside parentheses and quotes.
+fn split_list(text: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let (mut depth, mut quote, mut start) = (0, None, 0);
+    for (position, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                items.push(text[start..position].trim());
+                start = position + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(text[start..].trim());
+    items
+}
+
+fn parse_line(text: &str) -> Result<Line, String> {
+    let mut line = Line::default();
+    let mut rest = strip_comment(text).trim();
+
+    while let Some((name, after)) = take_identifier(rest) {
+        if let Some(after) = after.trim_start().strip_prefix(':') {
+            line.labels.push(name.to_string());
+            rest = after.trim_start();
+        } else if let Some(value) = after.trim_start().strip_prefix('=') {
+            line.statement = Some(Statement::Constant(
+                name.to_string(),
+                parse_expression(value)?,
+            ));
+            return Ok(line);
+        } else {
+            break;
+        }
+    }
+
+    if rest.is_empty() {
+        return Ok(line);
+    }
+
+    let (keyword, operand) = rest
+        .split_once(char::is_whitespace)
+        .map(|(keyword, operand)| (keyword, operand.trim()))
+        .unwrap_or((rest, ""));
+
+    line.statement = Some(match keyword.to_ascii_lowercase().as_str() {
+        ".org" => Statement::Org(parse_expression(operand)?),
+        ".byte" => Statement::Byte(
+            split_list(operand)
+                .into_iter()
+                .map(|item| match item.strip_prefix('"') {
+                    Some(text) => text
+                        .strip_suffix('"')
+                        .map(|text| ByteItem::Text(text.as_bytes().to_vec()))
+                        .ok_or_else(|| format!("Unterminated string: {item}")),
+                    None => parse_expression(item).map(ByteItem::Value),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        ".word" => Statement::Word(
+            split_list(operand)
+                .into_iter()
+                .map(parse_expression)
+                .collect::<Result<_, _>>()?,
+        ),
+        directive if directive.starts_with('.') => {
+            return Err(format!("Unknown directive: {keyword}"));
+        }
+        mnemonic => {
+            let instr = *MNEMONICS
+                .get(&mnemonic.to_ascii_uppercase())
+                .ok_or_else(|| format!("Unknown instruction: {keyword}"))?;
+            Statement::Instruction(instr, parse_operand(operand)?)
+        }
+    });
+    Ok(line)
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    // `( $80 ) , y` reads the same as `($80),Y`
+    let mut quoted = false;
+    let compact: String = text
+        .chars()
+        .filter(|&c| {
+            if c == '\'' {
+                quoted = !quoted;
+            }
+            quoted || !c.is_whitespace()
+        })
+        .collect();
+    let upper = compact.to_ascii_uppercase();
+
+    if compact.is_empty() {
+        return Ok(Operand::None);
+    }
+    if upper == "A" {
+        return Ok(Operand::Accumulator);
+    }
+    if let Some(value) = compact.strip_prefix('#') {
+        return Ok(Operand::Immediate(parse_expression(value)?));
+    }
+
+    if let Some(inner) = compact.strip_prefix('(') {
+        if upper.ends_with(",X)") {
+            return Ok(Operand::XIndirect(parse_expression(
+                &inner[..inner.len() - 3],
+            )?));
+        }
+        if upper.ends_with("),Y") && closing_paren(&compact) == Some(compact.len() - 3) {
+            return Ok(Operand::IndirectY(parse_expression(
+                &inner[..inner.len() - 3],
+            )?));
+        }
+        if closing_paren(&compact) == Some(compact.len() - 1) {
+            return Ok(Operand::Parenthesized(parse_expression(
+                &inner[..inner.len() - 1],
+            )?));
+        }
+    }
+
+    let (value, index) = if upper.ends_with(",X") {
+        (&compact[..compact.len() - 2], Some(Index::X))
+    } else if upper.ends_with(",Y") {
+        (&compact[..compact.len() - 2], Some(Index::Y))
+    } else {
+        (compact.as_str(), None)
+    };
+    let (value, absolute) = match value.strip_prefix("a:").or(value.strip_prefix("A:")) {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+
+    Ok(Operand::Direct {
+        value: parse_expression(value)?,
+        index,
+        absolute,
+    })
+}
+
+/// The position of the parenthesis closing the one `text` starts with.
+fn closing_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (position, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(position);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_expression(text: &str) -> Result<Expr, String> {
+    let mut parser = ExpressionParser { text, position: 0 };
+    let expr = parser.binary(0)?;
+    parser.skip_whitespace();
+    match parser.position == text.len() {
+        true => Ok(expr),
+        false => Err(format!("Invalid expression: {}", text.trim())),
+    }
+}
+
+// binary operators from the loosest binding to the tightest
+const PRECEDENCE: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/"],
+];
+
+struct ExpressionParser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> ExpressionParser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.position = self.text.len() - self.rest().trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        match self.rest().starts_with(token) {
+            true => {
+                self.position += token.len();
+                true
+            }
+            false => false,
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+
+        let mut left = self.binary(level + 1)?;
+        'operators: loop {
+            for &operator in *operators {
+                if self.eat(operator) {
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(operator, Box::new(left), Box::new(right));
+                    continue 'operators;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        for operator in ['-', '~', '<', '>'] {
+            if self.eat(&operator.to_string()) {
+                return Ok(Expr::Unary(operator, Box::new(self.unary()?)));
+            }
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        if self.eat("(") {
+            let expr = self.binary(0)?;
+            return match self.eat(")") {
+                true => Ok(expr),
+                false => Err(format!("Missing ) in {}", self.text.trim())),
+            };
+        }
+        if self.eat("*") {
+            return Ok(Expr::Here);
+        }
+
+        let rest = self.rest();
+        if let Some((name, _)) = take_identifier(rest) {
+            self.position += name.len();
+            return Ok(Expr::Symbol(name.to_string()));
+        }
+
+        let mut chars = rest.chars();
+        if chars.next() == Some('\'')
+            && let Some(c) = chars.next()
+            && chars.next() == Some('\'')
+        {
+            self.position += 2 + c.len_utf8();
+            return Ok(Expr::Number(c as i64));
+        }
+
+        let (radix, digits) = match rest.strip_prefix('$') {
+            Some(digits) => (16, digits),
+            None => match rest.strip_prefix('%') {
+                Some(digits) => (2, digits),
+                None => (10, rest),
+            },
+        };
+        let length = digits
+            .find(|c: char| !c.is_digit(radix))
+            .unwrap_or(digits.len());
+        let value = i64::from_str_radix(&digits[..length], radix)
+            .map_err(|_| format!("Invalid expression: {}", self.text.trim()))?;
+        self.position += rest.len() - digits.len() + length;
+        Ok(Expr::Number(value))
+    }
+}
@@ -0,0 +1,98 @@
+/*
+What the CPU needs from the bus it sits on. The console's `Bus` is the real thing; the
+hooks after the memory accesses only matter there and default to a bus where nothing else
+runs, no interrupts are raised and nobody watches.
+*/
+pub trait CpuBus {
+    fn read_u8(&mut self, addr: u16) -> u8;
+    fn write_u8(&mut self, addr: u16, data: u8);
+
+    /// Reads memory without side effects, for traces and debugging tools.
+    fn peek_u8(&self, addr: u16) -> u8;
+
+    /// Steps everything else on the bus by one CPU cycle, right before the CPU's access.
+    /// Returns how many more cycles the CPU stays halted for, like DMC fetches do.
+    fn tick(&mut self) -> u64 {
+        0
+    }
+
+    /// Takes a pending NMI edge.
+    fn take_nmi(&mut self) -> bool {
+        false
+    }
+
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    /// The page of an OAM DMA to run after the write that asked for it.
+    fn take_oam_dma_request(&mut self) -> Option<u8> {
+        None
+    }
+
+    /// Called after every access the CPU makes.
+    fn record_access(&mut self, _kind: AccessKind, _addr: u16, _data: u8) {}
+
+    /// The PPU's scanline and dot, for traces.
+    fn ppu_position(&self) -> (u16, u16) {
+        (0, 0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// One CPU cycle's bus access, as `FlatBus` logs them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusAccess {
+    pub kind: AccessKind,
+    pub address: u16,
+    pub value: u8,
+}
+
+/// 64K of RAM and nothing else, for running the CPU on its own in tests. Every access is
+/// logged in order, so there is one entry per cycle.
+pub struct FlatBus {
+    pub ram: Vec<u8>,
+    pub accesses: Vec<BusAccess>,
+}
+
+impl Default for FlatBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlatBus {
+    pub fn new() -> Self {
+        Self {
+            ram: vec![0; 0x10000],
+            accesses: Vec::new(),
+        }
+    }
+}
+
+impl CpuBus for FlatBus {
+    fn read_u8(&mut self, addr: u16) -> u8 {
+        self.ram[addr as usize]
+    }
+
+    fn write_u8(&mut self, addr: u16, data: u8) {
+        self.ram[addr as usize] = data;
+    }
+
+    fn peek_u8(&self, addr: u16) -> u8 {
+        self.ram[addr as usize]
+    }
+
+    fn record_access(&mut self, kind: AccessKind, addr: u16, data: u8) {
+        self.accesses.push(BusAccess {
+            kind,
+            address: addr,
+            value: data,
+        });
+    }
+}
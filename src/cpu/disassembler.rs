@@ -0,0 +1,86 @@
+use std::fmt::Display;
+
+use crate::cpu::instructions::{AddressingMode, OPCODE_MAP, Opcode};
+
+/// One instruction decoded from memory, without a CPU to run it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledInstruction {
+    pub address: u16,
+    pub opcode: Opcode,
+    pub bytes: Vec<u8>, // opcode and operand, cut short when the input ran out
+}
+
+impl DisassembledInstruction {
+    /// The raw operand bytes as a little endian number, 0 for instructions without one.
+    pub fn operand(&self) -> u16 {
+        match self.bytes[1..] {
+            [lo, hi, ..] => u16::from_le_bytes([lo, hi]),
+            [lo] => lo as u16,
+            [] => 0,
+        }
+    }
+
+    /// The address the operand refers to: where a branch or jump goes, what a load or
+    /// store accesses before indexing, or the pointer of indirect modes.
+    pub fn target(&self) -> Option<u16> {
+        match self.opcode.mode {
+            AddressingMode::Acc | AddressingMode::Impl | AddressingMode::Imm => None,
+            AddressingMode::Rel => Some(
+                self.address
+                    .wrapping_add(2)
+                    .wrapping_add_signed(self.operand() as i8 as i16),
+            ),
+            _ => Some(self.operand()),
+        }
+    }
+
+    /// The instruction is missing operand bytes past the end of the input.
+    pub fn is_truncated(&self) -> bool {
+        self.bytes.len() < self.opcode.mode.size() as usize
+    }
+
+    pub fn len(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operand = self.opcode.mode.format(self.operand(), self.address);
+        if operand.is_empty() {
+            write!(f, "{}", self.opcode.instr)
+        } else {
+            write!(f, "{} {operand}", self.opcode.instr)
+        }
+    }
+}
+
+/// Decodes the instruction at the start of `bytes`, which sits at `address`.
+pub fn disassemble_one(bytes: &[u8], address: u16) -> Option<DisassembledInstruction> {
+    let opcode = OPCODE_MAP[bytes.first()?];
+    let length = (opcode.mode.size() as usize).min(bytes.len());
+
+    Some(DisassembledInstruction {
+        address,
+        opcode,
+        bytes: bytes[..length].to_vec(),
+    })
+}
+
+/// Decodes `bytes` front to back as instructions, the first one sitting at `base_addr`.
+pub fn disassemble(bytes: &[u8], base_addr: u16) -> Vec<DisassembledInstruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+
+    while let Some(instruction) =
+        disassemble_one(&bytes[offset..], base_addr.wrapping_add(offset as u16))
+    {
+        offset += instruction.bytes.len();
+        instructions.push(instruction);
+    }
+    instructions
+}
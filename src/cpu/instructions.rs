@@ -1,9 +1,10 @@
+use crate::cpu::bus::CpuBus;
 use crate::cpu::olc6502::Olc6502;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 use std::fmt::Display;
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum AddressingMode {
     Acc,
     Abs,
@@ -21,22 +22,122 @@ pub enum AddressingMode {
 }
 
 impl AddressingMode {
-    #[allow(dead_code)]
-    pub fn format_operand(&self, operand: u16, cpu: &mut Olc6502) -> String {
+    /// Length in bytes of an instruction using this mode, opcode included.
+    pub fn size(&self) -> u16 {
+        match self {
+            AddressingMode::Acc | AddressingMode::Impl => 1,
+            AddressingMode::Imm
+            | AddressingMode::XInd
+            | AddressingMode::IndY
+            | AddressingMode::Rel
+            | AddressingMode::Zpg
+            | AddressingMode::ZpgX
+            | AddressingMode::ZpgY => 2,
+            AddressingMode::Abs | AddressingMode::AbsX | AddressingMode::AbsY | AddressingMode::Ind => 3,
+        }
+    }
+
+    /// Writes an operand the way assembly source does, with `value` standing in for the
+    /// number or address, e.g. `($80),Y` for `value` = `$80`.
+    pub fn syntax(&self, value: &str) -> String {
         match self {
             AddressingMode::Acc => String::from("A"),
-            AddressingMode::Abs => format!("${:04X}", operand),
-            AddressingMode::AbsX => format!("${:04X},X", operand),
-            AddressingMode::AbsY => format!("${:04X},Y", operand),
-            AddressingMode::Imm => format!("#${:02X}", cpu.bus.read_u8(operand)),
-            AddressingMode::Impl => String::from(""),
-            AddressingMode::Ind => format!("(${:04X})", operand),
-            AddressingMode::XInd => format!("(${:02X},X)", operand as u8),
-            AddressingMode::IndY => format!("(${:02X}),Y", operand as u8),
-            AddressingMode::Rel => format!("${:04X}", operand),
-            AddressingMode::Zpg => format!("${:02X}", operand as u8),
-            AddressingMode::ZpgX => format!("${:02X},X", operand as u8),
-            AddressingMode::ZpgY => format!("${:02X},Y", operand as u8),
+            AddressingMode::Impl => String::new(),
+            AddressingMode::Imm => format!("#{value}"),
+            AddressingMode::Abs | AddressingMode::Rel | AddressingMode::Zpg => value.to_string(),
+            AddressingMode::AbsX | AddressingMode::ZpgX => format!("{value},X"),
+            AddressingMode::AbsY | AddressingMode::ZpgY => format!("{value},Y"),
+            AddressingMode::Ind => format!("({value})"),
+            AddressingMode::XInd => format!("({value},X)"),
+            AddressingMode::IndY => format!("({value}),Y"),
+        }
+    }
+
+    /*
+    The operand of an instruction at `address` in hex, from its raw operand bytes as a
+    little endian number. Branches show the address they go to.
+    */
+    pub fn format(&self, operand: u16, address: u16) -> String {
+        let value = match self {
+            AddressingMode::Acc | AddressingMode::Impl => String::new(),
+            AddressingMode::Rel => {
+                let target = address.wrapping_add(2).wrapping_add_signed(operand as i8 as i16);
+                format!("${target:04X}")
+            }
+            AddressingMode::Abs
+            | AddressingMode::AbsX
+            | AddressingMode::AbsY
+            | AddressingMode::Ind => format!("${operand:04X}"),
+            _ => format!("${:02X}", operand as u8),
+        };
+        self.syntax(&value)
+    }
+
+    /*
+    Formats the operand of the instruction at `address` like nestest.log does: besides the
+    operand itself, the effective address and the value there are resolved against the
+    CPU's current state, e.g. `($80),Y = 0200 @ 0234 = 5F`. Memory is peeked, so tracing
+    doesn't disturb the machine.
+    */
+    pub fn format_operand<B: CpuBus>(
+        &self,
+        instr: Instruction,
+        address: u16,
+        cpu: &Olc6502<B>,
+    ) -> String {
+        let bus = &cpu.bus;
+        let byte = bus.peek_u8(address.wrapping_add(1));
+        let word = u16::from_le_bytes([byte, bus.peek_u8(address.wrapping_add(2))]);
+        let zero_page_word = |pointer: u8| {
+            u16::from_le_bytes([
+                bus.peek_u8(pointer as u16),
+                bus.peek_u8(pointer.wrapping_add(1) as u16),
+            ])
+        };
+
+        let annotation = match self {
+            AddressingMode::Abs if matches!(instr, Instruction::JMP | Instruction::JSR) => None,
+            AddressingMode::Abs => Some(format!("= {:02X}", bus.peek_u8(word))),
+            AddressingMode::AbsX | AddressingMode::AbsY => {
+                let index = if *self == AddressingMode::AbsX { cpu.x } else { cpu.y };
+                let effective = word.wrapping_add(index as u16);
+                Some(format!("@ {effective:04X} = {:02X}", bus.peek_u8(effective)))
+            }
+            AddressingMode::Ind => {
+                // the pointer never carries into the high byte
+                let hi_pointer = (word & 0xff00) | (word.wrapping_add(1) & 0x00ff);
+                let target = u16::from_le_bytes([bus.peek_u8(word), bus.peek_u8(hi_pointer)]);
+                Some(format!("= {target:04X}"))
+            }
+            AddressingMode::XInd => {
+                let pointer = byte.wrapping_add(cpu.x);
+                let effective = zero_page_word(pointer);
+                Some(format!(
+                    "@ {pointer:02X} = {effective:04X} = {:02X}",
+                    bus.peek_u8(effective)
+                ))
+            }
+            AddressingMode::IndY => {
+                let base = zero_page_word(byte);
+                let effective = base.wrapping_add(cpu.y as u16);
+                Some(format!(
+                    "= {base:04X} @ {effective:04X} = {:02X}",
+                    bus.peek_u8(effective)
+                ))
+            }
+            AddressingMode::Zpg => Some(format!("= {:02X}", bus.peek_u8(byte as u16))),
+            AddressingMode::ZpgX | AddressingMode::ZpgY => {
+                let index = if *self == AddressingMode::ZpgX { cpu.x } else { cpu.y };
+                let effective = byte.wrapping_add(index);
+                Some(format!("@ {effective:02X} = {:02X}", bus.peek_u8(effective as u16)))
+            }
+            _ => None,
+        };
+
+        let operand = self.format(word, address);
+        match annotation {
+            Some(annotation) => format!("{operand} {annotation}"),
+            None => operand,
         }
     }
 }
@@ -100,6 +201,28 @@ pub enum Instruction {
     TXA,
     TXS,
     TYA,
+
+    // unofficial
+    ALR,
+    ANC,
+    ANE,
+    ARR,
+    DCP,
+    ISC,
+    JAM,
+    LAS,
+    LAX,
+    LXA,
+    RLA,
+    RRA,
+    SAX,
+    SBX,
+    SHA,
+    SHX,
+    SHY,
+    SLO,
+    SRE,
+    TAS,
 }
 
 impl Display for Instruction {
@@ -108,15 +231,47 @@ impl Display for Instruction {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Opcode {
     pub code: u8,
     pub instr: Instruction,
     pub mode: AddressingMode,
+    // documented timings; the CPU gets its timing from the bus accesses it makes instead
     pub cycles: u8,
     pub cross_cycle: bool,
 }
 
+impl Opcode {
+    /// Opcodes outside the documented instruction set, including the extra NOPs and SBC.
+    pub fn is_unofficial(&self) -> bool {
+        match self.instr {
+            Instruction::NOP => self.code != 0xEA,
+            Instruction::SBC => self.code == 0xEB,
+            Instruction::ALR
+            | Instruction::ANC
+            | Instruction::ANE
+            | Instruction::ARR
+            | Instruction::DCP
+            | Instruction::ISC
+            | Instruction::JAM
+            | Instruction::LAS
+            | Instruction::LAX
+            | Instruction::LXA
+            | Instruction::RLA
+            | Instruction::RRA
+            | Instruction::SAX
+            | Instruction::SBX
+            | Instruction::SHA
+            | Instruction::SHX
+            | Instruction::SHY
+            | Instruction::SLO
+            | Instruction::SRE
+            | Instruction::TAS => true,
+            _ => false,
+        }
+    }
+}
+
 lazy_static! {
     pub static ref OPCODE_MAP: HashMap<u8, Opcode> = {
         let mut m = HashMap::new();
@@ -185,6 +340,27 @@ lazy_static! {
         m.insert(Instruction::TXS, "TXS");
         m.insert(Instruction::TYA, "TYA");
 
+        m.insert(Instruction::ALR, "ALR");
+        m.insert(Instruction::ANC, "ANC");
+        m.insert(Instruction::ANE, "ANE");
+        m.insert(Instruction::ARR, "ARR");
+        m.insert(Instruction::DCP, "DCP");
+        m.insert(Instruction::ISC, "ISC");
+        m.insert(Instruction::JAM, "JAM");
+        m.insert(Instruction::LAS, "LAS");
+        m.insert(Instruction::LAX, "LAX");
+        m.insert(Instruction::LXA, "LXA");
+        m.insert(Instruction::RLA, "RLA");
+        m.insert(Instruction::RRA, "RRA");
+        m.insert(Instruction::SAX, "SAX");
+        m.insert(Instruction::SBX, "SBX");
+        m.insert(Instruction::SHA, "SHA");
+        m.insert(Instruction::SHX, "SHX");
+        m.insert(Instruction::SHY, "SHY");
+        m.insert(Instruction::SLO, "SLO");
+        m.insert(Instruction::SRE, "SRE");
+        m.insert(Instruction::TAS, "TAS");
+
         m
     };
 }
@@ -1303,5 +1479,762 @@ const OPCODES: &[Opcode] = &[
         mode: AddressingMode::Impl,
         cycles: 2,
         cross_cycle: false,
+    },    // Unofficial opcodes, see https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+    // SLO
+    Opcode {
+        code: 0x07,
+        instr: Instruction::SLO,
+        mode: AddressingMode::Zpg,
+        cycles: 5,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x17,
+        instr: Instruction::SLO,
+        mode: AddressingMode::ZpgX,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x0F,
+        instr: Instruction::SLO,
+        mode: AddressingMode::Abs,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x1F,
+        instr: Instruction::SLO,
+        mode: AddressingMode::AbsX,
+        cycles: 7,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x1B,
+        instr: Instruction::SLO,
+        mode: AddressingMode::AbsY,
+        cycles: 7,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x03,
+        instr: Instruction::SLO,
+        mode: AddressingMode::XInd,
+        cycles: 8,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x13,
+        instr: Instruction::SLO,
+        mode: AddressingMode::IndY,
+        cycles: 8,
+        cross_cycle: false,
+    },
+    // RLA
+    Opcode {
+        code: 0x27,
+        instr: Instruction::RLA,
+        mode: AddressingMode::Zpg,
+        cycles: 5,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x37,
+        instr: Instruction::RLA,
+        mode: AddressingMode::ZpgX,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x2F,
+        instr: Instruction::RLA,
+        mode: AddressingMode::Abs,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x3F,
+        instr: Instruction::RLA,
+        mode: AddressingMode::AbsX,
+        cycles: 7,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x3B,
+        instr: Instruction::RLA,
+        mode: AddressingMode::AbsY,
+        cycles: 7,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x23,
+        instr: Instruction::RLA,
+        mode: AddressingMode::XInd,
+        cycles: 8,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x33,
+        instr: Instruction::RLA,
+        mode: AddressingMode::IndY,
+        cycles: 8,
+        cross_cycle: false,
+    },
+    // SRE
+    Opcode {
+        code: 0x47,
+        instr: Instruction::SRE,
+        mode: AddressingMode::Zpg,
+        cycles: 5,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x57,
+        instr: Instruction::SRE,
+        mode: AddressingMode::ZpgX,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x4F,
+        instr: Instruction::SRE,
+        mode: AddressingMode::Abs,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x5F,
+        instr: Instruction::SRE,
+        mode: AddressingMode::AbsX,
+        cycles: 7,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x5B,
+        instr: Instruction::SRE,
+        mode: AddressingMode::AbsY,
+        cycles: 7,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x43,
+        instr: Instruction::SRE,
+        mode: AddressingMode::XInd,
+        cycles: 8,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x53,
+        instr: Instruction::SRE,
+        mode: AddressingMode::IndY,
+        cycles: 8,
+        cross_cycle: false,
+    },
+    // RRA
+    Opcode {
+        code: 0x67,
+        instr: Instruction::RRA,
+        mode: AddressingMode::Zpg,
+        cycles: 5,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x77,
+        instr: Instruction::RRA,
+        mode: AddressingMode::ZpgX,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x6F,
+        instr: Instruction::RRA,
+        mode: AddressingMode::Abs,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x7F,
+        instr: Instruction::RRA,
+        mode: AddressingMode::AbsX,
+        cycles: 7,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x7B,
+        instr: Instruction::RRA,
+        mode: AddressingMode::AbsY,
+        cycles: 7,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x63,
+        instr: Instruction::RRA,
+        mode: AddressingMode::XInd,
+        cycles: 8,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x73,
+        instr: Instruction::RRA,
+        mode: AddressingMode::IndY,
+        cycles: 8,
+        cross_cycle: false,
+    },
+    // SAX
+    Opcode {
+        code: 0x87,
+        instr: Instruction::SAX,
+        mode: AddressingMode::Zpg,
+        cycles: 3,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x97,
+        instr: Instruction::SAX,
+        mode: AddressingMode::ZpgY,
+        cycles: 4,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x8F,
+        instr: Instruction::SAX,
+        mode: AddressingMode::Abs,
+        cycles: 4,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x83,
+        instr: Instruction::SAX,
+        mode: AddressingMode::XInd,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    // LAX
+    Opcode {
+        code: 0xA7,
+        instr: Instruction::LAX,
+        mode: AddressingMode::Zpg,
+        cycles: 3,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xB7,
+        instr: Instruction::LAX,
+        mode: AddressingMode::ZpgY,
+        cycles: 4,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xAF,
+        instr: Instruction::LAX,
+        mode: AddressingMode::Abs,
+        cycles: 4,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xBF,
+        instr: Instruction::LAX,
+        mode: AddressingMode::AbsY,
+        cycles: 4,
+        cross_cycle: true,
+    },
+    Opcode {
+        code: 0xA3,
+        instr: Instruction::LAX,
+        mode: AddressingMode::XInd,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xB3,
+        instr: Instruction::LAX,
+        mode: AddressingMode::IndY,
+        cycles: 5,
+        cross_cycle: true,
+    },
+    // DCP
+    Opcode {
+        code: 0xC7,
+        instr: Instruction::DCP,
+        mode: AddressingMode::Zpg,
+        cycles: 5,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xD7,
+        instr: Instruction::DCP,
+        mode: AddressingMode::ZpgX,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xCF,
+        instr: Instruction::DCP,
+        mode: AddressingMode::Abs,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xDF,
+        instr: Instruction::DCP,
+        mode: AddressingMode::AbsX,
+        cycles: 7,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xDB,
+        instr: Instruction::DCP,
+        mode: AddressingMode::AbsY,
+        cycles: 7,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xC3,
+        instr: Instruction::DCP,
+        mode: AddressingMode::XInd,
+        cycles: 8,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xD3,
+        instr: Instruction::DCP,
+        mode: AddressingMode::IndY,
+        cycles: 8,
+        cross_cycle: false,
+    },
+    // ISC
+    Opcode {
+        code: 0xE7,
+        instr: Instruction::ISC,
+        mode: AddressingMode::Zpg,
+        cycles: 5,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xF7,
+        instr: Instruction::ISC,
+        mode: AddressingMode::ZpgX,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xEF,
+        instr: Instruction::ISC,
+        mode: AddressingMode::Abs,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xFF,
+        instr: Instruction::ISC,
+        mode: AddressingMode::AbsX,
+        cycles: 7,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xFB,
+        instr: Instruction::ISC,
+        mode: AddressingMode::AbsY,
+        cycles: 7,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xE3,
+        instr: Instruction::ISC,
+        mode: AddressingMode::XInd,
+        cycles: 8,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xF3,
+        instr: Instruction::ISC,
+        mode: AddressingMode::IndY,
+        cycles: 8,
+        cross_cycle: false,
+    },
+    // ANC
+    Opcode {
+        code: 0x0B,
+        instr: Instruction::ANC,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x2B,
+        instr: Instruction::ANC,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    // ALR
+    Opcode {
+        code: 0x4B,
+        instr: Instruction::ALR,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    // ARR
+    Opcode {
+        code: 0x6B,
+        instr: Instruction::ARR,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    // SBX
+    Opcode {
+        code: 0xCB,
+        instr: Instruction::SBX,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    // SBC (USBC)
+    Opcode {
+        code: 0xEB,
+        instr: Instruction::SBC,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    // NOP (DOP, TOP)
+    Opcode {
+        code: 0x1A,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x3A,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x5A,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x7A,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xDA,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xFA,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x80,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x82,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x89,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xC2,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xE2,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x04,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Zpg,
+        cycles: 3,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x44,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Zpg,
+        cycles: 3,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x64,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Zpg,
+        cycles: 3,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x14,
+        instr: Instruction::NOP,
+        mode: AddressingMode::ZpgX,
+        cycles: 4,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x34,
+        instr: Instruction::NOP,
+        mode: AddressingMode::ZpgX,
+        cycles: 4,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x54,
+        instr: Instruction::NOP,
+        mode: AddressingMode::ZpgX,
+        cycles: 4,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x74,
+        instr: Instruction::NOP,
+        mode: AddressingMode::ZpgX,
+        cycles: 4,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xD4,
+        instr: Instruction::NOP,
+        mode: AddressingMode::ZpgX,
+        cycles: 4,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xF4,
+        instr: Instruction::NOP,
+        mode: AddressingMode::ZpgX,
+        cycles: 4,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x0C,
+        instr: Instruction::NOP,
+        mode: AddressingMode::Abs,
+        cycles: 4,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x1C,
+        instr: Instruction::NOP,
+        mode: AddressingMode::AbsX,
+        cycles: 4,
+        cross_cycle: true,
+    },
+    Opcode {
+        code: 0x3C,
+        instr: Instruction::NOP,
+        mode: AddressingMode::AbsX,
+        cycles: 4,
+        cross_cycle: true,
+    },
+    Opcode {
+        code: 0x5C,
+        instr: Instruction::NOP,
+        mode: AddressingMode::AbsX,
+        cycles: 4,
+        cross_cycle: true,
+    },
+    Opcode {
+        code: 0x7C,
+        instr: Instruction::NOP,
+        mode: AddressingMode::AbsX,
+        cycles: 4,
+        cross_cycle: true,
+    },
+    Opcode {
+        code: 0xDC,
+        instr: Instruction::NOP,
+        mode: AddressingMode::AbsX,
+        cycles: 4,
+        cross_cycle: true,
+    },
+    Opcode {
+        code: 0xFC,
+        instr: Instruction::NOP,
+        mode: AddressingMode::AbsX,
+        cycles: 4,
+        cross_cycle: true,
+    },
+    // ANE
+    Opcode {
+        code: 0x8B,
+        instr: Instruction::ANE,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    // LXA
+    Opcode {
+        code: 0xAB,
+        instr: Instruction::LXA,
+        mode: AddressingMode::Imm,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    // SHA
+    Opcode {
+        code: 0x9F,
+        instr: Instruction::SHA,
+        mode: AddressingMode::AbsY,
+        cycles: 5,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x93,
+        instr: Instruction::SHA,
+        mode: AddressingMode::IndY,
+        cycles: 6,
+        cross_cycle: false,
+    },
+    // SHX
+    Opcode {
+        code: 0x9E,
+        instr: Instruction::SHX,
+        mode: AddressingMode::AbsY,
+        cycles: 5,
+        cross_cycle: false,
+    },
+    // SHY
+    Opcode {
+        code: 0x9C,
+        instr: Instruction::SHY,
+        mode: AddressingMode::AbsX,
+        cycles: 5,
+        cross_cycle: false,
+    },
+    // TAS
+    Opcode {
+        code: 0x9B,
+        instr: Instruction::TAS,
+        mode: AddressingMode::AbsY,
+        cycles: 5,
+        cross_cycle: false,
+    },
+    // LAS
+    Opcode {
+        code: 0xBB,
+        instr: Instruction::LAS,
+        mode: AddressingMode::AbsY,
+        cycles: 4,
+        cross_cycle: true,
+    },
+    // JAM
+    Opcode {
+        code: 0x02,
+        instr: Instruction::JAM,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x12,
+        instr: Instruction::JAM,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x22,
+        instr: Instruction::JAM,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x32,
+        instr: Instruction::JAM,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x42,
+        instr: Instruction::JAM,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x52,
+        instr: Instruction::JAM,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x62,
+        instr: Instruction::JAM,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x72,
+        instr: Instruction::JAM,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0x92,
+        instr: Instruction::JAM,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xB2,
+        instr: Instruction::JAM,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xD2,
+        instr: Instruction::JAM,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
+    },
+    Opcode {
+        code: 0xF2,
+        instr: Instruction::JAM,
+        mode: AddressingMode::Impl,
+        cycles: 2,
+        cross_cycle: false,
     },
 ];
@@ -1,2 +1,6 @@
-mod instructions;
+pub mod assembler;
+pub mod bus;
+pub mod disassembler;
+pub mod instructions;
 pub mod olc6502;
+pub mod trace;
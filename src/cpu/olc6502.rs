@@ -1,11 +1,58 @@
-use crate::cpu::instructions::{AddressingMode, Instruction, OPCODE_MAP};
+use crate::cpu::bus::{AccessKind, CpuBus};
+use crate::cpu::instructions::{AddressingMode, Instruction, OPCODE_MAP, Opcode};
+use crate::cpu::trace::TraceLogger;
 use crate::memory::bus::Bus;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
 use bitflags::bitflags;
 
 const NMI_ADDRESS: u16 = 0xfffa;
 const RESET_ADDRESS: u16 = 0xfffc;
 const IRQ_ADDRESS: u16 = 0xfffe;
 
+// what the unstable ANE and LXA opcodes OR the accumulator with
+const UNSTABLE_MAGIC_CONSTANT: u8 = 0xee;
+
+/// How an instruction uses its operand address, which decides the dummy cycles it makes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
+impl Access {
+    fn of(opcode: &Opcode) -> Self {
+        match opcode.instr {
+            Instruction::STA
+            | Instruction::STX
+            | Instruction::STY
+            | Instruction::SAX
+            | Instruction::SHA
+            | Instruction::SHX
+            | Instruction::SHY
+            | Instruction::TAS => Access::Write,
+            Instruction::ASL | Instruction::LSR | Instruction::ROL | Instruction::ROR
+                if opcode.mode == AddressingMode::Acc =>
+            {
+                Access::Read
+            }
+            Instruction::ASL
+            | Instruction::LSR
+            | Instruction::ROL
+            | Instruction::ROR
+            | Instruction::INC
+            | Instruction::DEC
+            | Instruction::SLO
+            | Instruction::RLA
+            | Instruction::SRE
+            | Instruction::RRA
+            | Instruction::DCP
+            | Instruction::ISC => Access::ReadModifyWrite,
+            _ => Access::Read,
+        }
+    }
+}
+
 bitflags! {
     /// Represents a set of flags.
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -21,8 +68,20 @@ bitflags! {
     }
 }
 
-pub struct Olc6502 {
-    pub(crate) bus: Bus,
+/// The programmer visible registers, as tools outside the CPU see them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub s: u8,
+    pub pc: u16,
+}
+
+/// The 2A03's 6502 core, on the console's bus unless told otherwise.
+pub struct Olc6502<B: CpuBus = Bus> {
+    pub bus: B,
 
     // Registers
     pub(crate) a: u8,
@@ -35,63 +94,265 @@ pub struct Olc6502 {
     // instruction argument
     operand: u16,
     cycles: u64,
+
+    jammed: bool, // a KIL/JAM opcode stopped the CPU, only a reset recovers it
+
+    // interrupt inputs as sampled at the end of the last two cycles, see `end_cycle`
+    need_nmi: bool,
+    prev_need_nmi: bool,
+    run_irq: bool,
+    prev_run_irq: bool,
+
+    tracer: Option<TraceLogger>,
 }
 
-impl Olc6502 {
-    pub fn new(bus: Bus) -> Self {
+impl<B: CpuBus> Olc6502<B> {
+    pub fn new(bus: B) -> Self {
         Self {
             bus,
             a: 0,
             x: 0,
             y: 0,
             p: StatusFlags::I | StatusFlags::U,
-            s: 0xfd,
+            s: 0,
             pc: 0,
             operand: 0,
 
-            cycles: 7,
+            cycles: 0,
+            jammed: false,
+
+            need_nmi: false,
+            prev_need_nmi: false,
+            run_irq: false,
+            prev_run_irq: false,
+
+            tracer: None,
         }
     }
 
+    /// Runs one instruction, followed by an interrupt sequence if one was polled during it.
     pub fn tick(&mut self) {
-        let cpu_cycles_ran = self.execute_instruction();
-        for _ in 0..cpu_cycles_ran * 3 {
-            self.bus.ppu.tick();
-
-            if self.bus.ppu.should_nmi {
-                self.bus.ppu.should_nmi = false;
-                self.nmi();
-                for _ in 0..6 {
-                    self.bus.ppu.tick();
-                }
-            }
+        self.execute_instruction();
+
+        if !self.jammed && (self.prev_need_nmi || self.prev_run_irq) {
+            self.interrupt();
+        }
+    }
+
+    /*
+    Every CPU cycle is exactly one bus access, so the rest of the console is stepped one
+    cycle (three PPU dots) right before each access, and sees reads and writes on the dot
+    they actually happen.
+    */
+    fn start_cycle(&mut self) {
+        let mut cycles = 1;
+        while cycles > 0 {
+            cycles -= 1;
+            self.cycles += 1;
+
+            // a DMC sample fetch halts the CPU for a few cycles before its next access
+            cycles += self.bus.tick();
+        }
+    }
+
+    /*
+    The interrupt inputs are sampled at the end of every cycle, and an instruction checks
+    what was sampled at the end of its second to last cycle. NMI is edge triggered: the PPU
+    latches the edge in `should_nmi`. IRQ is a level that any device on the bus can hold,
+    masked by the I flag. CLI, SEI and PLP change the flag after their last cycle, which is
+    why their effect on IRQs is delayed by one instruction.
+    */
+    fn end_cycle(&mut self) {
+        self.prev_need_nmi = self.need_nmi;
+        if self.bus.take_nmi() {
+            self.need_nmi = true;
         }
+
+        self.prev_run_irq = self.run_irq;
+        self.run_irq = self.bus.irq_pending() && !self.p.contains(StatusFlags::I);
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        self.start_cycle();
+        let data = self.bus.read_u8(addr);
+        self.bus.record_access(AccessKind::Read, addr, data);
+        self.end_cycle();
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.start_cycle();
+        self.bus.write_u8(addr, data);
+        self.bus.record_access(AccessKind::Write, addr, data);
+        self.end_cycle();
+
+        if let Some(page) = self.bus.take_oam_dma_request() {
+            self.oam_dma(page);
+        }
+    }
+
+    /// A cycle where the CPU is halted and leaves the bus alone.
+    fn idle_cycle(&mut self) {
+        self.start_cycle();
+        self.end_cycle();
+    }
+
+    /// Reads the byte at PC and moves past it.
+    fn fetch(&mut self) -> u8 {
+        let data = self.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        data
     }
 
-    fn bus_write_u8(&mut self, addr: u16, data: u8) {
-        let extra_cycles = self.bus.write_u8(addr, data);
-        self.cycles += extra_cycles as u64;
+    fn fetch_u16(&mut self) -> u16 {
+        let lo = self.fetch();
+        let hi = self.fetch();
+        u16::from_le_bytes([lo, hi])
     }
 
+    /*
+    Writing $4014 halts the CPU while 256 bytes are copied to OAM, each taking a read and a
+    write cycle. Halting takes one cycle, and one more to line up with a read cycle when the
+    halt landed on an odd one.
+    */
+    fn oam_dma(&mut self, page: u8) {
+        self.idle_cycle();
+        if !self.cycles.is_multiple_of(2) {
+            self.idle_cycle();
+        }
+
+        for i in 0..0x100 {
+            let data = self.read(((page as u16) << 8) | i);
+            self.write(0x2004, data); // OAMDATA register
+        }
+    }
+
+    /*
+    Reset runs the interrupt sequence with its stack writes turned into reads, so it takes
+    7 cycles and leaves S 3 lower without touching the stack. From power on, S goes from 0
+    to $FD.
+    */
     pub fn reset(&mut self) {
-        self.pc = self.bus.read_u16(RESET_ADDRESS);
+        self.jammed = false;
+        self.read(self.pc);
+        self.read(self.pc);
+        for _ in 0..3 {
+            self.stack_dummy_read();
+            self.s = self.s.wrapping_sub(1);
+        }
+
         self.a = 0;
         self.x = 0;
         self.y = 0;
-        self.s -= 3;
         self.p |= StatusFlags::I;
+
+        let lo = self.read(RESET_ADDRESS);
+        let hi = self.read(RESET_ADDRESS + 1);
+        self.pc = u16::from_le_bytes([lo, hi]);
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            p: self.p.bits(),
+            s: self.s,
+            pc: self.pc,
+        }
+    }
+
+    pub fn set_registers(&mut self, registers: Registers) {
+        self.a = registers.a;
+        self.x = registers.x;
+        self.y = registers.y;
+        self.p = StatusFlags::from_bits_retain(registers.p);
+        self.s = registers.s;
+        self.pc = registers.pc;
     }
 
+    /// Logs every instruction from now on, before it runs.
+    pub fn start_trace(&mut self, tracer: TraceLogger) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Stops logging and hands the logger back, so it can be finished.
+    pub fn stop_trace(&mut self) -> Option<TraceLogger> {
+        self.tracer.take()
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.tracer.is_some()
+    }
+
+    /// CPU cycles since power on.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Whether a KIL/JAM opcode halted the CPU. `pc` is left on the offending opcode.
+    pub fn is_jammed(&self) -> bool {
+        self.jammed
+    }
+
+    /// Runs the NMI sequence right away.
     pub fn nmi(&mut self) {
+        self.need_nmi = true;
+        self.interrupt();
+    }
+
+    /// Runs the IRQ sequence right away, unless a pending NMI hijacks it.
+    pub fn irq(&mut self) {
+        self.interrupt();
+    }
+
+    fn interrupt(&mut self) {
+        // the opcode fetch is replaced with two reads that don't advance PC
+        self.read(self.pc);
+        self.read(self.pc);
+        self.push_interrupt_frame(false);
+    }
+
+    /*
+    BRK, IRQ and NMI share one sequence: push PC, push P, fetch the vector. The vector is
+    picked while P is pushed, so an NMI that shows up before that hijacks a BRK or IRQ:
+    the pushed P is unchanged, but execution continues at the NMI handler and the BRK or
+    IRQ is lost.
+    */
+    fn push_interrupt_frame(&mut self, break_flag: bool) {
         self.push_u16(self.pc);
-        self.push_u8(self.p.bits() | StatusFlags::I.bits()); // we gotta add this B flag here
+
+        let vector = if self.need_nmi {
+            self.need_nmi = false;
+            NMI_ADDRESS
+        } else {
+            IRQ_ADDRESS
+        };
+
+        let mut status = self.p | StatusFlags::U;
+        status.set(StatusFlags::B, break_flag);
+        self.push_u8(status.bits());
         self.p.insert(StatusFlags::I);
-        self.pc = self.bus.read_u16(NMI_ADDRESS);
-        self.cycles += 2;
+
+        let lo = self.read(vector);
+        let hi = self.read(vector + 1);
+        self.pc = u16::from_le_bytes([lo, hi]);
     }
 
     pub fn execute_instruction(&mut self) -> u64 {
-        let current_byte = self.bus.read_u8(self.pc);
+        if self.jammed {
+            // the rest of the console keeps running while the CPU is stuck
+            self.idle_cycle();
+            return 1;
+        }
+
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.trace(self);
+            self.tracer = Some(tracer);
+        }
+
+        let old_cycles = self.cycles;
+        let current_byte = self.read(self.pc);
 
         let opcode_option = OPCODE_MAP.get(&current_byte);
 
@@ -103,27 +364,12 @@ impl Olc6502 {
             ),
         };
 
-        self.pc += 1;
-        let old_cycles = self.cycles;
-        self.handle_addressing(opcode.mode, opcode.cross_cycle);
-
-        // let opcode_bytes = self.bus.read_buffer(self.pc, opcode.mode.size() as u16);
-        // let old_pc = self.pc;
-        // println!(
-        //     "{:04X}  {:02X} {} {}  {} {:28}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
-        //     old_pc,
-        //     opcode_bytes[0],
-        //     if opcode.mode.size() > 1 {format!("{:02X}", opcode_bytes[1])} else {String::from("  ")},
-        //     if opcode.mode.size() > 2 {format!("{:02X}", opcode_bytes[2])} else {String::from("  ")} ,
-        //     format!("{}", opcode.instr),
-        //     opcode.mode.format_operand(self.operand, self),
-        //     self.a,
-        //     self.x,
-        //     self.y,
-        //     self.p.bits(),
-        //     self.s,
-        //     old_cycles
-        // );
+        self.pc = self.pc.wrapping_add(1);
+
+        // JSR fetches its operand in between the stack pushes, so it does its own addressing
+        if opcode.instr != Instruction::JSR {
+            self.handle_addressing(opcode.mode, Access::of(opcode));
+        }
 
         match opcode.instr {
             Instruction::ADC => self.inst_adc(),
@@ -171,7 +417,7 @@ impl Olc6502 {
                     self.inst_lsr_memory()
                 }
             }
-            Instruction::NOP => (),
+            Instruction::NOP => self.inst_nop(opcode.mode),
             Instruction::ORA => self.inst_ora(),
             Instruction::PHA => self.inst_pha(),
             Instruction::PHP => self.inst_php(),
@@ -206,99 +452,142 @@ impl Olc6502 {
             Instruction::TXA => self.inst_txa(),
             Instruction::TXS => self.inst_txs(),
             Instruction::TYA => self.inst_tya(),
-        }
 
-        self.cycles += opcode.cycles as u64;
+            Instruction::ALR => self.inst_alr(),
+            Instruction::ANC => self.inst_anc(),
+            Instruction::ANE => self.inst_ane(),
+            Instruction::ARR => self.inst_arr(),
+            Instruction::DCP => self.inst_dcp(),
+            Instruction::ISC => self.inst_isc(),
+            Instruction::JAM => self.inst_jam(),
+            Instruction::LAS => self.inst_las(),
+            Instruction::LAX => self.inst_lax(),
+            Instruction::LXA => self.inst_lxa(),
+            Instruction::RLA => self.inst_rla(),
+            Instruction::RRA => self.inst_rra(),
+            Instruction::SAX => self.inst_sax(),
+            Instruction::SBX => self.inst_sbx(),
+            Instruction::SHA => self.inst_sha(),
+            Instruction::SHX => self.inst_shx(),
+            Instruction::SHY => self.inst_shy(),
+            Instruction::SLO => self.inst_slo(),
+            Instruction::SRE => self.inst_sre(),
+            Instruction::TAS => self.inst_tas(),
+        }
 
         self.cycles - old_cycles
     }
 
-    fn handle_addressing(&mut self, am: AddressingMode, cross_cycle: bool) {
+    /*
+    Resolves the operand address one bus cycle at a time, including the dummy reads the
+    6502 makes while it computes an address. Immediate operands are left for the
+    instruction to read.
+    */
+    fn handle_addressing(&mut self, am: AddressingMode, access: Access) {
         match am {
+            AddressingMode::Acc | AddressingMode::Impl => {
+                // the byte after the opcode is read and thrown away
+                self.read(self.pc);
+            }
             AddressingMode::Abs => {
-                self.operand = self.bus.read_u16(self.pc);
-                self.pc += 2;
+                self.operand = self.fetch_u16();
             }
             AddressingMode::AbsX => {
-                let base = self.bus.read_u16(self.pc);
-                self.operand = base.wrapping_add(self.x as u16);
-                self.pc += 2;
-
-                if cross_cycle && ((base & 0x00ff) + (self.x as u16) > 0x00ff) {
-                    self.cycles += 1;
-                }
+                let base = self.fetch_u16();
+                self.operand = self.index_address(base, self.x, access);
             }
             AddressingMode::AbsY => {
-                let base = self.bus.read_u16(self.pc);
-                self.operand = base.wrapping_add(self.y as u16);
-                self.pc += 2;
-
-                if cross_cycle && ((base & 0x00ff) + (self.y as u16) > 0x00ff) {
-                    self.cycles += 1;
-                }
+                let base = self.fetch_u16();
+                self.operand = self.index_address(base, self.y, access);
             }
             AddressingMode::Imm => {
                 self.operand = self.pc;
-                self.pc += 1;
+                self.pc = self.pc.wrapping_add(1);
             }
-            AddressingMode::Impl => {}
             AddressingMode::Ind => {
-                let address = self.bus.read_u16(self.pc);
-                self.operand = self.bus.read_u16_no_page_crossing(address);
-
-                self.pc += 2;
+                let pointer = self.fetch_u16();
+                let lo = self.read(pointer);
+                // the pointer never carries into the high byte
+                let hi = self.read((pointer & 0xff00) | (pointer.wrapping_add(1) & 0x00ff));
+                self.operand = u16::from_le_bytes([lo, hi]);
             }
             AddressingMode::XInd => {
-                let address = self.bus.read_u8(self.pc).wrapping_add(self.x);
-                self.operand = self.bus.read_u16_no_page_crossing(address as u16);
-                self.pc += 1;
+                let pointer = self.fetch();
+                self.read(pointer as u16);
+                let pointer = pointer.wrapping_add(self.x);
+                let lo = self.read(pointer as u16);
+                let hi = self.read(pointer.wrapping_add(1) as u16);
+                self.operand = u16::from_le_bytes([lo, hi]);
             }
             AddressingMode::IndY => {
-                let address = self.bus.read_u8(self.pc);
-                let base = self.bus.read_u16_no_page_crossing(address as u16);
-                self.operand = base.wrapping_add(self.y as u16);
-                self.pc += 1;
-
-                if cross_cycle && ((base & 0x00ff) + (self.y as u16) > 0x00ff) {
-                    self.cycles += 1;
-                }
+                let pointer = self.fetch();
+                let lo = self.read(pointer as u16);
+                let hi = self.read(pointer.wrapping_add(1) as u16);
+                self.operand = self.index_address(u16::from_le_bytes([lo, hi]), self.y, access);
             }
             AddressingMode::Rel => {
-                self.operand = self.bus.read_u8(self.pc) as i8 as i16 as u16;
-                self.pc += 1;
+                self.operand = self.fetch() as i8 as i16 as u16;
             }
             AddressingMode::Zpg => {
-                self.operand = self.bus.read_u8(self.pc) as u16;
-                self.pc += 1;
+                self.operand = self.fetch() as u16;
             }
             AddressingMode::ZpgX => {
-                self.operand = self.bus.read_u8(self.pc).wrapping_add(self.x) as u16;
-                self.pc += 1;
+                let base = self.fetch();
+                self.read(base as u16);
+                self.operand = base.wrapping_add(self.x) as u16;
             }
             AddressingMode::ZpgY => {
-                self.operand = self.bus.read_u8(self.pc).wrapping_add(self.y) as u16;
-                self.pc += 1;
+                let base = self.fetch();
+                self.read(base as u16);
+                self.operand = base.wrapping_add(self.y) as u16;
             }
-            _ => (),
         };
     }
 
+    /*
+    The index is added to the low byte first, and the CPU reads from that address while it
+    fixes up the high byte. Reads that didn't cross a page keep that value and skip the fix
+    up; writes and read-modify-writes always take the extra cycle.
+    */
+    fn index_address(&mut self, base: u16, index: u8, access: Access) -> u16 {
+        let address = base.wrapping_add(index as u16);
+        let page_crossed = (base & 0xff00) != (address & 0xff00);
+
+        if page_crossed || access != Access::Read {
+            self.read((base & 0xff00) | (address & 0x00ff));
+        }
+        address
+    }
+
+    /// Read-modify-write instructions write the unmodified value back while they work on it.
+    fn read_for_modify(&mut self) -> u8 {
+        let memory = self.read(self.operand);
+        self.write(self.operand, memory);
+        memory
+    }
+
     //// Instruction helpers ////
 
     fn branch(&mut self, condition: bool) {
         if condition {
-            self.cycles += 1;
+            // a taken branch doesn't poll interrupts on its extra cycle, so an IRQ that
+            // only showed up during the operand fetch waits for the next instruction
+            if self.run_irq && !self.prev_run_irq {
+                self.run_irq = false;
+            }
+
+            self.read(self.pc);
             let old_pc = self.pc;
             self.pc = self.pc.wrapping_add_signed(self.operand as i16);
 
             if (old_pc & 0xff00) != (self.pc & 0xff00) {
-                self.cycles += 1;
+                self.read((old_pc & 0xff00) | (self.pc & 0x00ff));
             }
         }
     }
 
     fn push_u8(&mut self, data: u8) {
-        self.bus_write_u8(0x0100 | (self.s as u16), data);
+        self.write(0x0100 | (self.s as u16), data);
         self.s = self.s.wrapping_sub(1);
     }
 
@@ -309,7 +598,12 @@ impl Olc6502 {
 
     fn pop_u8(&mut self) -> u8 {
         self.s = self.s.wrapping_add(1);
-        self.bus.read_u8(0x0100 | (self.s as u16))
+        self.read(0x0100 | (self.s as u16))
+    }
+
+    /// Pulls start with a read of the current stack slot while S is incremented.
+    fn stack_dummy_read(&mut self) {
+        self.read(0x0100 | (self.s as u16));
     }
 
     fn pop_u16(&mut self) -> u16 {
@@ -325,26 +619,44 @@ impl Olc6502 {
         self.p.set(StatusFlags::N, value & (1 << 7) != 0);
     }
 
-    fn inst_adc(&mut self) {
-        let memory = self.bus.read_u8(self.operand) as u16;
+    fn add_with_carry(&mut self, value: u8) {
         let carry = self.p.contains(StatusFlags::C) as u16;
 
         // a sum set to set flags
-        let sum_u16 = (self.a as u16) + memory + carry;
+        let sum_u16 = (self.a as u16) + value as u16 + carry;
         let sum_u8 = (sum_u16 & 0xff) as u8;
 
         self.p.set(StatusFlags::C, sum_u16 > 0xff);
         self.p.set(
             StatusFlags::V,
-            ((sum_u8 ^ self.a) & (sum_u8 ^ memory as u8) & 0x80) != 0,
+            ((sum_u8 ^ self.a) & (sum_u8 ^ value) & 0x80) != 0,
         );
         self.set_zn_flags(sum_u8);
 
         self.a = sum_u8;
     }
 
+    /*
+    A - M - (1 - C) is the same as A + !M + C, so subtraction is addition of the complement
+    */
+    fn subtract_with_borrow(&mut self, value: u8) {
+        self.add_with_carry(!value);
+    }
+
+    fn compare(&mut self, register: u8, memory: u8) {
+        let result = register.wrapping_sub(memory);
+
+        self.p.set(StatusFlags::C, register >= memory);
+        self.set_zn_flags(result);
+    }
+
+    fn inst_adc(&mut self) {
+        let memory = self.read(self.operand);
+        self.add_with_carry(memory);
+    }
+
     fn inst_and(&mut self) {
-        let memory = self.bus.read_u8(self.operand);
+        let memory = self.read(self.operand);
         self.a &= memory;
         self.set_zn_flags(self.a);
     }
@@ -359,10 +671,10 @@ impl Olc6502 {
     }
 
     fn inst_asl_memory(&mut self) {
-        let mut result = self.bus.read_u8(self.operand);
+        let mut result = self.read_for_modify();
         let old = result;
         result <<= 1;
-        self.bus_write_u8(self.operand, result);
+        self.write(self.operand, result);
 
         self.set_zn_flags(result);
         self.p.set(StatusFlags::C, old & (1 << 7) != 0);
@@ -381,7 +693,7 @@ impl Olc6502 {
     }
 
     fn inst_bit(&mut self) {
-        let memory = self.bus.read_u8(self.operand);
+        let memory = self.read(self.operand);
         let res = self.a & memory;
 
         self.p.set(StatusFlags::Z, res == 0);
@@ -403,12 +715,8 @@ impl Olc6502 {
 
     fn inst_brk(&mut self) {
         // no need to inc pc, as it was incremented because mode is set to immediate here
-        self.push_u16(self.pc);
-        let status = self.p | StatusFlags::B | StatusFlags::U;
-        self.push_u8(status.bits());
-        self.p.insert(StatusFlags::I);
-
-        self.pc = self.bus.read_u16(IRQ_ADDRESS);
+        self.read(self.operand);
+        self.push_interrupt_frame(true);
     }
 
     fn inst_bvc(&mut self) {
@@ -436,33 +744,24 @@ impl Olc6502 {
     }
 
     fn inst_cmp(&mut self) {
-        let memory = self.bus.read_u8(self.operand);
-        let result = self.a.wrapping_sub(memory);
-
-        self.p.set(StatusFlags::C, self.a >= memory);
-        self.set_zn_flags(result);
+        let memory = self.read(self.operand);
+        self.compare(self.a, memory);
     }
 
     fn inst_cpx(&mut self) {
-        let memory = self.bus.read_u8(self.operand);
-        let result = self.x.wrapping_sub(memory);
-
-        self.p.set(StatusFlags::C, self.x >= memory);
-        self.set_zn_flags(result);
+        let memory = self.read(self.operand);
+        self.compare(self.x, memory);
     }
 
     fn inst_cpy(&mut self) {
-        let memory = self.bus.read_u8(self.operand);
-        let result = self.y.wrapping_sub(memory);
-
-        self.p.set(StatusFlags::C, self.y >= memory);
-        self.set_zn_flags(result);
+        let memory = self.read(self.operand);
+        self.compare(self.y, memory);
     }
 
     fn inst_dec(&mut self) {
-        let mut memory = self.bus.read_u8(self.operand);
+        let mut memory = self.read_for_modify();
         memory = memory.wrapping_sub(1);
-        self.bus_write_u8(self.operand, memory);
+        self.write(self.operand, memory);
         self.set_zn_flags(memory);
     }
 
@@ -476,15 +775,15 @@ impl Olc6502 {
         self.set_zn_flags(self.y);
     }
     fn inst_eor(&mut self) {
-        let memory = self.bus.read_u8(self.operand);
+        let memory = self.read(self.operand);
         self.a ^= memory;
         self.set_zn_flags(self.a);
     }
 
     fn inst_inc(&mut self) {
-        let mut memory = self.bus.read_u8(self.operand);
+        let mut memory = self.read_for_modify();
         memory = memory.wrapping_add(1);
-        self.bus_write_u8(self.operand, memory);
+        self.write(self.operand, memory);
         self.set_zn_flags(memory);
     }
 
@@ -503,25 +802,28 @@ impl Olc6502 {
     }
 
     fn inst_jsr(&mut self) {
-        let ret_address = self.pc.wrapping_sub(1);
-        self.push_u16(ret_address);
-        self.pc = self.operand;
+        let lo = self.fetch();
+        self.stack_dummy_read();
+        // the return address pushed is the last byte of the instruction
+        self.push_u16(self.pc);
+        let hi = self.read(self.pc);
+        self.pc = u16::from_le_bytes([lo, hi]);
     }
 
     fn inst_lda(&mut self) {
-        let memory = self.bus.read_u8(self.operand);
+        let memory = self.read(self.operand);
         self.a = memory;
         self.set_zn_flags(self.a);
     }
 
     fn inst_ldx(&mut self) {
-        let memory = self.bus.read_u8(self.operand);
+        let memory = self.read(self.operand);
         self.x = memory;
         self.set_zn_flags(self.x);
     }
 
     fn inst_ldy(&mut self) {
-        let memory = self.bus.read_u8(self.operand);
+        let memory = self.read(self.operand);
         self.y = memory;
         self.set_zn_flags(self.y);
     }
@@ -536,17 +838,17 @@ impl Olc6502 {
     }
 
     fn inst_lsr_memory(&mut self) {
-        let mut result = self.bus.read_u8(self.operand);
+        let mut result = self.read_for_modify();
         let old = result;
         result >>= 1;
-        self.bus_write_u8(self.operand, result);
+        self.write(self.operand, result);
 
         self.set_zn_flags(result);
         self.p.set(StatusFlags::C, old & 1 != 0);
     }
 
     fn inst_ora(&mut self) {
-        let memory = self.bus.read_u8(self.operand);
+        let memory = self.read(self.operand);
         self.a |= memory;
         self.set_zn_flags(self.a);
     }
@@ -561,11 +863,13 @@ impl Olc6502 {
     }
 
     fn inst_pla(&mut self) {
+        self.stack_dummy_read();
         self.a = self.pop_u8();
         self.set_zn_flags(self.a);
     }
 
     fn inst_plp(&mut self) {
+        self.stack_dummy_read();
         let status = self.pop_u8();
         self.p = StatusFlags::from_bits_truncate(status);
         self.p.insert(StatusFlags::U);
@@ -587,7 +891,7 @@ impl Olc6502 {
     }
 
     fn inst_rol_memory(&mut self) {
-        let mut result = self.bus.read_u8(self.operand);
+        let mut result = self.read_for_modify();
         let old = result;
         let carry = if self.p.contains(StatusFlags::C) {
             1
@@ -595,7 +899,7 @@ impl Olc6502 {
             0
         };
         result = (result << 1) | carry;
-        self.bus_write_u8(self.operand, result);
+        self.write(self.operand, result);
 
         self.set_zn_flags(result);
         self.p.set(StatusFlags::C, old & (1 << 7) != 0);
@@ -616,7 +920,7 @@ impl Olc6502 {
     }
 
     fn inst_ror_memory(&mut self) {
-        let mut result = self.bus.read_u8(self.operand);
+        let mut result = self.read_for_modify();
         let old = result;
         let carry = if self.p.contains(StatusFlags::C) {
             1
@@ -624,13 +928,14 @@ impl Olc6502 {
             0
         };
         result = (result >> 1) | (carry << 7);
-        self.bus_write_u8(self.operand, result);
+        self.write(self.operand, result);
 
         self.set_zn_flags(result);
         self.p.set(StatusFlags::C, old & 1 != 0);
     }
 
     fn inst_rti(&mut self) {
+        self.stack_dummy_read();
         let status = self.pop_u8();
         self.p = StatusFlags::from_bits_truncate(status);
         // there is no need to remove the I flag as it wasn't enabled when the register
@@ -641,23 +946,15 @@ impl Olc6502 {
     }
 
     fn inst_rts(&mut self) {
-        self.pc = self.pop_u16().wrapping_add(1);
+        self.stack_dummy_read();
+        self.pc = self.pop_u16();
+        self.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
     }
 
     fn inst_sbc(&mut self) {
-        let memory = self.bus.read_u8(self.operand);
-        let carry = 1 - (self.p.contains(StatusFlags::C) as u8);
-
-        let result = self.a.wrapping_sub(memory).wrapping_sub(carry);
-
-        self.set_zn_flags(result);
-        self.p.set(
-            StatusFlags::V,
-            ((self.a ^ memory) & (self.a ^ result) & 0x80) != 0,
-        );
-        self.p.set(StatusFlags::C, (result as i8) >= 0);
-
-        self.a = result;
+        let memory = self.read(self.operand);
+        self.subtract_with_borrow(memory);
     }
 
     fn inst_sec(&mut self) {
@@ -673,15 +970,15 @@ impl Olc6502 {
     }
 
     fn inst_sta(&mut self) {
-        self.bus_write_u8(self.operand, self.a);
+        self.write(self.operand, self.a);
     }
 
     fn inst_stx(&mut self) {
-        self.bus_write_u8(self.operand, self.x);
+        self.write(self.operand, self.x);
     }
 
     fn inst_sty(&mut self) {
-        self.bus_write_u8(self.operand, self.y);
+        self.write(self.operand, self.y);
     }
 
     fn inst_tax(&mut self) {
@@ -712,4 +1009,218 @@ impl Olc6502 {
         self.a = self.y;
         self.set_zn_flags(self.a);
     }
+
+    //// Unofficial instructions ////
+
+    /*
+    Most unofficial opcodes are two official instructions sharing one decode: a read-modify-
+    write followed by an ALU operation on the result.
+    */
+
+    fn inst_alr(&mut self) {
+        let memory = self.read(self.operand);
+        self.a &= memory;
+        self.inst_lsr_accumulator();
+    }
+
+    fn inst_anc(&mut self) {
+        let memory = self.read(self.operand);
+        self.a &= memory;
+        self.set_zn_flags(self.a);
+        self.p.set(StatusFlags::C, self.a & (1 << 7) != 0);
+    }
+
+    /*
+    ANE and LXA are unstable: the accumulator is ORed with a value that depends on the chip
+    and temperature before the AND. $EE is the commonly documented one.
+    */
+    fn inst_ane(&mut self) {
+        let memory = self.read(self.operand);
+        self.a = (self.a | UNSTABLE_MAGIC_CONSTANT) & self.x & memory;
+        self.set_zn_flags(self.a);
+    }
+
+    fn inst_arr(&mut self) {
+        let memory = self.read(self.operand);
+        let carry = self.p.contains(StatusFlags::C) as u8;
+        self.a = ((self.a & memory) >> 1) | (carry << 7);
+
+        self.set_zn_flags(self.a);
+        self.p.set(StatusFlags::C, self.a & (1 << 6) != 0);
+        self.p
+            .set(StatusFlags::V, ((self.a >> 6) ^ (self.a >> 5)) & 1 != 0);
+    }
+
+    fn inst_dcp(&mut self) {
+        let memory = self.read_for_modify().wrapping_sub(1);
+        self.write(self.operand, memory);
+        self.compare(self.a, memory);
+    }
+
+    fn inst_isc(&mut self) {
+        let memory = self.read_for_modify().wrapping_add(1);
+        self.write(self.operand, memory);
+        self.subtract_with_borrow(memory);
+    }
+
+    fn inst_nop(&mut self, mode: AddressingMode) {
+        // the unofficial NOPs with an operand still read it
+        if mode != AddressingMode::Impl {
+            self.read(self.operand);
+        }
+    }
+
+    fn inst_jam(&mut self) {
+        self.pc = self.pc.wrapping_sub(1);
+        self.jammed = true;
+    }
+
+    fn inst_las(&mut self) {
+        let value = self.read(self.operand) & self.s;
+        self.a = value;
+        self.x = value;
+        self.s = value;
+        self.set_zn_flags(value);
+    }
+
+    fn inst_lax(&mut self) {
+        let memory = self.read(self.operand);
+        self.a = memory;
+        self.x = memory;
+        self.set_zn_flags(memory);
+    }
+
+    fn inst_lxa(&mut self) {
+        let memory = self.read(self.operand);
+        self.a = (self.a | UNSTABLE_MAGIC_CONSTANT) & memory;
+        self.x = self.a;
+        self.set_zn_flags(self.a);
+    }
+
+    fn inst_rla(&mut self) {
+        let memory = self.read_for_modify();
+        let carry = self.p.contains(StatusFlags::C) as u8;
+        let result = (memory << 1) | carry;
+        self.write(self.operand, result);
+
+        self.p.set(StatusFlags::C, memory & (1 << 7) != 0);
+        self.a &= result;
+        self.set_zn_flags(self.a);
+    }
+
+    fn inst_rra(&mut self) {
+        let memory = self.read_for_modify();
+        let carry = self.p.contains(StatusFlags::C) as u8;
+        let result = (memory >> 1) | (carry << 7);
+        self.write(self.operand, result);
+
+        self.p.set(StatusFlags::C, memory & 1 != 0);
+        self.add_with_carry(result);
+    }
+
+    fn inst_sax(&mut self) {
+        self.write(self.operand, self.a & self.x);
+    }
+
+    fn inst_sbx(&mut self) {
+        let memory = self.read(self.operand);
+        let value = self.a & self.x;
+
+        self.p.set(StatusFlags::C, value >= memory);
+        self.x = value.wrapping_sub(memory);
+        self.set_zn_flags(self.x);
+    }
+
+    /*
+    The SH* stores AND the value with the high byte of the base address plus one. When
+    indexing crosses a page, the value also replaces the high byte of the target address.
+    */
+    fn store_and_high_byte(&mut self, value: u8, index: u8) {
+        let base = self.operand.wrapping_sub(index as u16);
+        let value = value & ((base >> 8) as u8).wrapping_add(1);
+
+        let address = if (base & 0xff00) != (self.operand & 0xff00) {
+            ((value as u16) << 8) | (self.operand & 0x00ff)
+        } else {
+            self.operand
+        };
+        self.write(address, value);
+    }
+
+    fn inst_sha(&mut self) {
+        self.store_and_high_byte(self.a & self.x, self.y);
+    }
+
+    fn inst_shx(&mut self) {
+        self.store_and_high_byte(self.x, self.y);
+    }
+
+    fn inst_shy(&mut self) {
+        self.store_and_high_byte(self.y, self.x);
+    }
+
+    fn inst_slo(&mut self) {
+        let memory = self.read_for_modify();
+        let result = memory << 1;
+        self.write(self.operand, result);
+
+        self.p.set(StatusFlags::C, memory & (1 << 7) != 0);
+        self.a |= result;
+        self.set_zn_flags(self.a);
+    }
+
+    fn inst_sre(&mut self) {
+        let memory = self.read_for_modify();
+        let result = memory >> 1;
+        self.write(self.operand, result);
+
+        self.p.set(StatusFlags::C, memory & 1 != 0);
+        self.a ^= result;
+        self.set_zn_flags(self.a);
+    }
+
+    fn inst_tas(&mut self) {
+        self.s = self.a & self.x;
+        self.store_and_high_byte(self.s, self.y);
+    }
+}
+
+/*
+Besides what the CPU sampled, interrupts live with their sources: the NMI edge in the PPU,
+and the IRQ line in the APU and the mapper.
+*/
+impl Snapshot for Olc6502 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.a);
+        state.write_u8(self.x);
+        state.write_u8(self.y);
+        state.write_u8(self.p.bits());
+        state.write_u8(self.s);
+        state.write_u16(self.pc);
+        state.write_u16(self.operand);
+        state.write_u64(self.cycles);
+        state.write_bool(self.jammed);
+        state.write_bool(self.need_nmi);
+        state.write_bool(self.prev_need_nmi);
+        state.write_bool(self.run_irq);
+        state.write_bool(self.prev_run_irq);
+        self.bus.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.a = state.read_u8()?;
+        self.x = state.read_u8()?;
+        self.y = state.read_u8()?;
+        self.p = StatusFlags::from_bits_retain(state.read_u8()?);
+        self.s = state.read_u8()?;
+        self.pc = state.read_u16()?;
+        self.operand = state.read_u16()?;
+        self.cycles = state.read_u64()?;
+        self.jammed = state.read_bool()?;
+        self.need_nmi = state.read_bool()?;
+        self.prev_need_nmi = state.read_bool()?;
+        self.run_irq = state.read_bool()?;
+        self.prev_run_irq = state.read_bool()?;
+        self.bus.load_state(state)
+    }
 }
@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::cpu::bus::CpuBus;
+use crate::cpu::instructions::{Instruction, OPCODE_MAP};
+use crate::cpu::olc6502::Olc6502;
+use crate::debugger::Condition;
+
+/*
+Logs one line per instruction in the nestest.log format that Mesen can also produce, so
+traces can be diffed against reference emulators:
+
+C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+
+Lines show the state before the instruction runs. Unofficial opcodes are marked with `*`.
+Interrupt sequences aren't instructions and don't get a line.
+*/
+pub struct TraceLogger {
+    output: Box<dyn Write>,
+    pc_range: Option<RangeInclusive<u16>>,
+    trigger: Option<Condition>,
+    triggered: bool, // the trigger held once, from then on everything is logged
+    error: Option<io::Error>,
+}
+
+impl TraceLogger {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        Self {
+            output,
+            pc_range: None,
+            trigger: None,
+            triggered: false,
+            error: None,
+        }
+    }
+
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self::new(Box::new(BufWriter::new(file))))
+    }
+
+    /// Only logs instructions whose address is in `range`.
+    pub fn set_pc_range(&mut self, range: Option<RangeInclusive<u16>>) {
+        self.pc_range = range;
+    }
+
+    /// Starts logging at the first instruction where `condition` holds.
+    pub fn set_trigger(&mut self, condition: Option<Condition>) {
+        self.trigger = condition;
+        self.triggered = false;
+    }
+
+    pub(crate) fn trace<B: CpuBus>(&mut self, cpu: &Olc6502<B>) {
+        if self.error.is_some() {
+            return;
+        }
+
+        if !self.triggered {
+            let registers = cpu.registers();
+            self.triggered = self
+                .trigger
+                .is_none_or(|condition| condition.holds(&registers));
+            if !self.triggered {
+                return;
+            }
+        }
+
+        if self
+            .pc_range
+            .as_ref()
+            .is_some_and(|range| !range.contains(&cpu.pc))
+        {
+            return;
+        }
+
+        if let Err(err) = writeln!(self.output, "{}", trace_line(cpu)) {
+            self.error = Some(err);
+        }
+    }
+
+    /// Flushes the log, and reports the first write error if logging stopped on one.
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.output.flush(),
+        }
+    }
+}
+
+/// The trace line for the instruction the CPU is about to run.
+pub fn trace_line<B: CpuBus>(cpu: &Olc6502<B>) -> String {
+    let pc = cpu.pc;
+    let opcode = OPCODE_MAP[&cpu.bus.peek_u8(pc)];
+
+    let bytes: Vec<String> = (0..opcode.mode.size())
+        .map(|offset| format!("{:02X}", cpu.bus.peek_u8(pc.wrapping_add(offset))))
+        .collect();
+
+    // nestest.log spells ISC the other common way
+    let mnemonic = match opcode.instr {
+        Instruction::ISC => String::from("ISB"),
+        instr => instr.to_string(),
+    };
+    let disassembly = format!(
+        "{mnemonic} {}",
+        opcode.mode.format_operand(opcode.instr, pc, cpu)
+    );
+
+    let registers = cpu.registers();
+    let (scanline, dot) = cpu.bus.ppu_position();
+    format!(
+        "{pc:04X}  {:<8} {}{:<31} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        bytes.join(" "),
+        if opcode.is_unofficial() { '*' } else { ' ' },
+        disassembly.trim_end(),
+        registers.a,
+        registers.x,
+        registers.y,
+        registers.p,
+        registers.s,
+        scanline,
+        dot,
+        cpu.cycles(),
+    )
+}
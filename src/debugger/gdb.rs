@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use crate::Nes;
+use crate::cpu::olc6502::Registers;
+use crate::debugger::{AddressSpace, Breakpoint, Debugger, StopReason, Watchpoint};
+
+const INTERRUPT: u8 = 0x03;
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+// gdb has no 6502 support built in, so the registers are described to it
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.simpleness.mos6502">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+    <reg name="s" bitsize="8" type="uint8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/*
+A GDB remote serial protocol stub for the console's CPU, serving one client over TCP.
+
+Registers are numbered a, x, y, p, s, pc, in that order, and `g` sends them the same way
+with pc in little endian. Memory is the CPU's address space, read and written through the
+bus like the CPU would, so reading an I/O register has its usual side effects.
+Breakpoints and watchpoints are kept by a `Debugger` instead of patching memory, which
+also works on ROM. While running, the client's interrupt byte is checked once per frame.
+*/
+pub fn serve(nes: &mut Nes, stream: TcpStream) -> io::Result<()> {
+    if !nes.rom_loaded() {
+        return Err(io::Error::other("a ROM has to be loaded to debug it"));
+    }
+
+    GdbSession {
+        nes,
+        stream,
+        debugger: Debugger::new(),
+        breakpoints: HashMap::new(),
+        watchpoints: HashMap::new(),
+        acknowledge: true,
+    }
+    .run()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+enum Reply {
+    Packet(String),
+    Resume,
+    Close(Option<String>),
+}
+
+struct GdbSession<'a> {
+    nes: &'a mut Nes,
+    stream: TcpStream,
+    debugger: Debugger,
+    // gdb refers to points by address, the debugger by id
+    breakpoints: HashMap<u16, usize>,
+    watchpoints: HashMap<(WatchKind, u16, u16), usize>, // keyed by kind, address and length
+    acknowledge: bool,                                  // until the client asks for no-ack mode
+}
+
+impl GdbSession<'_> {
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle(&packet) {
+                Reply::Packet(reply) => self.send(&reply)?,
+                Reply::Resume => match self.run_until_stop()? {
+                    Some(reply) => self.send(&reply)?,
+                    None => return Ok(()),
+                },
+                Reply::Close(reply) => {
+                    if let Some(reply) = reply {
+                        self.send(&reply)?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str) -> Reply {
+        let reply = match packet.split_at_checked(1).unwrap_or(("", "")) {
+            ("?", _) => format!("S{SIGTRAP:02x}"),
+            ("g", _) => encode_registers(&self.nes.cpu().registers()),
+            ("G", data) => match decode_hex(data).as_deref() {
+                Some(&[a, x, y, p, s, pc_lo, pc_hi]) => {
+                    self.nes.cpu_mut().set_registers(Registers {
+                        a,
+                        x,
+                        y,
+                        p,
+                        s,
+                        pc: u16::from_le_bytes([pc_lo, pc_hi]),
+                    });
+                    String::from("OK")
+                }
+                _ => error_reply(),
+            },
+            ("p", register) => self.read_register(register).unwrap_or_else(error_reply),
+            ("P", assignment) => self
+                .write_register(assignment)
+                .map(|()| String::from("OK"))
+                .unwrap_or_else(error_reply),
+            ("m", range) => self.read_memory(range).unwrap_or_else(error_reply),
+            ("M", write) => self
+                .write_memory(write)
+                .map(|()| String::from("OK"))
+                .unwrap_or_else(error_reply),
+            ("Z", point) => self
+                .insert_point(point)
+                .map(|()| String::from("OK"))
+                .unwrap_or_else(error_reply),
+            ("z", point) => self
+                .remove_point(point)
+                .map(|()| String::from("OK"))
+                .unwrap_or_else(error_reply),
+            ("c", address) => {
+                self.resume_at(address);
+                self.debugger.resume();
+                return Reply::Resume;
+            }
+            ("s", address) => {
+                self.resume_at(address);
+                self.debugger.step_into();
+                return Reply::Resume;
+            }
+            ("H", _) => String::from("OK"),
+            ("k", _) => return Reply::Close(None),
+            ("D", _) => return Reply::Close(Some(String::from("OK"))),
+            _ => self.handle_query(packet),
+        };
+        Reply::Packet(reply)
+    }
+
+    fn handle_query(&mut self, packet: &str) -> String {
+        match packet {
+            _ if packet.starts_with("qSupported") => {
+                String::from("PacketSize=4000;qXfer:features:read+;QStartNoAckMode+")
+            }
+            "QStartNoAckMode" => {
+                self.acknowledge = false;
+                String::from("OK")
+            }
+            "qAttached" => String::from("1"),
+            "qC" => String::from("QC1"),
+            "qfThreadInfo" => String::from("m1"),
+            "qsThreadInfo" => String::from("l"),
+            _ => match packet.strip_prefix("qXfer:features:read:target.xml:") {
+                Some(range) => read_target_xml(range).unwrap_or_else(error_reply),
+                // an empty reply tells the client the packet isn't supported
+                None => String::new(),
+            },
+        }
+    }
+
+    fn resume_at(&mut self, address: &str) {
+        if let Ok(address) = u16::from_str_radix(address, 16) {
+            self.nes.cpu_mut().pc = address;
+        }
+    }
+
+    /// Runs until the debugger stops, and gives the stop reply. `None` when the client
+    /// went away in the meantime.
+    fn run_until_stop(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(reason) = self.debugger.run_frame(self.nes) {
+                return Ok(Some(self.stop_reply(reason)));
+            }
+
+            self.stream.set_nonblocking(true)?;
+            let mut byte = [0];
+            let polled = self.stream.read(&mut byte);
+            self.stream.set_nonblocking(false)?;
+
+            match polled {
+                Ok(0) => return Ok(None),
+                Ok(_) if byte[0] == INTERRUPT => {
+                    self.debugger.pause();
+                    return Ok(Some(format!("S{SIGINT:02x}")));
+                }
+                Ok(_) => (),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => (),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::Watchpoint(access) => {
+                let kind = self
+                    .watchpoints
+                    .iter()
+                    .find(|(_, id)| **id == access.watchpoint)
+                    .map(|((kind, _, _), _)| *kind);
+                let name = match kind {
+                    Some(WatchKind::Read) => "rwatch",
+                    Some(WatchKind::Access) => "awatch",
+                    _ => "watch",
+                };
+                format!("T{SIGTRAP:02x}{name}:{:04x};", access.address)
+            }
+            StopReason::Jammed => format!("S{SIGILL:02x}"),
+            _ => format!("S{SIGTRAP:02x}"),
+        }
+    }
+
+    fn read_register(&self, register: &str) -> Option<String> {
+        let registers = self.nes.cpu().registers();
+        let value = match usize::from_str_radix(register, 16).ok()? {
+            0 => registers.a,
+            1 => registers.x,
+            2 => registers.y,
+            3 => registers.p,
+            4 => registers.s,
+            5 => return Some(encode_hex(&registers.pc.to_le_bytes())),
+            _ => return None,
+        };
+        Some(encode_hex(&[value]))
+    }
+
+    fn write_register(&mut self, assignment: &str) -> Option<()> {
+        let (register, value) = assignment.split_once('=')?;
+        let value = decode_hex(value)?;
+        let mut registers = self.nes.cpu().registers();
+
+        match (usize::from_str_radix(register, 16).ok()?, value.as_slice()) {
+            (0, &[a]) => registers.a = a,
+            (1, &[x]) => registers.x = x,
+            (2, &[y]) => registers.y = y,
+            (3, &[p]) => registers.p = p,
+            (4, &[s]) => registers.s = s,
+            (5, &[lo, hi]) => registers.pc = u16::from_le_bytes([lo, hi]),
+            _ => return None,
+        }
+        self.nes.cpu_mut().set_registers(registers);
+        Some(())
+    }
+
+    fn read_memory(&mut self, range: &str) -> Option<String> {
+        let (address, length) = parse_address_length(range)?;
+        let bus = &mut self.nes.cpu_mut().bus;
+        let data: Vec<u8> = (0..length)
+            .map(|offset| bus.read_u8(address.wrapping_add(offset)))
+            .collect();
+        Some(encode_hex(&data))
+    }
+
+    fn write_memory(&mut self, write: &str) -> Option<()> {
+        let (range, data) = write.split_once(':')?;
+        let (address, length) = parse_address_length(range)?;
+        let data = decode_hex(data)?;
+        if data.len() != length as usize {
+            return None;
+        }
+
+        let bus = &mut self.nes.cpu_mut().bus;
+        for (offset, byte) in data.into_iter().enumerate() {
+            bus.write_u8(address.wrapping_add(offset as u16), byte);
+        }
+        // a write to OAMDMA from here shouldn't start a transfer on the CPU's next write
+        bus.take_oam_dma_request();
+        Some(())
+    }
+
+    fn insert_point(&mut self, point: &str) -> Option<()> {
+        let (kind, address, length) = parse_point(point)?;
+        match kind {
+            None => {
+                let id = self.debugger.add_breakpoint(Breakpoint {
+                    address: Some(address),
+                    condition: None,
+                });
+                if let Some(old) = self.breakpoints.insert(address, id) {
+                    self.debugger.remove(old);
+                }
+            }
+            Some(kind) => {
+                let id = self.debugger.add_watchpoint(Watchpoint {
+                    space: AddressSpace::Cpu,
+                    range: address..=address.saturating_add(length.max(1) - 1),
+                    on_read: kind != WatchKind::Write,
+                    on_write: kind != WatchKind::Read,
+                });
+                if let Some(old) = self.watchpoints.insert((kind, address, length), id) {
+                    self.debugger.remove(old);
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn remove_point(&mut self, point: &str) -> Option<()> {
+        let (kind, address, length) = parse_point(point)?;
+        let id = match kind {
+            None => self.breakpoints.remove(&address)?,
+            Some(kind) => self.watchpoints.remove(&(kind, address, length))?,
+        };
+        self.debugger.remove(id).then_some(())
+    }
+
+    /// Waits for the next `$data#checksum` packet, `None` once the client disconnects.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // acknowledgements and stray interrupts between packets are skipped
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'$') => break,
+                    Some(_) => (),
+                }
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+
+            let mut checksum = [0; 2];
+            for digit in checksum.iter_mut() {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(byte) => *digit = byte,
+                }
+            }
+
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(packet_checksum(&data));
+            if self.acknowledge {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        loop {
+            match self.stream.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(err) if err.kind() == ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${data}#{:02x}", packet_checksum(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+}
+
+fn packet_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn error_reply() -> String {
+    String::from("E01")
+}
+
+fn encode_registers(registers: &Registers) -> String {
+    let pc = registers.pc.to_le_bytes();
+    encode_hex(&[
+        registers.a,
+        registers.x,
+        registers.y,
+        registers.p,
+        registers.s,
+        pc[0],
+        pc[1],
+    ])
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// `addr,length` in hex.
+fn parse_address_length(range: &str) -> Option<(u16, u16)> {
+    let (address, length) = range.split_once(',')?;
+    Some((
+        u16::from_str_radix(address, 16).ok()?,
+        u16::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+/// `type,addr,kind` of a `Z`/`z` packet. Types 0 and 1 are breakpoints, 2 to 4 are write,
+/// read and access watchpoints where kind is the watched length.
+fn parse_point(point: &str) -> Option<(Option<WatchKind>, u16, u16)> {
+    let (point_type, range) = point.split_once(',')?;
+    let (address, length) = parse_address_length(range)?;
+    let kind = match point_type {
+        "0" | "1" => None,
+        "2" => Some(WatchKind::Write),
+        "3" => Some(WatchKind::Read),
+        "4" => Some(WatchKind::Access),
+        _ => return None,
+    };
+    Some((kind, address, length))
+}
+
+/// Answers `offset,length` of a `qXfer:features:read` request.
+fn read_target_xml(range: &str) -> Option<String> {
+    let (offset, length) = range.split_once(',')?;
+    let offset = usize::from_str_radix(offset, 16).ok()?;
+    let length = usize::from_str_radix(length, 16).ok()?;
+
+    let xml = TARGET_XML.get(offset.min(TARGET_XML.len())..)?;
+    if xml.len() > length {
+        Some(format!("m{}", &xml[..length]))
+    } else {
+        Some(format!("l{xml}"))
+    }
+}
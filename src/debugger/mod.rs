@@ -0,0 +1,414 @@
+pub mod gdb;
+pub mod repl;
+
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use crate::Nes;
+use crate::cpu::bus::AccessKind;
+use crate::cpu::olc6502::Registers;
+
+const JSR_OPCODE: u8 = 0x20;
+const RTI_OPCODE: u8 = 0x40;
+const RTS_OPCODE: u8 = 0x60;
+const JSR_LENGTH: u16 = 3;
+
+/*
+Breakpoints, watchpoints and stepping for a `Nes`. The debugger runs the console one
+instruction at a time and checks its stop conditions in between, so it always stops on an
+instruction boundary. Memory accesses are reported by the CPU and the PPU through the
+`MemoryWatch` on their side, which only does work while something is watched.
+*/
+pub struct Debugger {
+    breakpoints: BTreeMap<usize, BreakpointEntry>,
+    watchpoints: BTreeMap<usize, Watchpoint>,
+    next_id: usize, // breakpoints and watchpoints share their numbering
+    run_state: RunState,
+    resuming: bool, // the next instruction is the one the last stop happened before
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    Cpu,
+    Ppu,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub space: AddressSpace,
+    pub range: RangeInclusive<u16>,
+    pub on_read: bool,
+    pub on_write: bool,
+}
+
+impl Watchpoint {
+    fn matches(&self, kind: AccessKind, address: u16) -> bool {
+        let watched_kind = match kind {
+            AccessKind::Read => self.on_read,
+            AccessKind::Write => self.on_write,
+        };
+        watched_kind && self.range.contains(&address)
+    }
+}
+
+/// A watched access, caught in the middle of the instruction that made it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub watchpoint: usize,
+    pub space: AddressSpace,
+    pub kind: AccessKind,
+    pub address: u16,
+    pub value: u8,
+}
+
+/// The watchpoints of one bus, and the first access that hit one of them.
+#[derive(Default)]
+pub struct MemoryWatch {
+    watchpoints: Vec<(usize, Watchpoint)>,
+    hit: Option<MemoryAccess>,
+}
+
+impl MemoryWatch {
+    pub fn record(&mut self, kind: AccessKind, address: u16, value: u8) {
+        if self.watchpoints.is_empty() || self.hit.is_some() {
+            return;
+        }
+
+        if let Some((id, watchpoint)) = self
+            .watchpoints
+            .iter()
+            .find(|(_, watchpoint)| watchpoint.matches(kind, address))
+        {
+            self.hit = Some(MemoryAccess {
+                watchpoint: *id,
+                space: watchpoint.space,
+                kind,
+                address,
+                value,
+            });
+        }
+    }
+
+    fn take_hit(&mut self) -> Option<MemoryAccess> {
+        self.hit.take()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    P,
+    S,
+    PC,
+}
+
+impl Register {
+    pub fn value(self, registers: &Registers) -> u16 {
+        match self {
+            Register::A => registers.a as u16,
+            Register::X => registers.x as u16,
+            Register::Y => registers.y as u16,
+            Register::P => registers.p as u16,
+            Register::S => registers.s as u16,
+            Register::PC => registers.pc,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A test on a register, such as `x >= $10`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn holds(&self, registers: &Registers) -> bool {
+        let register = self.register.value(registers);
+        match self.comparison {
+            Comparison::Equal => register == self.value,
+            Comparison::NotEqual => register != self.value,
+            Comparison::Less => register < self.value,
+            Comparison::LessOrEqual => register <= self.value,
+            Comparison::Greater => register > self.value,
+            Comparison::GreaterOrEqual => register >= self.value,
+        }
+    }
+}
+
+/*
+A breakpoint stops before the instruction at `address` runs, if its condition holds
+there. Without an address the condition is checked before every instruction, and the
+breakpoint only fires when it goes from false to true. Breakpoints never fire on the
+instruction the debugger resumes from, so continuing doesn't stop again right away.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: Option<u16>,
+    pub condition: Option<Condition>,
+}
+
+struct BreakpointEntry {
+    breakpoint: Breakpoint,
+    condition_held: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(usize),
+    Watchpoint(MemoryAccess),
+    Step,
+    Scanline(u16),
+    Jammed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunState {
+    Paused,
+    Running(Target),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Continue,
+    Step,
+    StepOver {
+        return_address: u16,
+        stack_pointer: u8,
+    },
+    StepOut {
+        stack_pointer: u8,
+    },
+    Scanline(u16),
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    /// A debugger with nothing set, paused.
+    pub fn new() -> Self {
+        Self {
+            breakpoints: BTreeMap::new(),
+            watchpoints: BTreeMap::new(),
+            next_id: 1,
+            run_state: RunState::Paused,
+            resuming: false,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.run_state == RunState::Paused
+    }
+
+    pub fn pause(&mut self) {
+        self.run_state = RunState::Paused;
+    }
+
+    fn run(&mut self, target: Target) {
+        if self.is_paused() {
+            self.resuming = true;
+        }
+        self.run_state = RunState::Running(target);
+    }
+
+    /// Runs until something stops the console.
+    pub fn resume(&mut self) {
+        self.run(Target::Continue);
+    }
+
+    /// Runs a single instruction.
+    pub fn step_into(&mut self) {
+        self.run(Target::Step);
+    }
+
+    /// Runs a single instruction, or a whole subroutine when it is a JSR.
+    pub fn step_over(&mut self, nes: &Nes) {
+        let cpu = nes.cpu();
+        let target = if cpu.bus.peek_u8(cpu.pc) == JSR_OPCODE {
+            Target::StepOver {
+                return_address: cpu.pc.wrapping_add(JSR_LENGTH),
+                stack_pointer: cpu.registers().s,
+            }
+        } else {
+            Target::Step
+        };
+        self.run(target);
+    }
+
+    /// Runs until the current subroutine or interrupt handler returns.
+    pub fn step_out(&mut self, nes: &Nes) {
+        let stack_pointer = nes.cpu().registers().s;
+        self.run(Target::StepOut { stack_pointer });
+    }
+
+    /// Runs until the PPU starts drawing `scanline`.
+    pub fn run_to_scanline(&mut self, scanline: u16) {
+        self.run(Target::Scanline(scanline));
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.allocate_id();
+        self.breakpoints.insert(
+            id,
+            BreakpointEntry {
+                breakpoint,
+                condition_held: false,
+            },
+        );
+        id
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.allocate_id();
+        self.watchpoints.insert(id, watchpoint);
+        id
+    }
+
+    /// Deletes a breakpoint or watchpoint, returns whether `id` existed.
+    pub fn remove(&mut self, id: usize) -> bool {
+        self.breakpoints.remove(&id).is_some() || self.watchpoints.remove(&id).is_some()
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (usize, &Breakpoint)> {
+        self.breakpoints
+            .iter()
+            .map(|(id, entry)| (*id, &entry.breakpoint))
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.watchpoints
+            .iter()
+            .map(|(id, watchpoint)| (*id, watchpoint))
+    }
+
+    fn allocate_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /*
+    Stands in for `Nes::run_frame` while debugging. Runs until the frame is done, or until
+    something stops the console, and then pauses. A stop in the middle of a frame leaves
+    the rest of it for the next call once resumed.
+    */
+    pub fn run_frame(&mut self, nes: &mut Nes) -> Option<StopReason> {
+        let RunState::Running(target) = self.run_state else {
+            return None;
+        };
+        if !nes.rom_loaded() {
+            return None;
+        }
+
+        // the machine is rebuilt when a state is loaded, so the watchpoints are handed
+        // to it every time
+        self.install_watchpoints(nes);
+
+        let cpu = nes.cpu_mut();
+        loop {
+            if cpu.bus.ppu.frame_ready() {
+                return None;
+            }
+
+            // checked even when resuming, to keep track of the conditions
+            let breakpoint = self.check_breakpoints(&cpu.registers());
+            let resuming = std::mem::take(&mut self.resuming);
+            if let Some(id) = breakpoint.filter(|_| !resuming) {
+                self.run_state = RunState::Paused;
+                return Some(StopReason::Breakpoint(id));
+            }
+
+            let opcode = cpu.bus.peek_u8(cpu.pc);
+            let scanline = cpu.bus.ppu.scanline();
+            cpu.tick();
+            let registers = cpu.registers();
+
+            let stop = if let Some(access) = cpu
+                .bus
+                .watch
+                .take_hit()
+                .or_else(|| cpu.bus.ppu.watch.take_hit())
+            {
+                Some(StopReason::Watchpoint(access))
+            } else if cpu.is_jammed() {
+                Some(StopReason::Jammed)
+            } else {
+                match target {
+                    Target::Continue => None,
+                    Target::Step => Some(StopReason::Step),
+                    Target::StepOver {
+                        return_address,
+                        stack_pointer,
+                    } => (registers.pc == return_address && registers.s == stack_pointer)
+                        .then_some(StopReason::Step),
+                    Target::StepOut { stack_pointer } => ((opcode == RTS_OPCODE
+                        || opcode == RTI_OPCODE)
+                        && registers.s > stack_pointer)
+                        .then_some(StopReason::Step),
+                    Target::Scanline(line) => {
+                        let current = cpu.bus.ppu.scanline();
+                        (current == line && scanline != line).then_some(StopReason::Scanline(line))
+                    }
+                }
+            };
+
+            if stop.is_some() {
+                self.run_state = RunState::Paused;
+                return stop;
+            }
+        }
+    }
+
+    fn install_watchpoints(&self, nes: &mut Nes) {
+        let in_space = |space| {
+            self.watchpoints
+                .iter()
+                .filter(|(_, watchpoint)| watchpoint.space == space)
+                .map(|(id, watchpoint)| (*id, watchpoint.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let bus = &mut nes.cpu_mut().bus;
+        bus.watch.watchpoints = in_space(AddressSpace::Cpu);
+        bus.ppu.watch.watchpoints = in_space(AddressSpace::Ppu);
+    }
+
+    /// The first breakpoint that fires before the instruction the registers point at.
+    fn check_breakpoints(&mut self, registers: &Registers) -> Option<usize> {
+        let mut fired = None;
+        for (id, entry) in self.breakpoints.iter_mut() {
+            let breakpoint = entry.breakpoint;
+            let condition_holds = breakpoint
+                .condition
+                .is_none_or(|condition| condition.holds(registers));
+
+            let fires = match breakpoint.address {
+                Some(address) => registers.pc == address && condition_holds,
+                None => condition_holds && !entry.condition_held,
+            };
+            entry.condition_held = condition_holds;
+
+            if fires && fired.is_none() {
+                fired = Some(*id);
+            }
+        }
+        fired
+    }
+}
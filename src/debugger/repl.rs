@@ -0,0 +1,470 @@
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+use crate::Nes;
+use crate::cpu::assembler::assemble;
+use crate::cpu::bus::AccessKind;
+use crate::cpu::disassembler::disassemble_one;
+use crate::cpu::trace::TraceLogger;
+use crate::debugger::{
+    AddressSpace, Breakpoint, Comparison, Condition, Debugger, Register, StopReason, Watchpoint,
+};
+
+pub const PROMPT: &str = "(debug) ";
+
+const STACK_VIEW_BYTES: u16 = 8;
+const MEMORY_ROW_BYTES: usize = 16;
+
+const HELP: &str = "\
+break <addr> [if <reg> <op> <value>]  stop before the instruction at addr (b)
+break if <reg> <op> <value>           stop when the condition becomes true
+watch [cpu|ppu] <addr>[-<addr>] [r|w|rw]
+                                      stop on an access, ppu watches PPUDATA (w)
+delete <id>                           delete a breakpoint or watchpoint (d)
+list                                  list breakpoints and watchpoints (l)
+step                                  run one instruction (s)
+next                                  step over subroutine calls (n)
+finish                                run until the subroutine returns
+continue                              run until something stops (c)
+scanline <line>                       run until the PPU reaches a scanline
+pause                                 stop running
+regs                                  show registers, flags and the stack (r)
+x [cpu|ppu] <addr> [count]            dump memory
+asm <addr> <instruction>              assemble an instruction into memory (a)
+trace <file> [<addr>-<addr>] [if <reg> <op> <value>]
+                                      log instructions in nestest format, from
+                                      when the condition holds
+trace off                             stop logging
+registers are a, x, y, p, s and pc, comparisons are == != < <= > >=
+addresses and values are hexadecimal, with or without $, lines and counts are decimal";
+
+/*
+The debugger's terminal interface: one command per line, answered with the text to show.
+Commands that run the console only change the debugger's state, the frontend then runs it
+through `Debugger::run_frame` and reports where it stopped with `describe_stop`.
+*/
+pub fn execute(debugger: &mut Debugger, nes: &mut Nes, line: &str) -> String {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((&command, args)) = words.split_first() else {
+        return String::new();
+    };
+
+    let result = match command {
+        "break" | "b" => parse_breakpoint(args).map(|breakpoint| {
+            let id = debugger.add_breakpoint(breakpoint);
+            format!("Breakpoint {id}: {}", format_breakpoint(&breakpoint))
+        }),
+        "watch" | "w" => parse_watchpoint(args).map(|watchpoint| {
+            let description = format_watchpoint(&watchpoint);
+            let id = debugger.add_watchpoint(watchpoint);
+            format!("Watchpoint {id}: {description}")
+        }),
+        "delete" | "d" => match args {
+            [id] => id
+                .parse()
+                .map_err(|_| format!("Invalid id: {id}"))
+                .and_then(|id| match debugger.remove(id) {
+                    true => Ok(format!("Deleted {id}")),
+                    false => Err(format!("No breakpoint or watchpoint {id}")),
+                }),
+            _ => Err(String::from("Usage: delete <id>")),
+        },
+        "list" | "l" => Ok(list(debugger)),
+        "step" | "s" => {
+            debugger.step_into();
+            Ok(String::new())
+        }
+        "next" | "n" => {
+            debugger.step_over(nes);
+            Ok(String::new())
+        }
+        "finish" => {
+            debugger.step_out(nes);
+            Ok(String::new())
+        }
+        "continue" | "c" => {
+            debugger.resume();
+            Ok(String::new())
+        }
+        "scanline" => match args {
+            [line] => match line.parse::<u16>() {
+                Ok(line) if line <= 261 => {
+                    debugger.run_to_scanline(line);
+                    Ok(String::new())
+                }
+                _ => Err(format!("Invalid scanline: {line}, expected 0 to 261")),
+            },
+            _ => Err(String::from("Usage: scanline <line>")),
+        },
+        "pause" => {
+            debugger.pause();
+            Ok(status(nes))
+        }
+        "regs" | "r" => Ok(status(nes)),
+        "x" => dump_memory(nes, args),
+        "asm" | "a" => patch(nes, args),
+        "trace" => trace(nes, args),
+        "help" | "h" => Ok(String::from(HELP)),
+        _ => Err(format!("Unknown command: {command}, try help")),
+    };
+
+    result.unwrap_or_else(|err| err)
+}
+
+/// Tells where and why the console stopped.
+pub fn describe_stop(nes: &Nes, reason: StopReason) -> String {
+    let cpu = nes.cpu();
+    let headline = match reason {
+        StopReason::Breakpoint(id) => format!("Breakpoint {id} at ${:04X}", cpu.pc),
+        StopReason::Watchpoint(access) => format!(
+            "Watchpoint {}: {} {} ${:04X} = ${:02X}",
+            access.watchpoint,
+            format_space(access.space),
+            match access.kind {
+                AccessKind::Read => "read",
+                AccessKind::Write => "write",
+            },
+            access.address,
+            access.value
+        ),
+        StopReason::Step => return status(nes),
+        StopReason::Scanline(line) => format!("Reached scanline {line}"),
+        StopReason::Jammed => format!("CPU jammed at ${:04X}", cpu.pc),
+    };
+    format!("{headline}\n{}", status(nes))
+}
+
+/// The registers, flags, top of the stack and the next instruction.
+pub fn status(nes: &Nes) -> String {
+    let cpu = nes.cpu();
+    let registers = cpu.registers();
+    let ppu = &cpu.bus.ppu;
+
+    let mut status = format!(
+        "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} [{}] S:{:02X} CYC:{} SL:{} DOT:{}\n",
+        registers.pc,
+        registers.a,
+        registers.x,
+        registers.y,
+        registers.p,
+        format_flags(registers.p),
+        registers.s,
+        cpu.cycles(),
+        ppu.scanline(),
+        ppu.dot(),
+    );
+
+    status.push_str("stack:");
+    let top = registers.s as u16 + 1;
+    for address in top..(top + STACK_VIEW_BYTES).min(0x100) {
+        write!(status, " {:02X}", cpu.bus.peek_u8(0x0100 | address)).unwrap();
+    }
+    status.push('\n');
+
+    status.push_str(&format_instruction(nes, registers.pc));
+    status
+}
+
+fn list(debugger: &Debugger) -> String {
+    let mut list = String::new();
+    for (id, breakpoint) in debugger.breakpoints() {
+        writeln!(list, "{id}: break {}", format_breakpoint(breakpoint)).unwrap();
+    }
+    for (id, watchpoint) in debugger.watchpoints() {
+        writeln!(list, "{id}: watch {}", format_watchpoint(watchpoint)).unwrap();
+    }
+
+    if list.is_empty() {
+        String::from("No breakpoints or watchpoints")
+    } else {
+        list.trim_end().to_string()
+    }
+}
+
+fn trace(nes: &mut Nes, args: &[&str]) -> Result<String, String> {
+    let finish = |tracer: TraceLogger| {
+        tracer
+            .finish()
+            .map_err(|err| format!("Failed to write the trace: {err}"))
+    };
+
+    let (path, range, condition) = match args {
+        ["off"] => {
+            let tracer = nes.cpu_mut().stop_trace().ok_or("Not tracing")?;
+            return finish(tracer).map(|()| String::from("Stopped tracing"));
+        }
+        [path] => (path, None, None),
+        [path, "if", condition @ ..] => (path, None, Some(condition)),
+        [path, range] => (path, Some(range), None),
+        [path, range, "if", condition @ ..] => (path, Some(range), Some(condition)),
+        _ => {
+            return Err(String::from(
+                "Usage: trace <file> [<addr>-<addr>] [if <reg> <op> <value>] | trace off",
+            ));
+        }
+    };
+
+    let mut tracer =
+        TraceLogger::create(path).map_err(|err| format!("Failed to create {path}: {err}"))?;
+    tracer.set_pc_range(range.map(|range| parse_range(range)).transpose()?);
+    tracer.set_trigger(
+        condition
+            .map(|condition| parse_condition(&condition.join(" ")))
+            .transpose()?,
+    );
+
+    if let Some(previous) = nes.cpu_mut().stop_trace() {
+        finish(previous)?;
+    }
+    nes.cpu_mut().start_trace(tracer);
+    Ok(format!("Tracing to {path}"))
+}
+
+fn dump_memory(nes: &Nes, args: &[&str]) -> Result<String, String> {
+    let (space, args) = parse_space(args);
+    let (address, count) = match args {
+        [address] => (parse_number(address)?, MEMORY_ROW_BYTES),
+        [address, count] => (
+            parse_number(address)?,
+            count
+                .parse()
+                .map_err(|_| format!("Invalid count: {count}"))?,
+        ),
+        _ => return Err(String::from("Usage: x [cpu|ppu] <addr> [count]")),
+    };
+
+    let mut dump = String::new();
+    let addresses: Vec<u16> = (0..count)
+        .map(|offset| address.wrapping_add(offset as u16))
+        .collect();
+    for row in addresses.chunks(MEMORY_ROW_BYTES) {
+        write!(dump, "{:04X}:", row[0]).unwrap();
+        for &address in row {
+            let value = match space {
+                AddressSpace::Cpu => nes.cpu().bus.peek_u8(address),
+                AddressSpace::Ppu => nes.cpu().bus.ppu.peek_u8(address),
+            };
+            write!(dump, " {value:02X}").unwrap();
+        }
+        dump.push('\n');
+    }
+    Ok(dump.trim_end().to_string())
+}
+
+/*
+Writes through the bus like the CPU does, so RAM can be patched but writes to cartridge
+space reach the mapper's registers rather than the ROM.
+*/
+fn patch(nes: &mut Nes, args: &[&str]) -> Result<String, String> {
+    let [address, instruction @ ..] = args else {
+        return Err(String::from("Usage: asm <addr> <instruction>"));
+    };
+    if instruction.is_empty() {
+        return Err(String::from("Usage: asm <addr> <instruction>"));
+    }
+
+    let address = parse_number(address)?;
+    let bytes = assemble(&instruction.join(" "), address).map_err(|err| err.message)?;
+
+    let bus = &mut nes.cpu_mut().bus;
+    for (offset, byte) in bytes.into_iter().enumerate() {
+        bus.write_u8(address.wrapping_add(offset as u16), byte);
+    }
+    bus.take_oam_dma_request();
+    Ok(format_instruction(nes, address))
+}
+
+fn format_instruction(nes: &Nes, address: u16) -> String {
+    let bus = &nes.cpu().bus;
+    let bytes: Vec<u8> = (0..3)
+        .map(|offset| bus.peek_u8(address.wrapping_add(offset)))
+        .collect();
+    let instruction = disassemble_one(&bytes, address).unwrap();
+
+    let hex: Vec<String> = instruction
+        .bytes
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect();
+    format!("{address:04X}  {:<8}  {instruction}", hex.join(" "))
+}
+
+/// Set flags in upper case, clear ones in lower case.
+fn format_flags(p: u8) -> String {
+    "NV-BDIZC"
+        .chars()
+        .enumerate()
+        .map(|(i, flag)| match p & (0x80 >> i) != 0 {
+            true => flag,
+            false => flag.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+fn format_breakpoint(breakpoint: &Breakpoint) -> String {
+    let address = breakpoint.address.map(|address| format!("${address:04X}"));
+    let condition = breakpoint.condition.map(|condition| {
+        let register = match condition.register {
+            Register::A => "a",
+            Register::X => "x",
+            Register::Y => "y",
+            Register::P => "p",
+            Register::S => "s",
+            Register::PC => "pc",
+        };
+        let comparison = match condition.comparison {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        };
+        format!("if {register} {comparison} ${:X}", condition.value)
+    });
+
+    [address, condition]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn format_watchpoint(watchpoint: &Watchpoint) -> String {
+    let access = match (watchpoint.on_read, watchpoint.on_write) {
+        (true, true) => "rw",
+        (true, false) => "r",
+        _ => "w",
+    };
+    let (start, end) = (watchpoint.range.start(), watchpoint.range.end());
+    let range = match start == end {
+        true => format!("${start:04X}"),
+        false => format!("${start:04X}-${end:04X}"),
+    };
+    format!("{} {range} {access}", format_space(watchpoint.space))
+}
+
+fn format_space(space: AddressSpace) -> &'static str {
+    match space {
+        AddressSpace::Cpu => "cpu",
+        AddressSpace::Ppu => "ppu",
+    }
+}
+
+fn parse_breakpoint(args: &[&str]) -> Result<Breakpoint, String> {
+    let (address, condition) = match args {
+        [address] => (Some(parse_number(address)?), None),
+        [address, "if", condition @ ..] => (Some(parse_number(address)?), Some(condition)),
+        ["if", condition @ ..] => (None, Some(condition)),
+        _ => {
+            return Err(String::from(
+                "Usage: break <addr> [if <reg> <op> <value>] | break if <reg> <op> <value>",
+            ));
+        }
+    };
+
+    let condition = match condition {
+        Some(condition) => Some(parse_condition(&condition.join(" "))?),
+        None => None,
+    };
+
+    Ok(Breakpoint { address, condition })
+}
+
+/// Parses `<reg> <op> <value>`, such as `x >= $10`.
+pub fn parse_condition(condition: &str) -> Result<Condition, String> {
+    match condition.split_whitespace().collect::<Vec<_>>()[..] {
+        [register, comparison, value] => Ok(Condition {
+            register: parse_register(register)?,
+            comparison: parse_comparison(comparison)?,
+            value: parse_number(value)?,
+        }),
+        _ => Err(format!(
+            "Invalid condition: {condition}, expected <reg> <op> <value>"
+        )),
+    }
+}
+
+/// Parses a single address or an inclusive `<addr>-<addr>` range.
+pub fn parse_range(range: &str) -> Result<RangeInclusive<u16>, String> {
+    let range = match range.split_once('-') {
+        Some((start, end)) => parse_number(start)?..=parse_number(end)?,
+        None => {
+            let address = parse_number(range)?;
+            address..=address
+        }
+    };
+    if range.is_empty() {
+        return Err(String::from("The range ends before it starts"));
+    }
+    Ok(range)
+}
+
+fn parse_watchpoint(args: &[&str]) -> Result<Watchpoint, String> {
+    let (space, args) = parse_space(args);
+    let (range, access) = match args {
+        [range] => (range, "rw"),
+        [range, access] => (range, *access),
+        _ => {
+            return Err(String::from(
+                "Usage: watch [cpu|ppu] <addr>[-<addr>] [r|w|rw]",
+            ));
+        }
+    };
+
+    let range = parse_range(range)?;
+
+    let (on_read, on_write) = match access {
+        "r" => (true, false),
+        "w" => (false, true),
+        "rw" => (true, true),
+        _ => return Err(format!("Invalid access: {access}, expected r, w or rw")),
+    };
+
+    Ok(Watchpoint {
+        space,
+        range,
+        on_read,
+        on_write,
+    })
+}
+
+fn parse_space<'a, 'b>(args: &'a [&'b str]) -> (AddressSpace, &'a [&'b str]) {
+    match args {
+        ["cpu", rest @ ..] => (AddressSpace::Cpu, rest),
+        ["ppu", rest @ ..] => (AddressSpace::Ppu, rest),
+        _ => (AddressSpace::Cpu, args),
+    }
+}
+
+fn parse_register(register: &str) -> Result<Register, String> {
+    match register {
+        "a" => Ok(Register::A),
+        "x" => Ok(Register::X),
+        "y" => Ok(Register::Y),
+        "p" => Ok(Register::P),
+        "s" => Ok(Register::S),
+        "pc" => Ok(Register::PC),
+        _ => Err(format!("Unknown register: {register}")),
+    }
+}
+
+fn parse_comparison(comparison: &str) -> Result<Comparison, String> {
+    match comparison {
+        "==" => Ok(Comparison::Equal),
+        "!=" => Ok(Comparison::NotEqual),
+        "<" => Ok(Comparison::Less),
+        "<=" => Ok(Comparison::LessOrEqual),
+        ">" => Ok(Comparison::Greater),
+        ">=" => Ok(Comparison::GreaterOrEqual),
+        _ => Err(format!("Unknown comparison: {comparison}")),
+    }
+}
+
+fn parse_number(number: &str) -> Result<u16, String> {
+    let digits = number
+        .strip_prefix('$')
+        .or_else(|| number.strip_prefix("0x"))
+        .unwrap_or(number);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid number: {number}"))
+}
@@ -0,0 +1,43 @@
+use std::{error::Error, fmt};
+
+/// Why a ROM or its save file couldn't be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimplenessError {
+    NotAnINesFile,
+    InvalidRom,
+    TruncatedRom { expected: usize, actual: usize },
+    UnsupportedMapper(u16),
+    InvalidPrgRomSize(usize),
+    InvalidChrRomSize(usize),
+    SaveRamSizeMismatch { expected: usize, actual: usize },
+}
+
+impl fmt::Display for SimplenessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimplenessError::NotAnINesFile => write!(f, "not an iNES file"),
+            SimplenessError::InvalidRom => {
+                write!(f, "the header gives ROM sizes larger than any file can be")
+            }
+            SimplenessError::TruncatedRom { expected, actual } => write!(
+                f,
+                "ROM is truncated: the header asks for {expected} bytes, the file has {actual}"
+            ),
+            SimplenessError::UnsupportedMapper(mapper) => {
+                write!(f, "mapper {mapper} is not supported")
+            }
+            SimplenessError::InvalidPrgRomSize(size) => {
+                write!(f, "{size} bytes of PRG ROM don't fit the mapper")
+            }
+            SimplenessError::InvalidChrRomSize(size) => {
+                write!(f, "{size} bytes of CHR ROM don't fit the mapper")
+            }
+            SimplenessError::SaveRamSizeMismatch { expected, actual } => write!(
+                f,
+                "save file holds {actual} bytes, the cartridge has {expected} bytes of RAM"
+            ),
+        }
+    }
+}
+
+impl Error for SimplenessError {}
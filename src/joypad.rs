@@ -1,5 +1,7 @@
 use modular_bitfield::prelude::*;
 
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
 #[bitfield(bits = 8)]
 #[derive(Clone, Copy, Debug)]
 pub struct JoypadState {
@@ -13,12 +15,25 @@ pub struct JoypadState {
     pub right: B1,
 }
 
+/// The two controller ports on the front of the console.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Port {
+    One,
+    Two,
+}
+
 pub struct Joypad {
     pub state: JoypadState,
     shift_register_strobe: bool,
     current_read_number: u8,
 }
 
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Joypad {
     pub fn new() -> Self {
         Self {
@@ -50,3 +65,18 @@ impl Joypad {
         }
     }
 }
+
+impl Snapshot for Joypad {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.state.into_bytes()[0]);
+        state.write_bool(self.shift_register_strobe);
+        state.write_u8(self.current_read_number);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.state = JoypadState::from_bytes([state.read_u8()?]);
+        self.shift_register_strobe = state.read_bool()?;
+        self.current_read_number = state.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+/*
+The public API is the console itself: `Nes`, its controllers, what the cartridge header
+says and the errors it reports. The components behind it are only public with the `debug`
+feature, for the debugger, the binaries in this crate and the tests. It is on by default;
+embedders that want just the console turn default features off.
+*/
+#![cfg_attr(not(feature = "debug"), allow(dead_code, unused_imports))]
+
+macro_rules! internal_modules {
+    ($($module:ident),* $(,)?) => {
+        $(
+            #[cfg(feature = "debug")]
+            pub mod $module;
+            #[cfg(not(feature = "debug"))]
+            mod $module;
+        )*
+    };
+}
+
+internal_modules!(apu, audio, cpu, debugger, joypad, memory, ppu, rewind, savestate);
+
+mod error;
+mod nes;
+
+pub use error::SimplenessError;
+pub use joypad::{JoypadState, Port};
+pub use memory::rom_info::{ConsoleType, HeaderFormat, RomInfo, Timing};
+pub use nes::{Nes, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use ppu::{NametableArrangement, NametableSource};
+pub use savestate::SaveStateError;
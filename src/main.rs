@@ -1,14 +1,9 @@
-mod cpu;
-mod joypad;
-mod memory;
-mod ppu;
-
 use std::{
-    cell::RefCell,
-    rc::Rc,
+    io::Write,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
 };
 
-use cpu::olc6502::Olc6502;
 use pixels::{
     Pixels, PixelsBuilder, SurfaceTexture,
     wgpu::RequestAdapterOptions,
@@ -22,24 +17,202 @@ use winit::{
     window::WindowId,
 };
 
-use crate::memory::mapper::Rom;
+use simpleness::{
+    Nes, Port, SCREEN_HEIGHT as HEIGHT, SCREEN_WIDTH as WIDTH, Timing, apu,
+    audio::{self, AudioSink, NullSink, WavSink},
+    cpu::trace::TraceLogger,
+    debugger::{Debugger, repl},
+    rewind::RewindBuffer,
+};
+
+const REWIND_FRAMES: usize = 60 * 10;
+const REWIND_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+const BATTERY_FLUSH_FRAMES: u32 = 60 * 5;
+
+/*
+The debugger is driven from the terminal: stdin is read on its own thread, so the window
+keeps showing the paused frame while a command is being typed.
+*/
+struct DebugSession {
+    debugger: Debugger,
+    commands: Receiver<String>,
+}
+
+impl DebugSession {
+    fn start() -> Self {
+        let (sender, commands) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        print_prompt();
+        Self {
+            debugger: Debugger::new(),
+            commands,
+        }
+    }
+}
 
-const WIDTH: u32 = 256;
-const HEIGHT: u32 = 240;
+fn print_prompt() {
+    print!("{}", repl::PROMPT);
+    std::io::stdout().flush().unwrap();
+}
 
 struct NesApp<'a> {
     window_id: Option<WindowId>,
     pixels: Option<Pixels<'a>>,
-    cpu: Olc6502,
+    nes: Nes,
+    audio: Box<dyn AudioSink>,
+
+    rom_path: Option<PathBuf>,
+    save_slot: u8,
+    frames_until_flush: u32,
+
+    rewind: RewindBuffer,
+    rewinding: bool, // the rewind key is held
+
+    debug: Option<DebugSession>,
 }
 
 impl<'a> NesApp<'a> {
-    fn new(cpu: Olc6502) -> Self {
+    fn new(mut nes: Nes, audio: Box<dyn AudioSink>, debug: Option<DebugSession>) -> Self {
+        nes.set_sample_rate(audio.sample_rate());
         Self {
             window_id: None,
             pixels: None,
-            cpu,
+            nes,
+            audio,
+            rom_path: None,
+            save_slot: 0,
+            frames_until_flush: BATTERY_FLUSH_FRAMES,
+            rewind: RewindBuffer::new(REWIND_FRAMES, REWIND_MEMORY_BUDGET),
+            rewinding: false,
+            debug,
+        }
+    }
+
+    /// Save states live next to the ROM, one file per slot: `game.ss0` to `game.ss9`.
+    fn save_state_path(&self) -> Option<PathBuf> {
+        self.rom_path
+            .as_ref()
+            .map(|path| path.with_extension(format!("ss{}", self.save_slot)))
+    }
+
+    fn save_state(&mut self) {
+        let Some(path) = self.save_state_path() else {
+            return;
+        };
+
+        let result = self
+            .nes
+            .save_state()
+            .map_err(|err| err.to_string())
+            .and_then(|state| std::fs::write(&path, state).map_err(|err| err.to_string()));
+        match result {
+            Ok(()) => println!("Saved state to slot {}", self.save_slot),
+            Err(err) => eprintln!("Failed to save state to {}: {err}", path.display()),
+        }
+    }
+
+    fn load_state(&mut self) {
+        let Some(path) = self.save_state_path() else {
+            return;
+        };
+
+        let result = std::fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|state| self.nes.load_state(&state).map_err(|err| err.to_string()));
+        match result {
+            Ok(()) => println!("Loaded state from slot {}", self.save_slot),
+            Err(err) => eprintln!("Failed to load state from {}: {err}", path.display()),
+        }
+    }
+
+    /// Traces go next to the ROM as well, in `game.log`.
+    fn toggle_trace(&mut self) {
+        if let Some(tracer) = self.nes.cpu_mut().stop_trace() {
+            match tracer.finish() {
+                Ok(()) => println!("Stopped tracing"),
+                Err(err) => eprintln!("Failed to write the trace: {err}"),
+            }
+            return;
+        }
+
+        let Some(path) = self.rom_path.as_ref().map(|path| path.with_extension("log")) else {
+            return;
+        };
+        match TraceLogger::create(&path) {
+            Ok(tracer) => {
+                self.nes.cpu_mut().start_trace(tracer);
+                println!("Tracing to {}", path.display());
+            }
+            Err(err) => eprintln!("Failed to create {}: {err}", path.display()),
+        }
+    }
+
+    /// Battery backed RAM is kept next to the ROM too, in `game.sav`.
+    fn save_file_path(&self) -> Option<PathBuf> {
+        self.rom_path.as_ref().map(|path| path.with_extension("sav"))
+    }
+
+    /// Writes the battery backed RAM out if the game changed it since the last flush.
+    fn flush_battery_ram(&mut self) {
+        let Some(path) = self.save_file_path() else {
+            return;
+        };
+        if let Some(data) = self.nes.take_battery_ram_changes()
+            && let Err(err) = std::fs::write(&path, data)
+        {
+            eprintln!("Failed to write {}: {err}", path.display());
+        }
+    }
+
+    fn load_battery_ram(&mut self) {
+        let Some(path) = self.save_file_path() else {
+            return;
+        };
+        let result = match std::fs::read(&path) {
+            Ok(data) => self.nes.load_battery_ram(&data).map_err(|err| err.to_string()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.to_string()),
+        };
+        if let Err(err) = result {
+            eprintln!("Failed to load {}: {err}", path.display());
+        }
+    }
+
+    /// A ROM that fails to load leaves the current game running.
+    fn load_rom(&mut self, path: PathBuf) {
+        self.flush_battery_ram();
+
+        let result = std::fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|rom| self.nes.load_rom(rom).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            eprintln!("Failed to load {}: {err}", path.display());
+            return;
+        }
+
+        if let Some(info) = self.nes.rom_info() {
+            println!("Loaded {}: {info}", path.display());
+            if info.timing == Timing::Pal || info.timing == Timing::Dendy {
+                eprintln!("Only NTSC timing is emulated, the game may misbehave");
+            }
         }
+        self.rom_path = Some(path);
+        self.load_battery_ram();
+        self.frames_until_flush = BATTERY_FLUSH_FRAMES;
+        self.rewind.clear();
+    }
+
+    fn select_save_slot(&mut self, slot: u8) {
+        self.save_slot = slot;
+        println!("Selected save slot {slot}");
     }
 
     fn initialize_window(&mut self, event_loop: &ActiveEventLoop) {
@@ -65,16 +238,78 @@ impl<'a> NesApp<'a> {
     }
 
     fn tick_frame(&mut self) {
-        while !self.cpu.bus.ppu.frame_ready() {
-            self.cpu.tick();
+        match &mut self.debug {
+            Some(session) => {
+                if let Some(reason) = session.debugger.run_frame(&mut self.nes) {
+                    println!("\n{}", repl::describe_stop(&self.nes, reason));
+                    print_prompt();
+                }
+            }
+            None => self.nes.run_frame(),
+        }
+
+        let samples = self.nes.audio_samples();
+        self.audio.write(&samples);
+        self.nes
+            .set_audio_rate_adjust(audio::dynamic_rate_adjustment(self.audio.fill_level()));
+
+        // the picture goes along with each state, which doesn't carry it, as palette
+        // indices so a changing frame costs a quarter of its RGBA size
+        if let Ok(mut state) = self.nes.save_state() {
+            state.extend_from_slice(self.nes.frame_colors());
+            self.rewind.push(state);
+        }
+
+        self.frames_until_flush -= 1;
+        if self.frames_until_flush == 0 {
+            self.flush_battery_ram();
+            self.frames_until_flush = BATTERY_FLUSH_FRAMES;
+        }
+    }
+
+    /// Steps back one frame while the rewind key is held; stays on the oldest frame once
+    /// the history runs out.
+    fn rewind_frame(&mut self) {
+        let Some(entry) = self.rewind.pop() else {
+            return;
+        };
+
+        let (state, colors) = entry.split_at(entry.len() - self.nes.frame_colors().len());
+        match self.nes.load_state(state) {
+            Ok(()) => self.nes.restore_frame(colors),
+            Err(err) => {
+                eprintln!("Failed to rewind: {err}");
+                self.rewind.clear();
+            }
+        }
+    }
+
+    fn handle_debugger_commands(&mut self) {
+        let Some(session) = &mut self.debug else {
+            return;
+        };
+
+        while let Ok(line) = session.commands.try_recv() {
+            let output = repl::execute(&mut session.debugger, &mut self.nes, &line);
+            if !output.is_empty() {
+                println!("{output}");
+            }
+            if session.debugger.is_paused() {
+                print_prompt();
+            }
         }
     }
 
+    fn debugger_paused(&self) -> bool {
+        self.debug
+            .as_ref()
+            .is_some_and(|session| session.debugger.is_paused())
+    }
+
     fn redraw(&mut self) {
         if let Some(pixels) = &mut self.pixels {
             let frame = pixels.frame_mut();
-            let framebuffer = self.cpu.bus.ppu.get_pixel_buffer();
-            frame.copy_from_slice(framebuffer);
+            frame.copy_from_slice(self.nes.framebuffer());
 
             pixels.render().unwrap();
         }
@@ -97,24 +332,16 @@ impl<'a> ApplicationHandler<()> for NesApp<'a> {
     ) {
         if Some(window_id) == self.window_id {
             match event {
-                WindowEvent::CloseRequested => event_loop.exit(),
+                WindowEvent::CloseRequested => {
+                    self.flush_battery_ram();
+                    event_loop.exit();
+                }
                 WindowEvent::Resized(size) => {
                     if let Some(p) = &mut self.pixels {
                         p.resize_surface(size.width, size.height).unwrap();
                     }
                 }
-                WindowEvent::DroppedFile(path) => {
-                    if path.is_file() {
-                        if let Some(path_str) = path.to_str() {
-                            let file = std::fs::read(path_str).unwrap();
-                            let rom = Rom::parse(file);
-                            self.cpu.bus.set_mapper(Rc::new(RefCell::new(rom.mapper)));
-                            self.cpu.bus.ppu.set_nametable_arrangement(rom.flag6.get_nametable_mirroring_mode());
-                            self.cpu.reset();
-                            self.cpu.bus.ppu.reset();
-                        }
-                    }
-                }
+                WindowEvent::DroppedFile(path) if path.is_file() => self.load_rom(path),
                 _ => {}
             }
         }
@@ -126,45 +353,98 @@ impl<'a> ApplicationHandler<()> for NesApp<'a> {
         _device_id: winit::event::DeviceId,
         event: winit::event::DeviceEvent,
     ) {
-        match event {
-            DeviceEvent::Key(key_event) => {
-                if let PhysicalKey::Code(code) = key_event.physical_key {
-                    let joypad1 = &mut self.cpu.bus.joypad1;
-                    let state = if key_event.state.is_pressed() { 1 } else { 0 };
-                    match code {
-                        KeyCode::ArrowUp => joypad1.state.set_up(state),
-                        KeyCode::ArrowDown => joypad1.state.set_down(state),
-                        KeyCode::ArrowLeft => joypad1.state.set_left(state),
-                        KeyCode::ArrowRight => joypad1.state.set_right(state),
-                        KeyCode::KeyX => joypad1.state.set_a(state),
-                        KeyCode::KeyZ => joypad1.state.set_b(state),
-                        KeyCode::ShiftLeft | KeyCode::ShiftRight => joypad1.state.set_select(state),
-                        KeyCode::Enter => joypad1.state.set_start(state),
-                        _ => (),
-                    }
+        if let DeviceEvent::Key(key_event) = event
+            && let PhysicalKey::Code(code) = key_event.physical_key
+        {
+            if key_event.state.is_pressed() {
+                match code {
+                    KeyCode::F5 => self.save_state(),
+                    KeyCode::F7 => self.load_state(),
+                    KeyCode::F9 => self.toggle_trace(),
+                    KeyCode::Backspace => self.rewinding = true,
+                    KeyCode::Digit0 => self.select_save_slot(0),
+                    KeyCode::Digit1 => self.select_save_slot(1),
+                    KeyCode::Digit2 => self.select_save_slot(2),
+                    KeyCode::Digit3 => self.select_save_slot(3),
+                    KeyCode::Digit4 => self.select_save_slot(4),
+                    KeyCode::Digit5 => self.select_save_slot(5),
+                    KeyCode::Digit6 => self.select_save_slot(6),
+                    KeyCode::Digit7 => self.select_save_slot(7),
+                    KeyCode::Digit8 => self.select_save_slot(8),
+                    KeyCode::Digit9 => self.select_save_slot(9),
+                    _ => (),
                 }
             }
-            _ => (),
+
+            if code == KeyCode::Backspace && !key_event.state.is_pressed() {
+                self.rewinding = false;
+            }
+
+            let mut buttons = self.nes.buttons(Port::One);
+            let state = if key_event.state.is_pressed() { 1 } else { 0 };
+            match code {
+                KeyCode::ArrowUp => buttons.set_up(state),
+                KeyCode::ArrowDown => buttons.set_down(state),
+                KeyCode::ArrowLeft => buttons.set_left(state),
+                KeyCode::ArrowRight => buttons.set_right(state),
+                KeyCode::KeyX => buttons.set_a(state),
+                KeyCode::KeyZ => buttons.set_b(state),
+                KeyCode::ShiftLeft | KeyCode::ShiftRight => buttons.set_select(state),
+                KeyCode::Enter => buttons.set_start(state),
+                _ => (),
+            }
+            self.nes.set_buttons(Port::One, buttons);
         }
     }
 
     fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
-        if self.window_id.is_some() && self.cpu.bus.mapper_inserted() {
-            self.tick_frame();
+        if self.window_id.is_some() && self.nes.rom_loaded() {
+            self.handle_debugger_commands();
+
+            if self.debugger_paused() {
+                // keep showing the frame the debugger stopped on
+            } else if self.rewinding {
+                self.rewind_frame();
+            } else {
+                self.tick_frame();
+            }
             self.redraw();
         }
     }
 }
 
-fn main() {
-    let bus = memory::bus::Bus::new();
+/*
+`--wav <file>` records the audio to a file instead of playing it
+`--debug` starts paused, with the debugger reading commands from the terminal
+*/
+fn open_audio_sink() -> Box<dyn AudioSink> {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--wav")
+        && let Some(path) = args.get(index + 1)
+    {
+        match WavSink::create(path, apu::DEFAULT_SAMPLE_RATE) {
+            Ok(sink) => return Box::new(sink),
+            Err(err) => eprintln!("Failed to create {path}: {err}"),
+        }
+    }
 
-    let cpu = Olc6502::new(bus);
+    #[cfg(feature = "audio-device")]
+    match audio::DeviceSink::open() {
+        Ok(sink) => return Box::new(sink),
+        Err(err) => eprintln!("Failed to open audio device, running without sound: {err}"),
+    }
 
+    Box::new(NullSink::new(apu::DEFAULT_SAMPLE_RATE))
+}
+
+fn main() {
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let mut app = NesApp::new(cpu);
+    let debug = std::env::args()
+        .any(|arg| arg == "--debug")
+        .then(DebugSession::start);
+    let mut app = NesApp::new(Nes::new(), open_audio_sink(), debug);
 
     event_loop.run_app(&mut app).unwrap();
 }
@@ -1,55 +1,143 @@
+use crate::apu::{APU_FRAME_COUNTER, APU_STATUS, Apu, DEFAULT_SAMPLE_RATE};
+use crate::cpu::bus::{AccessKind, CpuBus};
+use crate::debugger::MemoryWatch;
 use crate::joypad::Joypad;
 use crate::memory::mapper::SharedMapper;
 use crate::ppu::{OAMDMA, Ppu};
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+use bitflags::bitflags;
 const INTERNAL_RAM_SIZE: usize = 0x800;
 
 const JOY1: u16 = 0x4016;
 const JOY2: u16 = 0x4017;
 
+// CPU cycles the DMC steals from the CPU for each sample fetch
+const DMC_FETCH_STALL_CYCLES: u64 = 4;
+
+bitflags! {
+    /// Devices that can hold the shared, level triggered IRQ line.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct IrqSource: u8 {
+        const FRAME_COUNTER = 1 << 0;
+        const DMC = 1 << 1;
+        const MAPPER = 1 << 2;
+        const EXTERNAL = 1 << 3; // anything outside the console, e.g. the expansion port
+    }
+}
+
 pub struct Bus {
     pub ppu: Ppu,
+    pub apu: Apu,
     pub joypad1: Joypad,
     pub joypad2: Joypad,
+    pub watch: MemoryWatch, // CPU accesses a debugger is interested in
     internal_ram: [u8; INTERNAL_RAM_SIZE],
     mapper: Option<SharedMapper>,
+    asserted_irq_sources: IrqSource, // sources driven through `set_irq`
+    oam_dma_request: Option<u8>,     // page written to OAMDMA, copied by the CPU
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-// For now we only support nrom (no mapper)
 impl Bus {
+    /// A bus without a cartridge; the mapper built by `Rom::parse` goes in with `set_mapper`.
     pub fn new() -> Self {
         Self {
             ppu: Ppu::new(crate::ppu::NametableArrangement::Vertical),
+            apu: Apu::new(DEFAULT_SAMPLE_RATE),
             joypad1: Joypad::new(),
             joypad2: Joypad::new(),
+            watch: MemoryWatch::default(),
             internal_ram: [0xff; INTERNAL_RAM_SIZE],
             mapper: None,
+            asserted_irq_sources: IrqSource::empty(),
+            oam_dma_request: None,
         }
     }
 
     pub fn set_mapper(&mut self, mapper: SharedMapper) {
+        self.ppu.set_mapper(mapper.clone());
         self.mapper = Some(mapper);
-        self.ppu.set_mapper(self.mapper.as_ref().unwrap().clone());
     }
 
     pub fn mapper_inserted(&self) -> bool {
         self.mapper.is_some()
     }
 
-    pub fn read_u8(&mut self, addr: u16) -> u8 {
-        if self.mapper.is_none() {
-            panic!("Attempted to read from bus before loading ROM");
+    pub fn mapper(&self) -> Option<&SharedMapper> {
+        self.mapper.as_ref()
+    }
+
+    /// Every source currently holding the IRQ line. The console's own devices are asked
+    /// for their level; anything else asserts and releases it through `set_irq`.
+    pub fn irq_sources(&self) -> IrqSource {
+        let mut sources = self.asserted_irq_sources;
+        sources.set(IrqSource::FRAME_COUNTER, self.apu.frame_irq_pending());
+        sources.set(IrqSource::DMC, self.apu.dmc_irq_pending());
+        sources.set(
+            IrqSource::MAPPER,
+            self.mapper
+                .as_ref()
+                .is_some_and(|mapper| mapper.borrow().irq_pending()),
+        );
+        sources
+    }
+
+    pub fn set_irq(&mut self, source: IrqSource, asserted: bool) {
+        self.asserted_irq_sources.set(source, asserted);
+    }
+
+    /// Level of the IRQ line shared by the devices on the bus.
+    pub fn irq_pending(&self) -> bool {
+        !self.irq_sources().is_empty()
+    }
+
+    /// Advances the APU by one CPU cycle, and returns the cycles the CPU is stalled for
+    /// while the DMC fetches a sample.
+    pub fn tick_apu(&mut self) -> u64 {
+        self.apu.tick();
+
+        if let Some(addr) = self.apu.dmc_sample_request() {
+            let sample = self.read_u8(addr);
+            self.apu.dmc_load_sample(sample);
+            DMC_FETCH_STALL_CYCLES
+        } else {
+            0
         }
-        let mapper = self.mapper.as_ref().unwrap().borrow();
+    }
 
+    /// Without a cartridge the cartridge space reads as 0 and ignores writes.
+    pub fn read_u8(&mut self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x1FFF => self.internal_ram[addr as usize & (INTERNAL_RAM_SIZE - 1)],
             0x2000..=0x3FFF => {
                 let ppu_register_addr = 0x2000 + (addr % 8);
                 self.ppu.read_register(ppu_register_addr)
             }
+            APU_STATUS => self.apu.read_status(),
             JOY1 => self.joypad1.read_status(),
             JOY2 => self.joypad2.read_status(),
-            _ => mapper.cpu_map_read(addr),
+            _ => self
+                .mapper
+                .as_ref()
+                .map_or(0, |mapper| mapper.borrow().cpu_map_read(addr)),
+        }
+    }
+
+    /// Reads memory without the side effects of a CPU read, for debugging tools. I/O
+    /// registers change state when read, so they show up as 0.
+    pub fn peek_u8(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.internal_ram[addr as usize & (INTERNAL_RAM_SIZE - 1)],
+            0x2000..=0x401F => 0,
+            _ => self
+                .mapper
+                .as_ref()
+                .map_or(0, |mapper| mapper.borrow().cpu_map_read(addr)),
         }
     }
 
@@ -64,12 +152,13 @@ impl Bus {
         u16::from_le_bytes([lo, hi])
     }
 
-    pub fn write_u8(&mut self, addr: u16, data: u8) -> usize {
-        let mut extra_cpu_cycles = 0;
-        if self.mapper.is_none() {
-            panic!("Attempted to write to bus before loading ROM");
-        }
+    /// The page of the last OAMDMA write, if the CPU hasn't started that transfer yet.
+    /// The copy is driven by the CPU since it halts for it and does the reads and writes.
+    pub fn take_oam_dma_request(&mut self) -> Option<u8> {
+        self.oam_dma_request.take()
+    }
 
+    pub fn write_u8(&mut self, addr: u16, data: u8) {
         match addr {
             0x0000..=0x1FFF => {
                 self.internal_ram[addr as usize & (INTERNAL_RAM_SIZE - 1)] = data;
@@ -84,30 +173,88 @@ impl Bus {
                 self.joypad2.set_shift_register_strobe(data & 1 != 0);
             }
 
-            OAMDMA => {
-                // Perform OAM DMA transfer
-                let base_addr = (data as u16) << 8;
-                extra_cpu_cycles += 513; // 513 or 514 cycles depending on CPU cycle alignment
-                for i in 0..0x100 {
-                    let byte = self.read_u8(base_addr.wrapping_add(i));
-
-                    for _ in 0..3 {
-                        self.ppu.tick();
-                    }
-
-                    self.ppu.write_register(0x2004, byte); // OAMDATA register
+            0x4000..=0x4013 | APU_STATUS | APU_FRAME_COUNTER => {
+                self.apu.write_register(addr, data);
+            }
 
-                    for _ in 0..3 {
-                        self.ppu.tick();
-                    }
-                }
+            OAMDMA => {
+                self.oam_dma_request = Some(data);
             }
 
             _ => {
-                let mut mapper = self.mapper.as_ref().unwrap().borrow_mut();
-                mapper.cpu_map_write(addr, data);
+                if let Some(mapper) = &self.mapper {
+                    mapper.borrow_mut().cpu_map_write(addr, data);
+                }
             }
         }
-        extra_cpu_cycles
+    }
+}
+
+impl CpuBus for Bus {
+    fn read_u8(&mut self, addr: u16) -> u8 {
+        Bus::read_u8(self, addr)
+    }
+
+    fn write_u8(&mut self, addr: u16, data: u8) {
+        Bus::write_u8(self, addr, data)
+    }
+
+    fn peek_u8(&self, addr: u16) -> u8 {
+        Bus::peek_u8(self, addr)
+    }
+
+    fn tick(&mut self) -> u64 {
+        let stall_cycles = self.tick_apu();
+        for _ in 0..3 {
+            self.ppu.tick();
+        }
+        stall_cycles
+    }
+
+    fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.ppu.should_nmi)
+    }
+
+    fn irq_pending(&self) -> bool {
+        Bus::irq_pending(self)
+    }
+
+    fn take_oam_dma_request(&mut self) -> Option<u8> {
+        Bus::take_oam_dma_request(self)
+    }
+
+    fn record_access(&mut self, kind: AccessKind, addr: u16, data: u8) {
+        self.watch.record(kind, addr, data);
+    }
+
+    fn ppu_position(&self) -> (u16, u16) {
+        (self.ppu.scanline(), self.ppu.dot())
+    }
+}
+
+impl Snapshot for Bus {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.internal_ram);
+        state.write_u8(self.asserted_irq_sources.bits());
+        self.ppu.save_state(state);
+        self.apu.save_state(state);
+        self.joypad1.save_state(state);
+        self.joypad2.save_state(state);
+        if let Some(mapper) = &self.mapper {
+            mapper.borrow().save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        state.read_bytes(&mut self.internal_ram)?;
+        self.asserted_irq_sources = IrqSource::from_bits_truncate(state.read_u8()?);
+        self.ppu.load_state(state)?;
+        self.apu.load_state(state)?;
+        self.joypad1.load_state(state)?;
+        self.joypad2.load_state(state)?;
+        if let Some(mapper) = &self.mapper {
+            mapper.borrow_mut().load_state(state)?;
+        }
+        Ok(())
     }
 }
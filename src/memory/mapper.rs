@@ -1,7 +1,21 @@
-use crate::{memory::mapper0::Mapper0, ppu::NametableArrangement};
-use byteorder::ReadBytesExt;
-use std::{cell::RefCell, io::Read, rc::Rc};
-use modular_bitfield::prelude::*;
+use crate::{
+    error::SimplenessError,
+    memory::{
+        mapper0::Mapper0,
+        mapper1::Mapper1,
+        mapper2::Mapper2,
+        mapper3::Mapper3,
+        mapper4::Mapper4,
+        mapper7::Mapper7,
+        mapper34::Mapper34,
+        mapper66::Mapper66,
+        rom_info::{HEADER_SIZE, RomInfo, TRAINER_ADDRESS},
+        wram::Wram,
+    },
+    ppu::NametableArrangement,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+use std::{cell::RefCell, rc::Rc};
 
 pub type SharedMapper = Rc<RefCell<Box<dyn crate::memory::mapper::Mapper>>>;
 
@@ -10,71 +24,111 @@ pub trait Mapper {
     fn cpu_map_write(&mut self, addr: u16, data: u8);
     fn ppu_map_read(&self, addr: u16) -> u8;
     fn ppu_map_write(&mut self, addr: u16, data: u8);
+
+    /// Boards that switch mirroring at runtime report the arrangement they currently select,
+    /// overriding the one from the header.
+    fn nametable_arrangement(&self) -> Option<NametableArrangement> {
+        None
+    }
+
+    /// Called with every address the PPU puts on the cartridge bus, along with the PPU dot
+    /// count, for boards that snoop it (e.g. MMC3 counting A12 rises).
+    fn ppu_bus_activity(&mut self, _addr: u16, _ppu_cycle: u64) {}
+
+    /// The board's work RAM at $6000-$7FFF, if it has any.
+    fn wram(&self) -> Option<&Wram> {
+        None
+    }
+
+    fn wram_mut(&mut self) -> Option<&mut Wram> {
+        None
+    }
+
+    /// Level of the cartridge's IRQ output.
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    /// Writes the board's registers and RAM into a save state. ROM contents are left out,
+    /// they come from the ROM file the state is loaded against.
+    fn save_state(&self, _state: &mut StateWriter) {}
+
+    fn load_state(&mut self, _state: &mut StateReader) -> Result<(), SaveStateError> {
+        Ok(())
+    }
 }
 
-#[bitfield(bits=8)]
-#[derive(Clone, Copy)]
-#[allow(dead_code)]
-pub struct INesFlag6 {
-    pub nametable_arrangement: B1, // 0: vertical arrangement 1: horizontal arrangement
-    pub persistant_ram: B1,
-    pub has_trainer: B1,
-    pub alternative_nametable_layout: B1,
-    pub low_mapper_nybble: B4,
+const MIN_CHR_RAM_SIZE: usize = 0x2000;
+
+/// The board's CHR memory: its CHR ROM, or the CHR RAM the header asks for when it has
+/// none, at least 8K. The flag tells whether it's RAM.
+pub fn chr_memory(chr_rom: Vec<u8>, info: &RomInfo) -> (Vec<u8>, bool) {
+    if chr_rom.is_empty() {
+        let size = (info.chr_ram_size + info.chr_nvram_size).max(MIN_CHR_RAM_SIZE);
+        (vec![0; size], true)
+    } else {
+        (chr_rom, false)
+    }
 }
 
-impl INesFlag6 {
-    pub fn get_nametable_mirroring_mode(&self) -> NametableArrangement {
-        if self.nametable_arrangement() == 0 {
-            NametableArrangement::Vertical
-        } else {
-            NametableArrangement::Horizontal
-        }
+/*
+Discrete logic boards latch the data bus on writes to $8000-$FFFF while the PRG ROM drives
+it with the byte at that address too. With these AND-type bus conflicts the latch only
+gets the bits both agree on, which is why games write a value equal to the ROM byte. For
+mappers 2, 3 and 7, NES 2.0 submapper 1 means a board without them and 2 one with them;
+otherwise it's up to what the board usually does.
+*/
+pub fn has_bus_conflicts(info: &RomInfo, default: bool) -> bool {
+    match info.submapper {
+        1 => false,
+        2 => true,
+        _ => default,
     }
 }
 
 pub struct Rom {
     pub mapper: Box<dyn Mapper>,
-    pub flag6: INesFlag6,
+    pub info: RomInfo,
 }
 
 impl Rom {
-    pub fn new(mapper: Box<dyn Mapper>, flag6: INesFlag6) -> Self {
-        Self { mapper, flag6 }
+    pub fn new(mapper: Box<dyn Mapper>, info: RomInfo) -> Self {
+        Self { mapper, info }
     }
 
-    pub fn parse(rom_content: Vec<u8>) -> Self {
-        let mut reader = std::io::BufReader::new(std::io::Cursor::new(rom_content));
-        let mut magic_buf = [0u8; 4];
-        reader.read_exact(&mut magic_buf).unwrap();
-        if &magic_buf != b"NES\x1A" {
-            panic!("Invalid ROM file: missing NES header");
+    pub fn parse(rom_content: &[u8]) -> Result<Self, SimplenessError> {
+        let info = rom_content
+            .first_chunk::<HEADER_SIZE>()
+            .and_then(RomInfo::parse)
+            .ok_or(SimplenessError::NotAnINesFile)?;
+        let expected = info.file_size().ok_or(SimplenessError::InvalidRom)?;
+        if rom_content.len() < expected {
+            return Err(SimplenessError::TruncatedRom {
+                expected,
+                actual: rom_content.len(),
+            });
         }
-        let prg_rom_size = reader.read_u8().unwrap() as usize * 0x4000;
-        let chr_rom_size = reader.read_u8().unwrap() as usize * 0x2000;
 
-        let flag6 = reader.read_u8().unwrap();
-        let flag7 = reader.read_u8().unwrap();
+        let prg_rom = rom_content[info.prg_rom_range()].to_vec();
+        let chr_rom = rom_content[info.chr_rom_range()].to_vec();
 
-        let mut garbage_buf = [0u8; 8];
-        reader.read_exact(&mut garbage_buf).unwrap();
+        let mut mapper: Box<dyn Mapper> = match info.mapper {
+            0 => Box::new(Mapper0::new(prg_rom, chr_rom, &info)?),
+            1 => Box::new(Mapper1::new(prg_rom, chr_rom, &info)?),
+            2 => Box::new(Mapper2::new(prg_rom, chr_rom, &info)?),
+            3 => Box::new(Mapper3::new(prg_rom, chr_rom, &info)?),
+            4 => Box::new(Mapper4::new(prg_rom, chr_rom, &info)?),
+            7 => Box::new(Mapper7::new(prg_rom, chr_rom, &info)?),
+            34 => Box::new(Mapper34::new(prg_rom, chr_rom, &info)?),
+            66 => Box::new(Mapper66::new(prg_rom, chr_rom, &info)?),
+            mapper_number => return Err(SimplenessError::UnsupportedMapper(mapper_number)),
+        };
 
-        let mut prg_rom = vec![0u8; prg_rom_size];
-        reader.read_exact(&mut prg_rom).unwrap();
-
-        let mut chr_rom = vec![0u8; chr_rom_size];
-        reader.read_exact(&mut chr_rom).unwrap();
-
-        let mapper_number = (flag7 & 0xF0) | (flag6 >> 4);
-
-        match mapper_number {
-            0 => {
-                let mapper = Box::new(Mapper0::new(prg_rom, chr_rom));
-                Self::new(mapper, INesFlag6::from_bytes([flag6]))
-            }
-            _ => {
-                panic!("Unsupported mapper number: {}", mapper_number);
-            }
+        // the trainer is loaded into PRG RAM before the game starts
+        for (addr, &data) in (TRAINER_ADDRESS..).zip(&rom_content[info.trainer_range()]) {
+            mapper.cpu_map_write(addr, data);
         }
+
+        Ok(Self::new(mapper, info))
     }
-}
\ No newline at end of file
+}
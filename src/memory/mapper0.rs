@@ -1,67 +1,79 @@
-use crate::memory::mapper::Mapper;
+use crate::{
+    error::SimplenessError,
+    memory::{
+        mapper::{Mapper, chr_memory},
+        rom_info::RomInfo,
+        wram::Wram,
+    },
+    savestate::{SaveStateError, Snapshot, StateReader, StateWriter},
+};
 
+/*
+NROM
+
+Plain NROM boards have no PRG RAM, but some variants do (Family BASIC), and test ROMs
+such as blargg's report their results at $6000, so it is always present here.
+*/
 pub struct Mapper0 {
     prg_rom: Vec<u8>,
     should_mirror_prg_rom_page: bool,
     chr_rom: Vec<u8>,
+    wram: Wram,
+    chr_ram: bool,
 }
 
 impl Mapper0 {
-    pub fn new(prg_rom: Vec<u8>, mut chr_rom: Vec<u8>) -> Self {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        info: &RomInfo,
+    ) -> Result<Self, SimplenessError> {
         let should_mirror_prg_rom_page = prg_rom.len() == 0x4000;
         if prg_rom.len() != 0x4000 && prg_rom.len() != 0x8000 {
-            panic!("Invalid prg rom size");
-        }
-
-        if chr_rom.len() == 0 {
-            chr_rom = vec![0; 0x2000];
+            return Err(SimplenessError::InvalidPrgRomSize(prg_rom.len()));
         }
 
-        if chr_rom.len() != 0x2000 && chr_rom.len() != 0 {
-            panic!("Invalid chr rom size");
+        let (chr_rom, chr_ram) = chr_memory(chr_rom, info);
+        if !chr_ram && chr_rom.len() != 0x2000 {
+            return Err(SimplenessError::InvalidChrRomSize(chr_rom.len()));
         }
 
-        Self {
+        Ok(Self {
             prg_rom,
             should_mirror_prg_rom_page,
             chr_rom,
-        }
+            wram: Wram::from_info(info),
+            chr_ram,
+        })
     }
 }
 
 impl Mapper for Mapper0 {
     fn cpu_map_read(&self, addr: u16) -> u8 {
         match addr {
+            0x6000..=0x7FFF => self.wram.read((addr - 0x6000) as usize),
             0x8000..=0xFFFF => {
                 let prg_rom_addr = (addr - 0x8000) as usize;
-                if prg_rom_addr < self.prg_rom.len() && !self.should_mirror_prg_rom_page {
-                    self.prg_rom[prg_rom_addr]
-                } else if prg_rom_addr & 0x3fff < self.prg_rom.len()
-                    && self.should_mirror_prg_rom_page
-                {
+                if self.should_mirror_prg_rom_page {
                     self.prg_rom[prg_rom_addr & 0x3fff]
                 } else {
-                    panic!("PRG ROM read out of bounds: {:04X}", addr);
+                    self.prg_rom[prg_rom_addr]
                 }
             }
             _ => 0,
         }
     }
 
-    fn cpu_map_write(&mut self, _addr: u16, _data: u8) {
+    fn cpu_map_write(&mut self, addr: u16, data: u8) {
         // NROM does not support writes to PRG ROM
+        if let 0x6000..=0x7FFF = addr {
+            self.wram.write((addr - 0x6000) as usize, data);
+        }
     }
 
     fn ppu_map_read(&self, addr: u16) -> u8 {
         match addr {
-            0x0000..=0x1FFF => {
-                let chr_rom_addr = addr as usize;
-                if chr_rom_addr < self.chr_rom.len() {
-                    self.chr_rom[chr_rom_addr]
-                } else {
-                    panic!("CHR ROM read out of bounds: {:04X}", addr);
-                }
-            }
+            0x0000..=0x1FFF => self.chr_rom[addr as usize],
             _ => 0,
         }
     }
@@ -71,4 +83,27 @@ impl Mapper for Mapper0 {
             self.chr_rom[addr as usize] = data;
         }
     }
+
+    fn wram(&self) -> Option<&Wram> {
+        Some(&self.wram)
+    }
+
+    fn wram_mut(&mut self) -> Option<&mut Wram> {
+        Some(&mut self.wram)
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.wram.save_state(state);
+        if self.chr_ram {
+            state.write_bytes(&self.chr_rom);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.wram.load_state(state)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr_rom)?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,239 @@
+use crate::{
+    error::SimplenessError,
+    memory::{
+        mapper::{Mapper, chr_memory},
+        rom_info::RomInfo,
+        wram::Wram,
+    },
+    ppu::NametableArrangement,
+    savestate::{SaveStateError, Snapshot, StateReader, StateWriter},
+};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+
+/*
+MMC1 (SxROM)
+
+Registers are loaded serially: every write to $8000-$FFFF shifts bit 0 into a 5 bit
+shift register, and the fifth write copies it into the register selected by bits 13-14
+of the address. A write with bit 7 set resets the shift register and locks the last
+PRG bank at $C000.
+*/
+pub struct Mapper1 {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    wram: Wram,
+    chr_ram: bool,
+
+    shift_register: u8,
+    shift_count: u8,
+
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+}
+
+impl Mapper1 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        info: &RomInfo,
+    ) -> Result<Self, SimplenessError> {
+        if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(SimplenessError::InvalidPrgRomSize(prg_rom.len()));
+        }
+
+        let (chr_rom, chr_ram) = chr_memory(chr_rom, info);
+
+        if !chr_rom.len().is_multiple_of(CHR_BANK_SIZE) {
+            return Err(SimplenessError::InvalidChrRomSize(chr_rom.len()));
+        }
+
+        Ok(Self {
+            prg_rom,
+            chr_rom,
+            wram: Wram::from_info(info),
+            chr_ram,
+            shift_register: 0,
+            shift_count: 0,
+            control: 0x0c, // power on in PRG mode 3, last bank fixed at $C000
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+        })
+    }
+
+    fn prg_bank_count(&self) -> usize {
+        self.prg_rom.len() / PRG_BANK_SIZE
+    }
+
+    /// SUROM and SXROM have 512K of PRG ROM; the extra address line comes from the CHR bank register.
+    fn prg_outer_bank(&self) -> usize {
+        if self.prg_rom.len() > 0x40000 {
+            (self.chr_bank_0 as usize & 0x10) & (self.prg_bank_count() - 1)
+        } else {
+            0
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0x10 == 0
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        let bank = (self.prg_bank & 0x0f) as usize;
+        let last_bank = (self.prg_bank_count() - 1) & 0x0f;
+
+        let selected_bank = match (self.control >> 2) & 0b11 {
+            // 32K mode: ignore the low bit of the bank number
+            0 | 1 => (bank & !1) | ((addr as usize >> 14) & 1),
+            // first bank fixed at $8000, switch the bank at $C000
+            2 => {
+                if addr < 0xc000 {
+                    0
+                } else {
+                    bank
+                }
+            }
+            // switch the bank at $8000, last bank fixed at $C000
+            3 => {
+                if addr < 0xc000 {
+                    bank
+                } else {
+                    last_bank
+                }
+            }
+            _ => unreachable!(),
+        };
+
+        let selected_bank = (self.prg_outer_bank() | selected_bank) % self.prg_bank_count();
+        selected_bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let chr_bank_count = self.chr_rom.len() / CHR_BANK_SIZE;
+
+        let selected_bank = if self.control & 0x10 == 0 {
+            // 8K mode: ignore the low bit of the bank number
+            (self.chr_bank_0 as usize & !1) | ((addr as usize >> 12) & 1)
+        } else if addr < 0x1000 {
+            self.chr_bank_0 as usize
+        } else {
+            self.chr_bank_1 as usize
+        };
+
+        (selected_bank % chr_bank_count) * CHR_BANK_SIZE + (addr as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.control = data,
+            0xA000..=0xBFFF => self.chr_bank_0 = data,
+            0xC000..=0xDFFF => self.chr_bank_1 = data,
+            0xE000..=0xFFFF => self.prg_bank = data,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Mapper for Mapper1 {
+    fn cpu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.wram.read((addr - 0x6000) as usize),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_rom_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                self.wram.write((addr - 0x6000) as usize, data);
+            }
+            0x8000..=0xFFFF => {
+                if data & 0x80 != 0 {
+                    self.shift_register = 0;
+                    self.shift_count = 0;
+                    self.control |= 0x0c;
+                    return;
+                }
+
+                self.shift_register |= (data & 1) << self.shift_count;
+                self.shift_count += 1;
+
+                if self.shift_count == 5 {
+                    self.write_register(addr, self.shift_register);
+                    self.shift_register = 0;
+                    self.shift_count = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.chr_rom[self.chr_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x0000..=0x1FFF = addr {
+            let offset = self.chr_offset(addr);
+            self.chr_rom[offset] = data;
+        }
+    }
+
+    fn nametable_arrangement(&self) -> Option<NametableArrangement> {
+        // note that "vertical arrangement" is what the wiki calls horizontal mirroring
+        Some(match self.control & 0b11 {
+            0 => NametableArrangement::SingleScreenA,
+            1 => NametableArrangement::SingleScreenB,
+            2 => NametableArrangement::Horizontal,
+            3 => NametableArrangement::Vertical,
+            _ => unreachable!(),
+        })
+    }
+
+    fn wram(&self) -> Option<&Wram> {
+        Some(&self.wram)
+    }
+
+    fn wram_mut(&mut self) -> Option<&mut Wram> {
+        Some(&mut self.wram)
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.wram.save_state(state);
+        if self.chr_ram {
+            state.write_bytes(&self.chr_rom);
+        }
+        state.write_u8(self.shift_register);
+        state.write_u8(self.shift_count);
+        state.write_u8(self.control);
+        state.write_u8(self.chr_bank_0);
+        state.write_u8(self.chr_bank_1);
+        state.write_u8(self.prg_bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.wram.load_state(state)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr_rom)?;
+        }
+        self.shift_register = state.read_u8()?;
+        self.shift_count = state.read_u8()?;
+        self.control = state.read_u8()?;
+        self.chr_bank_0 = state.read_u8()?;
+        self.chr_bank_1 = state.read_u8()?;
+        self.prg_bank = state.read_u8()?;
+
+        if self.shift_count >= 5 {
+            return Err(SaveStateError::Corrupt);
+        }
+        Ok(())
+    }
+}
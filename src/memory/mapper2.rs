@@ -0,0 +1,109 @@
+use crate::{
+    error::SimplenessError,
+    memory::{
+        mapper::{Mapper, chr_memory, has_bus_conflicts},
+        rom_info::RomInfo,
+    },
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/*
+UxROM
+
+A 16K PRG bank selected by any write to $8000-$FFFF shows at $8000, and the last bank is
+fixed at $C000. CHR is 8K, almost always RAM. Most UNROM and UOROM boards have bus
+conflicts.
+*/
+pub struct Mapper2 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    bus_conflicts: bool,
+
+    prg_bank: u8,
+}
+
+impl Mapper2 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        info: &RomInfo,
+    ) -> Result<Self, SimplenessError> {
+        if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(SimplenessError::InvalidPrgRomSize(prg_rom.len()));
+        }
+
+        let (chr, chr_ram) = chr_memory(chr_rom, info);
+        if !chr_ram && chr.len() != 0x2000 {
+            return Err(SimplenessError::InvalidChrRomSize(chr.len()));
+        }
+
+        Ok(Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            bus_conflicts: has_bus_conflicts(info, true),
+            prg_bank: 0,
+        })
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match addr {
+            0x8000..=0xBFFF => self.prg_bank as usize % bank_count,
+            _ => bank_count - 1,
+        };
+        bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))
+    }
+}
+
+impl Mapper for Mapper2 {
+    fn cpu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => self.prg_rom[self.prg_rom_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x8000..=0xFFFF = addr {
+            self.prg_bank = if self.bus_conflicts {
+                data & self.cpu_map_read(addr)
+            } else {
+                data
+            };
+        }
+    }
+
+    fn ppu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.chr[addr as usize],
+            _ => 0,
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x0000..=0x1FFF = addr
+            && self.chr_ram
+        {
+            self.chr[addr as usize] = data;
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.prg_bank);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.prg_bank = state.read_u8()?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        Ok(())
+    }
+}
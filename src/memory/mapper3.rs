@@ -0,0 +1,106 @@
+use crate::{
+    error::SimplenessError,
+    memory::{
+        mapper::{Mapper, chr_memory, has_bus_conflicts},
+        rom_info::RomInfo,
+    },
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/*
+CNROM
+
+NROM's PRG layout, with an 8K CHR bank selected by any write to $8000-$FFFF. The boards
+have bus conflicts.
+*/
+pub struct Mapper3 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    bus_conflicts: bool,
+
+    chr_bank: u8,
+}
+
+impl Mapper3 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        info: &RomInfo,
+    ) -> Result<Self, SimplenessError> {
+        if prg_rom.len() != 0x4000 && prg_rom.len() != 0x8000 {
+            return Err(SimplenessError::InvalidPrgRomSize(prg_rom.len()));
+        }
+
+        let (chr, chr_ram) = chr_memory(chr_rom, info);
+        if !chr.len().is_multiple_of(CHR_BANK_SIZE) {
+            return Err(SimplenessError::InvalidChrRomSize(chr.len()));
+        }
+
+        Ok(Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            bus_conflicts: has_bus_conflicts(info, true),
+            chr_bank: 0,
+        })
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        (self.chr_bank as usize % bank_count) * CHR_BANK_SIZE + addr as usize
+    }
+}
+
+impl Mapper for Mapper3 {
+    fn cpu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            // a 16K PRG ROM is mirrored at $C000
+            0x8000..=0xFFFF => self.prg_rom[(addr as usize - 0x8000) % self.prg_rom.len()],
+            _ => 0,
+        }
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x8000..=0xFFFF = addr {
+            self.chr_bank = if self.bus_conflicts {
+                data & self.cpu_map_read(addr)
+            } else {
+                data
+            };
+        }
+    }
+
+    fn ppu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.chr[self.chr_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x0000..=0x1FFF = addr
+            && self.chr_ram
+        {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.chr_bank);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.chr_bank = state.read_u8()?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{
+    error::SimplenessError,
+    memory::{
+        mapper::{Mapper, chr_memory},
+        rom_info::RomInfo,
+        wram::Wram,
+    },
+    savestate::{SaveStateError, Snapshot, StateReader, StateWriter},
+};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const NINA_CHR_BANK_SIZE: usize = 0x1000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Board {
+    Bnrom,
+    Nina001,
+}
+
+/*
+BNROM and NINA-001
+
+Two unrelated boards share mapper 34. BNROM selects a 32K PRG bank by writes to
+$8000-$FFFF, with bus conflicts, and has 8K of CHR RAM. NINA-001 has 8K of work RAM, and
+its registers sit at its top: $7FFD selects the 32K PRG bank, $7FFE and $7FFF the 4K CHR
+banks at $0000 and $1000. NES 2.0 submapper 1 is NINA-001 and 2 BNROM; otherwise only
+NINA-001 has more than 8K of CHR.
+*/
+pub struct Mapper34 {
+    board: Board,
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    wram: Option<Wram>,
+
+    prg_bank: u8,
+    chr_banks: [u8; 2],
+}
+
+impl Mapper34 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        info: &RomInfo,
+    ) -> Result<Self, SimplenessError> {
+        let board = match info.submapper {
+            1 => Board::Nina001,
+            2 => Board::Bnrom,
+            _ if chr_rom.len() > 0x2000 => Board::Nina001,
+            _ => Board::Bnrom,
+        };
+
+        if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(SimplenessError::InvalidPrgRomSize(prg_rom.len()));
+        }
+
+        let (chr, chr_ram) = chr_memory(chr_rom, info);
+        if !chr.len().is_multiple_of(NINA_CHR_BANK_SIZE) {
+            return Err(SimplenessError::InvalidChrRomSize(chr.len()));
+        }
+
+        Ok(Self {
+            board,
+            prg_rom,
+            chr,
+            chr_ram,
+            wram: (board == Board::Nina001).then(|| Wram::from_info(info)),
+            prg_bank: 0,
+            chr_banks: [0, 1],
+        })
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        (self.prg_bank as usize % bank_count) * PRG_BANK_SIZE
+            + (addr as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        match self.board {
+            Board::Bnrom => addr as usize % self.chr.len(),
+            Board::Nina001 => {
+                let bank_count = self.chr.len() / NINA_CHR_BANK_SIZE;
+                let bank = self.chr_banks[addr as usize >> 12] as usize % bank_count;
+                bank * NINA_CHR_BANK_SIZE + (addr as usize & (NINA_CHR_BANK_SIZE - 1))
+            }
+        }
+    }
+}
+
+impl Mapper for Mapper34 {
+    fn cpu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self
+                .wram
+                .as_ref()
+                .map_or(0, |wram| wram.read((addr - 0x6000) as usize)),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_rom_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) {
+        match (self.board, addr) {
+            (Board::Nina001, 0x6000..=0x7FFF) => {
+                match addr {
+                    0x7FFD => self.prg_bank = data,
+                    0x7FFE => self.chr_banks[0] = data,
+                    0x7FFF => self.chr_banks[1] = data,
+                    _ => {}
+                }
+                // the registers don't keep the RAM underneath from being written
+                if let Some(wram) = &mut self.wram {
+                    wram.write((addr - 0x6000) as usize, data);
+                }
+            }
+            (Board::Bnrom, 0x8000..=0xFFFF) => {
+                self.prg_bank = data & self.cpu_map_read(addr);
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.chr[self.chr_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x0000..=0x1FFF = addr
+            && self.chr_ram
+        {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn wram(&self) -> Option<&Wram> {
+        self.wram.as_ref()
+    }
+
+    fn wram_mut(&mut self) -> Option<&mut Wram> {
+        self.wram.as_mut()
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.prg_bank);
+        state.write_bytes(&self.chr_banks);
+        if let Some(wram) = &self.wram {
+            wram.save_state(state);
+        }
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.prg_bank = state.read_u8()?;
+        state.read_bytes(&mut self.chr_banks)?;
+        if let Some(wram) = &mut self.wram {
+            wram.load_state(state)?;
+        }
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{
+    error::SimplenessError,
+    memory::{
+        mapper::{Mapper, chr_memory},
+        rom_info::RomInfo,
+        wram::Wram,
+    },
+    ppu::NametableArrangement,
+    savestate::{SaveStateError, Snapshot, StateReader, StateWriter},
+};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+// A12 has to stay low for about 3 CPU cycles before a rise clocks the IRQ counter, which
+// filters out the short low periods between sprite pattern fetches.
+const A12_LOW_FILTER_DOTS: u64 = 9;
+
+/*
+MMC3 (TxROM)
+
+Even/odd register pairs live at $8000, $A000, $C000 and $E000. Bank data written to
+$8001 goes to one of eight bank registers (R0-R7) picked through $8000, which also
+selects the PRG and CHR layouts. The scanline counter is clocked by rising edges of
+PPU A12 and raises an IRQ when it reaches zero.
+*/
+pub struct Mapper4 {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    wram: Wram,
+    chr_ram: bool,
+
+    bank_select: u8,
+    bank_registers: [u8; 8],
+    mirroring: u8,
+    prg_ram_protect: u8,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    last_a12_high_cycle: u64,
+}
+
+impl Mapper4 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        info: &RomInfo,
+    ) -> Result<Self, SimplenessError> {
+        // the last two banks are fixed, so there have to be at least two
+        if prg_rom.len() < 2 * PRG_BANK_SIZE || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(SimplenessError::InvalidPrgRomSize(prg_rom.len()));
+        }
+
+        let (chr_rom, chr_ram) = chr_memory(chr_rom, info);
+
+        if !chr_rom.len().is_multiple_of(CHR_BANK_SIZE) {
+            return Err(SimplenessError::InvalidChrRomSize(chr_rom.len()));
+        }
+
+        Ok(Self {
+            prg_rom,
+            chr_rom,
+            wram: Wram::from_info(info),
+            chr_ram,
+            bank_select: 0,
+            bank_registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring: 0,
+            prg_ram_protect: 0x80,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            last_a12_high_cycle: 0,
+        })
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_ram_protect & 0x80 != 0
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_enabled() && self.prg_ram_protect & 0x40 == 0
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let second_last_bank = bank_count - 2;
+        let prg_mode = (self.bank_select >> 6) & 1;
+
+        let bank = match (addr >> 13) & 0b11 {
+            // $8000-$9FFF
+            0 => {
+                if prg_mode == 0 {
+                    self.bank_registers[6] as usize
+                } else {
+                    second_last_bank
+                }
+            }
+            // $A000-$BFFF
+            1 => self.bank_registers[7] as usize,
+            // $C000-$DFFF
+            2 => {
+                if prg_mode == 0 {
+                    second_last_bank
+                } else {
+                    self.bank_registers[6] as usize
+                }
+            }
+            // $E000-$FFFF
+            3 => bank_count - 1,
+            _ => unreachable!(),
+        };
+
+        (bank % bank_count) * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let bank_count = self.chr_rom.len() / CHR_BANK_SIZE;
+
+        // CHR A12 inversion swaps the 2K and 1K halves
+        let addr = if self.bank_select & 0x80 != 0 {
+            addr ^ 0x1000
+        } else {
+            addr
+        };
+
+        let bank = match addr >> 10 {
+            0 => self.bank_registers[0] as usize & !1,
+            1 => self.bank_registers[0] as usize | 1,
+            2 => self.bank_registers[1] as usize & !1,
+            3 => self.bank_registers[1] as usize | 1,
+            4..=7 => self.bank_registers[2 + (addr >> 10) as usize - 4] as usize,
+            _ => unreachable!(),
+        };
+
+        (bank % bank_count) * CHR_BANK_SIZE + (addr as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mapper4 {
+    fn cpu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.wram.read((addr - 0x6000) as usize),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_rom_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) {
+        match (addr, addr & 1) {
+            (0x6000..=0x7FFF, _) if self.prg_ram_writable() => {
+                self.wram.write((addr - 0x6000) as usize, data);
+            }
+            (0x8000..=0x9FFF, 0) => self.bank_select = data,
+            (0x8000..=0x9FFF, 1) => {
+                self.bank_registers[(self.bank_select & 0b111) as usize] = data;
+            }
+            (0xA000..=0xBFFF, 0) => self.mirroring = data & 1,
+            (0xA000..=0xBFFF, 1) => self.prg_ram_protect = data,
+            (0xC000..=0xDFFF, 0) => self.irq_latch = data,
+            (0xC000..=0xDFFF, 1) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (0xE000..=0xFFFF, 0) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (0xE000..=0xFFFF, 1) => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn ppu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.chr_rom[self.chr_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x0000..=0x1FFF = addr {
+            let offset = self.chr_offset(addr);
+            self.chr_rom[offset] = data;
+        }
+    }
+
+    fn nametable_arrangement(&self) -> Option<NametableArrangement> {
+        // 0: vertical mirroring, 1: horizontal mirroring
+        Some(if self.mirroring == 0 {
+            NametableArrangement::Horizontal
+        } else {
+            NametableArrangement::Vertical
+        })
+    }
+
+    fn ppu_bus_activity(&mut self, addr: u16, ppu_cycle: u64) {
+        if addr & 0x1000 == 0 {
+            return;
+        }
+
+        if ppu_cycle.wrapping_sub(self.last_a12_high_cycle) >= A12_LOW_FILTER_DOTS {
+            self.clock_irq_counter();
+        }
+        self.last_a12_high_cycle = ppu_cycle;
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn wram(&self) -> Option<&Wram> {
+        Some(&self.wram)
+    }
+
+    fn wram_mut(&mut self) -> Option<&mut Wram> {
+        Some(&mut self.wram)
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.wram.save_state(state);
+        if self.chr_ram {
+            state.write_bytes(&self.chr_rom);
+        }
+        state.write_u8(self.bank_select);
+        state.write_bytes(&self.bank_registers);
+        state.write_u8(self.mirroring);
+        state.write_u8(self.prg_ram_protect);
+        state.write_u8(self.irq_latch);
+        state.write_u8(self.irq_counter);
+        state.write_bool(self.irq_reload);
+        state.write_bool(self.irq_enabled);
+        state.write_bool(self.irq_pending);
+        state.write_u64(self.last_a12_high_cycle);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.wram.load_state(state)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr_rom)?;
+        }
+        self.bank_select = state.read_u8()?;
+        state.read_bytes(&mut self.bank_registers)?;
+        self.mirroring = state.read_u8()? & 1;
+        self.prg_ram_protect = state.read_u8()?;
+        self.irq_latch = state.read_u8()?;
+        self.irq_counter = state.read_u8()?;
+        self.irq_reload = state.read_bool()?;
+        self.irq_enabled = state.read_bool()?;
+        self.irq_pending = state.read_bool()?;
+        self.last_a12_high_cycle = state.read_u64()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{
+    error::SimplenessError,
+    memory::{
+        mapper::{Mapper, chr_memory},
+        rom_info::RomInfo,
+    },
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/*
+GxROM
+
+Writes to $8000-$FFFF select a 32K PRG bank with bits 4-5 and an 8K CHR bank with bits
+0-1. The boards have bus conflicts.
+*/
+pub struct Mapper66 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+
+    bank_select: u8,
+}
+
+impl Mapper66 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        info: &RomInfo,
+    ) -> Result<Self, SimplenessError> {
+        if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(SimplenessError::InvalidPrgRomSize(prg_rom.len()));
+        }
+
+        let (chr, chr_ram) = chr_memory(chr_rom, info);
+        if !chr.len().is_multiple_of(CHR_BANK_SIZE) {
+            return Err(SimplenessError::InvalidChrRomSize(chr.len()));
+        }
+
+        Ok(Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            bank_select: 0,
+        })
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = ((self.bank_select >> 4) & 0x03) as usize % bank_count;
+        bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        let bank = (self.bank_select & 0x03) as usize % bank_count;
+        bank * CHR_BANK_SIZE + addr as usize
+    }
+}
+
+impl Mapper for Mapper66 {
+    fn cpu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => self.prg_rom[self.prg_rom_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x8000..=0xFFFF = addr {
+            self.bank_select = data & self.cpu_map_read(addr);
+        }
+    }
+
+    fn ppu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.chr[self.chr_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x0000..=0x1FFF = addr
+            && self.chr_ram
+        {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.bank_select);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.bank_select = state.read_u8()?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        Ok(())
+    }
+}
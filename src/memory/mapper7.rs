@@ -0,0 +1,116 @@
+use crate::{
+    error::SimplenessError,
+    memory::{
+        mapper::{Mapper, chr_memory, has_bus_conflicts},
+        rom_info::RomInfo,
+    },
+    ppu::NametableArrangement,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+
+/*
+AxROM
+
+Writes to $8000-$FFFF select a 32K PRG bank with bits 0-3, and with bit 4 which half of
+CIRAM all four nametables show. CHR is 8K of RAM. AMROM and AOROM have bus conflicts and
+ANROM doesn't; games made for boards with conflicts avoid them, so they are only emulated
+when the header asks for them.
+*/
+pub struct Mapper7 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    bus_conflicts: bool,
+
+    bank_select: u8,
+}
+
+impl Mapper7 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        info: &RomInfo,
+    ) -> Result<Self, SimplenessError> {
+        if prg_rom.is_empty() || !prg_rom.len().is_multiple_of(PRG_BANK_SIZE) {
+            return Err(SimplenessError::InvalidPrgRomSize(prg_rom.len()));
+        }
+
+        let (chr, chr_ram) = chr_memory(chr_rom, info);
+        if !chr_ram && chr.len() != 0x2000 {
+            return Err(SimplenessError::InvalidChrRomSize(chr.len()));
+        }
+
+        Ok(Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            bus_conflicts: has_bus_conflicts(info, false),
+            bank_select: 0,
+        })
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = (self.bank_select & 0x0f) as usize % bank_count;
+        bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))
+    }
+}
+
+impl Mapper for Mapper7 {
+    fn cpu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => self.prg_rom[self.prg_rom_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x8000..=0xFFFF = addr {
+            self.bank_select = if self.bus_conflicts {
+                data & self.cpu_map_read(addr)
+            } else {
+                data
+            };
+        }
+    }
+
+    fn ppu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.chr[addr as usize],
+            _ => 0,
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x0000..=0x1FFF = addr
+            && self.chr_ram
+        {
+            self.chr[addr as usize] = data;
+        }
+    }
+
+    fn nametable_arrangement(&self) -> Option<NametableArrangement> {
+        Some(if self.bank_select & 0x10 == 0 {
+            NametableArrangement::SingleScreenA
+        } else {
+            NametableArrangement::SingleScreenB
+        })
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.bank_select);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.bank_select = state.read_u8()?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,12 @@
 pub mod bus;
 pub mod mapper;
 pub mod mapper0;
+pub mod mapper1;
+pub mod mapper2;
+pub mod mapper3;
+pub mod mapper34;
+pub mod mapper4;
+pub mod mapper66;
+pub mod mapper7;
+pub mod rom_info;
+pub mod wram;
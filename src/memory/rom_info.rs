@@ -0,0 +1,296 @@
+use std::{fmt, ops::Range};
+
+use crate::ppu::NametableArrangement;
+
+pub const HEADER_SIZE: usize = 16;
+pub const TRAINER_SIZE: usize = 512;
+pub const TRAINER_ADDRESS: u16 = 0x7000;
+
+const MAGIC: &[u8; 4] = b"NES\x1A";
+const PRG_ROM_UNIT: usize = 0x4000;
+const CHR_ROM_UNIT: usize = 0x2000;
+const PRG_RAM_UNIT: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderFormat {
+    INes,
+    Nes20,
+}
+
+/// The CPU/PPU timing the cartridge was made for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    /// The PPU and hardware types are only known from NES 2.0 headers, and are 0 otherwise.
+    VsSystem {
+        ppu: u8,
+        hardware: u8,
+    },
+    PlayChoice10,
+    /// One of the NES 2.0 extended console types (Famiclones, VT0x, ...).
+    Extended(u8),
+}
+
+/*
+What the 16 byte header says about the cartridge, for both the original iNES format and
+NES 2.0, which is told apart by bits 2-3 of byte 7 being 10.
+
+    0-3   "NES" $1A
+    4     PRG ROM size, 16K units (NES 2.0: low byte)
+    5     CHR ROM size, 8K units (NES 2.0: low byte)
+    6     mapper D0-D3, four-screen, trainer, battery, mirroring
+    7     mapper D4-D7, NES 2.0 identifier, console type
+    8     iNES: PRG RAM size in 8K units, 0 meaning 8K
+          NES 2.0: submapper, mapper D8-D11
+    9     NES 2.0: CHR ROM size MSB, PRG ROM size MSB
+    10    NES 2.0: PRG NVRAM shift, PRG RAM shift
+    11    NES 2.0: CHR NVRAM shift, CHR RAM shift
+    12    NES 2.0: timing
+    13    NES 2.0: Vs. hardware type and PPU, or the extended console type
+    14    NES 2.0: miscellaneous ROM count
+    15    NES 2.0: default expansion device
+
+A NES 2.0 size MSB nybble of $F switches the ROM size to exponent-multiplier notation,
+2^E * (MM * 2 + 1) with the low byte being EEEEEEMM. RAM sizes are 64 << shift, 0 meaning
+none. iNES headers don't tell volatile RAM from battery backed RAM, so the RAM goes into
+the battery backed size when the battery flag is set.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomInfo {
+    pub format: HeaderFormat,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub battery: bool,
+    pub trainer: bool,
+    pub nametable_arrangement: NametableArrangement,
+    pub four_screen: bool,
+    pub timing: Timing,
+    pub console_type: ConsoleType,
+    pub misc_roms: u8,
+    pub expansion_device: u8,
+}
+
+impl RomInfo {
+    /// Decodes a header, or returns `None` when it doesn't start with the iNES magic.
+    pub fn parse(header: &[u8; HEADER_SIZE]) -> Option<Self> {
+        if &header[..4] != MAGIC {
+            return None;
+        }
+
+        let flag6 = header[6];
+        let flag7 = header[7];
+        let format = if flag7 & 0x0c == 0x08 {
+            HeaderFormat::Nes20
+        } else {
+            HeaderFormat::INes
+        };
+
+        let mut info = Self {
+            format,
+            mapper: (flag6 >> 4) as u16,
+            submapper: 0,
+            prg_rom_size: header[4] as usize * PRG_ROM_UNIT,
+            chr_rom_size: header[5] as usize * CHR_ROM_UNIT,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            battery: flag6 & 0x02 != 0,
+            trainer: flag6 & 0x04 != 0,
+            // bit 0 set means vertical mirroring, i.e. nametables arranged horizontally
+            nametable_arrangement: if flag6 & 0x01 == 0 {
+                NametableArrangement::Vertical
+            } else {
+                NametableArrangement::Horizontal
+            },
+            four_screen: flag6 & 0x08 != 0,
+            timing: Timing::Ntsc,
+            console_type: ConsoleType::Nes,
+            misc_roms: 0,
+            expansion_device: 0,
+        };
+
+        match format {
+            HeaderFormat::Nes20 => info.parse_nes20(header),
+            HeaderFormat::INes => info.parse_ines(header),
+        }
+        Some(info)
+    }
+
+    fn parse_nes20(&mut self, header: &[u8; HEADER_SIZE]) {
+        self.mapper |= (header[7] & 0xf0) as u16 | ((header[8] & 0x0f) as u16) << 8;
+        self.submapper = header[8] >> 4;
+        // sizes too big to even address can't be in the file, which `file_size` reports
+        self.prg_rom_size =
+            nes20_rom_size(header[4], header[9] & 0x0f, PRG_ROM_UNIT).unwrap_or(usize::MAX);
+        self.chr_rom_size =
+            nes20_rom_size(header[5], header[9] >> 4, CHR_ROM_UNIT).unwrap_or(usize::MAX);
+        self.prg_ram_size = nes20_ram_size(header[10] & 0x0f);
+        self.prg_nvram_size = nes20_ram_size(header[10] >> 4);
+        self.chr_ram_size = nes20_ram_size(header[11] & 0x0f);
+        self.chr_nvram_size = nes20_ram_size(header[11] >> 4);
+        self.timing = match header[12] & 0x03 {
+            0 => Timing::Ntsc,
+            1 => Timing::Pal,
+            2 => Timing::MultiRegion,
+            _ => Timing::Dendy,
+        };
+        self.console_type = match header[7] & 0x03 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem {
+                ppu: header[13] & 0x0f,
+                hardware: header[13] >> 4,
+            },
+            2 => ConsoleType::PlayChoice10,
+            _ => ConsoleType::Extended(header[13] & 0x0f),
+        };
+        self.misc_roms = header[14] & 0x03;
+        self.expansion_device = header[15] & 0x3f;
+    }
+
+    fn parse_ines(&mut self, header: &[u8; HEADER_SIZE]) {
+        // Old dumping tools left their name in bytes 7-15 ("DiskDude!"), in which case the
+        // upper mapper nybble is garbage too. Byte 7 is only trusted with 12-15 clear.
+        let flag7 = if header[12..].iter().all(|&byte| byte == 0) {
+            header[7]
+        } else {
+            0
+        };
+        self.mapper |= (flag7 & 0xf0) as u16;
+        self.console_type = match flag7 & 0x03 {
+            1 => ConsoleType::VsSystem {
+                ppu: 0,
+                hardware: 0,
+            },
+            2 => ConsoleType::PlayChoice10,
+            _ => ConsoleType::Nes,
+        };
+        if header[9] & 0x01 != 0 {
+            self.timing = Timing::Pal;
+        }
+
+        let prg_ram_size = header[8].max(1) as usize * PRG_RAM_UNIT;
+        if self.battery {
+            self.prg_nvram_size = prg_ram_size;
+        } else {
+            self.prg_ram_size = prg_ram_size;
+        }
+        if self.chr_rom_size == 0 {
+            self.chr_ram_size = CHR_RAM_SIZE;
+        }
+    }
+
+    /// How long the file should be going by the header, or `None` when that's more than
+    /// can be addressed. The ranges below are only valid once the file is known to be
+    /// that long.
+    pub fn file_size(&self) -> Option<usize> {
+        self.trainer_range()
+            .end
+            .checked_add(self.prg_rom_size)?
+            .checked_add(self.chr_rom_size)
+    }
+
+    pub fn trainer_range(&self) -> Range<usize> {
+        let size = if self.trainer { TRAINER_SIZE } else { 0 };
+        HEADER_SIZE..HEADER_SIZE + size
+    }
+
+    pub fn prg_rom_range(&self) -> Range<usize> {
+        let start = self.trainer_range().end;
+        start..start + self.prg_rom_size
+    }
+
+    pub fn chr_rom_range(&self) -> Range<usize> {
+        let start = self.prg_rom_range().end;
+        start..start + self.chr_rom_size
+    }
+
+    /// All the PRG RAM on the board, battery backed or not.
+    pub fn total_prg_ram_size(&self) -> usize {
+        self.prg_ram_size + self.prg_nvram_size
+    }
+}
+
+fn nes20_rom_size(lsb: u8, msb: u8, unit: usize) -> Option<usize> {
+    if msb == 0x0f {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        2usize.checked_pow(exponent)?.checked_mul(multiplier)
+    } else {
+        Some((((msb as usize) << 8) | lsb as usize) * unit)
+    }
+}
+
+fn nes20_ram_size(shift: u8) -> usize {
+    if shift == 0 { 0 } else { 64 << shift }
+}
+
+fn format_size(size: usize) -> String {
+    if size.is_multiple_of(1024) {
+        format!("{}K", size / 1024)
+    } else {
+        format!("{size} bytes")
+    }
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.format {
+            HeaderFormat::INes => write!(f, "iNES, mapper {}", self.mapper)?,
+            HeaderFormat::Nes20 => write!(f, "NES 2.0, mapper {}.{}", self.mapper, self.submapper)?,
+        }
+        write!(f, ", {} PRG ROM", format_size(self.prg_rom_size))?;
+        if self.chr_rom_size != 0 {
+            write!(f, ", {} CHR ROM", format_size(self.chr_rom_size))?;
+        }
+        if self.chr_ram_size + self.chr_nvram_size != 0 {
+            write!(
+                f,
+                ", {} CHR RAM",
+                format_size(self.chr_ram_size + self.chr_nvram_size)
+            )?;
+        }
+        if self.total_prg_ram_size() != 0 {
+            write!(f, ", {} PRG RAM", format_size(self.total_prg_ram_size()))?;
+        }
+        if self.battery {
+            write!(f, ", battery")?;
+        }
+        if self.trainer {
+            write!(f, ", trainer")?;
+        }
+        if self.four_screen {
+            write!(f, ", four-screen")?;
+        } else {
+            write!(f, ", {:?} arrangement", self.nametable_arrangement)?;
+        }
+        let timing = match self.timing {
+            Timing::Ntsc => "NTSC",
+            Timing::Pal => "PAL",
+            Timing::MultiRegion => "multi-region",
+            Timing::Dendy => "Dendy",
+        };
+        write!(f, ", {timing}")?;
+        match self.console_type {
+            ConsoleType::Nes => Ok(()),
+            ConsoleType::VsSystem { .. } => write!(f, ", Vs. System"),
+            ConsoleType::PlayChoice10 => write!(f, ", PlayChoice-10"),
+            ConsoleType::Extended(console) => write!(f, ", extended console type {console}"),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{
+    error::SimplenessError,
+    memory::rom_info::RomInfo,
+    savestate::{SaveStateError, Snapshot, StateReader, StateWriter},
+};
+
+/// Work RAM boards carry even when the header asks for none, as they always did here.
+pub const MIN_WRAM_SIZE: usize = 0x2000;
+
+/*
+Cartridge work RAM, the PRG RAM boards map at $6000-$7FFF. Mappers own it and decide when
+it's enabled; frontends reach it through the mapper to persist it when it's battery
+backed. Writes mark it dirty, so it's only written back to disk after it changed.
+*/
+#[derive(Clone)]
+pub struct Wram {
+    data: Vec<u8>,
+    battery: bool,
+    dirty: bool,
+}
+
+impl Wram {
+    pub fn new(size: usize, battery: bool) -> Self {
+        Self {
+            data: vec![0; size],
+            battery,
+            dirty: false,
+        }
+    }
+
+    /// The RAM the header describes, with at least `MIN_WRAM_SIZE`.
+    pub fn from_info(info: &RomInfo) -> Self {
+        Self::new(info.total_prg_ram_size().max(MIN_WRAM_SIZE), info.battery)
+    }
+
+    /// Reads at an offset from the start of the RAM, mirrored over its size.
+    pub fn read(&self, offset: usize) -> u8 {
+        self.data[offset % self.data.len()]
+    }
+
+    pub fn write(&mut self, offset: usize, data: u8) {
+        let len = self.data.len();
+        self.data[offset % len] = data;
+        self.dirty = true;
+    }
+
+    pub fn is_battery_backed(&self) -> bool {
+        self.battery
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Restores the contents from a save file, which must be the size of the RAM.
+    pub fn load(&mut self, data: &[u8]) -> Result<(), SimplenessError> {
+        if data.len() != self.data.len() {
+            return Err(SimplenessError::SaveRamSizeMismatch {
+                expected: self.data.len(),
+                actual: data.len(),
+            });
+        }
+        self.data.copy_from_slice(data);
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Whether the RAM was written since the last call.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+}
+
+impl Snapshot for Wram {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.data);
+    }
+
+    // a loaded state changes the RAM like the game writing to it would
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        state.read_bytes(&mut self.data)?;
+        self.dirty = true;
+        Ok(())
+    }
+}
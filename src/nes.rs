@@ -0,0 +1,268 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    SimplenessError,
+    apu::{APU_STATUS, DEFAULT_SAMPLE_RATE},
+    cpu::olc6502::Olc6502,
+    joypad::{JoypadState, Port},
+    memory::{
+        bus::Bus,
+        mapper::Rom,
+        rom_info::{HEADER_SIZE, RomInfo},
+        wram::Wram,
+    },
+    ppu::NametableArrangement,
+    savestate::{self, SaveStateError, Snapshot, StateReader, StateWriter},
+};
+
+pub const SCREEN_WIDTH: u32 = 256;
+pub const SCREEN_HEIGHT: u32 = 240;
+
+/*
+The whole console behind one handle, for frontends and tools that embed the emulator.
+Frames are run to completion; everything finer grained is reachable through `cpu()`.
+*/
+pub struct Nes {
+    cpu: Olc6502,
+    rom_content: Option<Vec<u8>>,
+    rom_crc: u32, // identifies the cartridge in save states
+    sample_rate: u32,
+}
+
+impl Default for Nes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Nes {
+    pub fn new() -> Self {
+        Self {
+            cpu: Olc6502::new(Bus::new()),
+            rom_content: None,
+            rom_crc: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        }
+    }
+
+    /// Inserts a cartridge from the contents of an iNES or NES 2.0 file and powers the console on.
+    /// A ROM that can't be loaded leaves the console as it was.
+    pub fn load_rom(&mut self, rom_content: Vec<u8>) -> Result<(), SimplenessError> {
+        Rom::parse(&rom_content)?;
+        self.rom_crc = savestate::crc32(&rom_content);
+        self.rom_content = Some(rom_content);
+        self.power_on();
+        Ok(())
+    }
+
+    pub fn rom_loaded(&self) -> bool {
+        self.rom_content.is_some()
+    }
+
+    /// What the inserted cartridge's header says about it.
+    pub fn rom_info(&self) -> Option<RomInfo> {
+        self.rom_content
+            .as_ref()?
+            .first_chunk::<HEADER_SIZE>()
+            .and_then(RomInfo::parse)
+    }
+
+    /// Runs `f` on the cartridge's work RAM when it's battery backed.
+    fn with_battery_ram<T>(&self, f: impl FnOnce(&mut Wram) -> T) -> Option<T> {
+        let mut mapper = self.cpu.bus.mapper()?.borrow_mut();
+        mapper
+            .wram_mut()
+            .filter(|wram| wram.is_battery_backed())
+            .map(f)
+    }
+
+    /// The contents of the cartridge's battery backed RAM, to keep in a save file.
+    pub fn battery_ram(&self) -> Option<Vec<u8>> {
+        self.with_battery_ram(|wram| wram.data().to_vec())
+    }
+
+    /// Like `battery_ram`, but only when it changed since the last call, so frontends can
+    /// flush it periodically without rewriting an unchanged save file.
+    pub fn take_battery_ram_changes(&mut self) -> Option<Vec<u8>> {
+        self.with_battery_ram(|wram| wram.take_dirty().then(|| wram.data().to_vec()))
+            .flatten()
+    }
+
+    /// Restores the battery backed RAM from a save file. Does nothing for cartridges
+    /// without a battery.
+    pub fn load_battery_ram(&mut self, data: &[u8]) -> Result<(), SimplenessError> {
+        self.with_battery_ram(|wram| wram.load(data))
+            .unwrap_or(Ok(()))
+    }
+
+    /// Presses the reset button: RAM and cartridge state survive, the APU is silenced.
+    pub fn reset(&mut self) {
+        if !self.rom_loaded() {
+            return;
+        }
+
+        self.cpu.bus.apu.write_register(APU_STATUS, 0);
+        self.cpu.reset();
+        self.cpu.bus.ppu.reset();
+    }
+
+    /// Turns the console off and on again, which also reloads the cartridge. Battery backed
+    /// RAM keeps its contents.
+    pub fn power_cycle(&mut self) {
+        let battery_ram = self.with_battery_ram(|wram| wram.clone());
+        self.power_on();
+        if let Some(battery_ram) = battery_ram {
+            self.with_battery_ram(|wram| *wram = battery_ram);
+        }
+    }
+
+    fn power_on(&mut self) {
+        let tracer = self.cpu.stop_trace();
+        self.cpu = self.build_machine();
+        if let Some(tracer) = tracer {
+            self.cpu.start_trace(tracer);
+        }
+
+        if self.rom_loaded() {
+            self.cpu.reset();
+            self.cpu.bus.ppu.reset();
+        }
+    }
+
+    /// A console in its power on state, with the cartridge inserted but before reset.
+    fn build_machine(&self) -> Olc6502 {
+        let mut bus = Bus::new();
+        bus.apu.set_sample_rate(self.sample_rate);
+
+        // load_rom only keeps ROMs that parse
+        if let Some(Ok(rom)) = self.rom_content.as_deref().map(Rom::parse) {
+            bus.set_mapper(Rc::new(RefCell::new(rom.mapper)));
+            bus.ppu.set_nametable_arrangement(if rom.info.four_screen {
+                NametableArrangement::FourScreen
+            } else {
+                rom.info.nametable_arrangement
+            });
+        }
+
+        Olc6502::new(bus)
+    }
+
+    /// Snapshots the whole machine. Take it between frames, as `run_frame` leaves it.
+    pub fn save_state(&self) -> Result<Vec<u8>, SaveStateError> {
+        if !self.rom_loaded() {
+            return Err(SaveStateError::NoRom);
+        }
+
+        let mut state = StateWriter::new();
+        state.write_header(self.rom_crc);
+        self.cpu.save_state(&mut state);
+        Ok(state.into_bytes())
+    }
+
+    /// Restores a snapshot from `save_state`. The running machine is left untouched when
+    /// the state is rejected.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        if !self.rom_loaded() {
+            return Err(SaveStateError::NoRom);
+        }
+
+        let mut state = StateReader::new(data);
+        state.read_header(self.rom_crc)?;
+
+        let mut cpu = self.build_machine();
+        cpu.load_state(&mut state)?;
+        if !state.is_empty() {
+            return Err(SaveStateError::Corrupt);
+        }
+
+        // controllers are live input, not part of the snapshot
+        cpu.bus.joypad1.state = self.cpu.bus.joypad1.state;
+        cpu.bus.joypad2.state = self.cpu.bus.joypad2.state;
+        // and so is a running trace
+        if let Some(tracer) = self.cpu.stop_trace() {
+            cpu.start_trace(tracer);
+        }
+        self.cpu = cpu;
+        Ok(())
+    }
+
+    /// Runs until the PPU finishes the next frame. Does nothing without a cartridge.
+    pub fn run_frame(&mut self) {
+        if !self.rom_loaded() {
+            return;
+        }
+
+        while !self.cpu.bus.ppu.frame_ready() {
+            self.cpu.tick();
+        }
+    }
+
+    /// Sets the buttons held on a controller.
+    pub fn set_buttons(&mut self, port: Port, state: JoypadState) {
+        match port {
+            Port::One => self.cpu.bus.joypad1.state = state,
+            Port::Two => self.cpu.bus.joypad2.state = state,
+        }
+    }
+
+    pub fn buttons(&self, port: Port) -> JoypadState {
+        match port {
+            Port::One => self.cpu.bus.joypad1.state,
+            Port::Two => self.cpu.bus.joypad2.state,
+        }
+    }
+
+    /// The last finished frame, `SCREEN_WIDTH` * `SCREEN_HEIGHT` RGBA pixels.
+    pub fn framebuffer(&self) -> &[u8] {
+        self.cpu.bus.ppu.get_pixel_buffer()
+    }
+
+    /// The last finished frame as one NES palette index per pixel, a quarter of the size
+    /// of `framebuffer` and enough to redraw it.
+    pub fn frame_colors(&self) -> &[u8] {
+        self.cpu.bus.ppu.get_color_buffer()
+    }
+
+    /// Shows a frame kept from `frame_colors` until the next one is drawn, since save
+    /// states don't carry the picture.
+    pub fn restore_frame(&mut self, colors: &[u8]) {
+        self.cpu.bus.ppu.set_color_buffer(colors);
+    }
+
+    /// Hands over the audio samples produced since the last call.
+    pub fn audio_samples(&mut self) -> Vec<f32> {
+        self.cpu.bus.apu.take_samples()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.cpu.bus.apu.set_sample_rate(sample_rate);
+    }
+
+    /// See `audio::dynamic_rate_adjustment`.
+    pub fn set_audio_rate_adjust(&mut self, rate_adjust: f64) {
+        self.cpu.bus.apu.set_rate_adjust(rate_adjust);
+    }
+
+    // the machine's internals are only handed out with the `debug` feature
+
+    #[cfg(feature = "debug")]
+    pub fn cpu(&self) -> &Olc6502 {
+        &self.cpu
+    }
+
+    #[cfg(feature = "debug")]
+    pub fn cpu_mut(&mut self) -> &mut Olc6502 {
+        &mut self.cpu
+    }
+
+    #[cfg(not(feature = "debug"))]
+    pub(crate) fn cpu(&self) -> &Olc6502 {
+        &self.cpu
+    }
+
+    #[cfg(not(feature = "debug"))]
+    pub(crate) fn cpu_mut(&mut self) -> &mut Olc6502 {
+        &mut self.cpu
+    }
+}
@@ -1,8 +1,10 @@
+#[allow(dead_code)]
 pub struct AddressRegister {
     hibyte: u8,
     lobyte: u8,
 }
 
+#[allow(dead_code)]
 impl AddressRegister {
     pub fn new() -> Self {
         Self {
@@ -6,21 +6,23 @@ mod ppu_mask;
 mod ppu_registers;
 mod ppu_status;
 
-use std::{
-    collections::VecDeque,
-    io::{BufReader, Cursor, Read},
-    os::raw,
-    vec,
-};
+use std::{collections::VecDeque, vec};
 
-pub use ppu_bus::NametableArrangement;
+pub use ppu_bus::{NametableArrangement, NametableSource};
 use ppu_ctrl::PPUCtrl;
 use ppu_mask::PPUMask;
 use ppu_registers::PpuRegisters;
 
 use crate::{
+    cpu::bus::AccessKind,
+    debugger::MemoryWatch,
     memory::mapper::SharedMapper,
-    ppu::{oam_sprite::OAMSprite, ppu_bus::PPUBus, ppu_registers::ScrollRegister},
+    ppu::{
+        oam_sprite::OAMSpriteAttributes,
+        ppu_bus::PPUBus,
+        ppu_registers::ScrollRegister,
+    },
+    savestate::{SaveStateError, Snapshot, StateReader, StateWriter},
 };
 
 const PPUCTRL: u16 = 0x2000;
@@ -34,6 +36,11 @@ const PPUDATA: u16 = 0x2007;
 pub const OAMDMA: u16 = 0x4014;
 
 const PALLETTE_TABLE_START: u16 = 0x3F00;
+
+// OAM is DRAM that only rendering refreshes, an 8 byte row at a time
+const OAM_ROW_COUNT: usize = 32;
+const OAM_DECAY_DOTS: u64 = 3000 * 3; // roughly 3000 CPU cycles
+#[allow(dead_code)]
 const ATTRIBUTE_TABLE_OFFSET: u16 = 0x03C0;
 
 const COLORS: [(u8, u8, u8); 64] = [
@@ -104,6 +111,7 @@ const COLORS: [(u8, u8, u8); 64] = [
 ];
 
 #[derive(Default, Clone, Copy)]
+#[allow(dead_code)]
 struct ScrollState {
     pub coarse_x: u8,
     pub coarse_y: u8,
@@ -121,13 +129,27 @@ pub struct Ppu {
     current_cycle: u64,
 
     had_pre_render_scanline: bool,
+    odd_frame: bool,
 
     screen_pixelbuffer: Vec<u8>,
+    screen_colors: Vec<u8>, // the palette index of every pixel, a quarter of the size
     informed_frame_ready: bool, // has informed that the frame is ready to render
     pub should_nmi: bool,       // tells the cpu to nmi
+    pub watch: MemoryWatch,     // PPUDATA accesses a debugger is interested in
 
     oam_data: [u8; 0x100],
-    oam_addr: u8,
+    oam_addr: u8, // also the index sprite evaluation walks OAM with
+    oam_refreshed: [u64; OAM_ROW_COUNT], // the PPU bus cycle each row was last accessed on
+    oam_corrupt_rows: u32,               // rows that get row 0 copied over them
+
+    // sprite evaluation, filling secondary OAM with the next scanline's sprites
+    secondary_oam: [u8; 0x20],
+    secondary_oam_addr: u8,
+    oam_latch: u8, // the byte evaluation last read, which OAMDATA reads see while rendering
+    sprite_in_range: bool,
+    sprite_evaluation_done: bool,
+    overflow_bytes_left: u8,
+    sprite_zero_in_range: bool, // the first sprite evaluated is in secondary OAM
 
     // shifters and stuff
     bg_nametable_byte: u8,
@@ -140,11 +162,16 @@ pub struct Ppu {
     bg_shifter_attribute_lobyte: u16,
     bg_shifter_attribute_hibyte: u16,
 
-    scanline_sprites: [OAMSprite; 8],
-    scanline_sprites_count: usize,
+    // the eight sprite output units, loaded at dots 257-320 for the next scanline
+    sprite_shifter_lobytes: [u8; 8],
+    sprite_shifter_hibytes: [u8; 8],
+    sprite_attributes: [OAMSpriteAttributes; 8],
+    sprite_x_counters: [u8; 8],
+    sprite_zero_loaded: bool, // the first unit holds sprite 0
 
     opaque_bg_pixel_table: [[bool; 256]; 240],
 
+    #[allow(dead_code)]
     next_pixels: VecDeque<u8>,
 }
 
@@ -157,11 +184,23 @@ impl Ppu {
             current_scanline: 0,
             current_cycle: 0,
             had_pre_render_scanline: false,
+            odd_frame: false,
             screen_pixelbuffer: vec![0; 240 * 256 * 4],
+            screen_colors: vec![0x0f; 240 * 256],
             informed_frame_ready: false,
             should_nmi: false,
+            watch: MemoryWatch::default(),
             oam_data: [0; 0x100],
             oam_addr: 0,
+            oam_refreshed: [0; OAM_ROW_COUNT],
+            oam_corrupt_rows: 0,
+            secondary_oam: [0xff; 0x20],
+            secondary_oam_addr: 0,
+            oam_latch: 0,
+            sprite_in_range: false,
+            sprite_evaluation_done: false,
+            overflow_bytes_left: 0,
+            sprite_zero_in_range: false,
             bg_nametable_byte: 0,
             bg_attribute_byte: 0,
             bg_pattern_lsbits: 0,
@@ -172,8 +211,11 @@ impl Ppu {
             bg_shifter_attribute_lobyte: 0,
             bg_shifter_attribute_hibyte: 0,
 
-            scanline_sprites: [OAMSprite::from_bytes(&[0u8; 4], false); 8],
-            scanline_sprites_count: 0,
+            sprite_shifter_lobytes: [0; 8],
+            sprite_shifter_hibytes: [0; 8],
+            sprite_attributes: [OAMSpriteAttributes::new(); 8],
+            sprite_x_counters: [0; 8],
+            sprite_zero_loaded: false,
             opaque_bg_pixel_table: [[false; 256]; 240],
             next_pixels: VecDeque::from([0; 0x10]), // pixels are emitted before.
         }
@@ -198,6 +240,17 @@ impl Ppu {
         &self.screen_pixelbuffer
     }
 
+    pub fn get_color_buffer(&self) -> &[u8] {
+        &self.screen_colors
+    }
+
+    /// Redraws the pixel buffer from palette indices kept from `get_color_buffer`.
+    pub fn set_color_buffer(&mut self, colors: &[u8]) {
+        for (i, &color) in colors.iter().enumerate() {
+            self.set_pixel(i, color as usize);
+        }
+    }
+
     fn call_nmi(&mut self) {
         if self.registers.ppu_ctrl.vblank_nmi_enable() == 1 {
             self.should_nmi = true;
@@ -217,6 +270,21 @@ impl Ppu {
         self.ppu_bus.set_mapper(mapper);
     }
 
+    /// The scanline being drawn, 261 being the pre-render line.
+    pub fn scanline(&self) -> u16 {
+        self.current_scanline as u16
+    }
+
+    /// The dot (PPU cycle) within the current scanline.
+    pub fn dot(&self) -> u16 {
+        self.current_cycle as u16
+    }
+
+    /// Reads PPU memory without side effects, for debugging tools.
+    pub fn peek_u8(&self, addr: u16) -> u8 {
+        self.ppu_bus.peek_u8(addr & 0x3fff)
+    }
+
     pub fn read_register(&mut self, addr: u16) -> u8 {
         match addr {
             PPUSTATUS => {
@@ -229,8 +297,12 @@ impl Ppu {
             }
 
             OAMDATA => {
-                // Reads a byte from OAM at the current OAM address
-                self.oam_data[self.oam_addr as usize]
+                // While rendering, reads see what sprite evaluation and the sprite fetches read
+                if self.rendering_enabled() && self.is_render_line() {
+                    self.oam_latch
+                } else {
+                    self.read_oam(self.oam_addr)
+                }
             }
 
             PPUDATA => {
@@ -243,6 +315,7 @@ impl Ppu {
                 */
                 let addr = self.registers.v;
                 let data = self.ppu_bus.read_u8(addr);
+                self.watch.record(AccessKind::Read, addr & 0x3fff, data);
 
                 let increment = self.registers.ppu_ctrl.get_increment_value();
                 self.registers.v += increment;
@@ -264,41 +337,37 @@ impl Ppu {
     pub fn write_register(&mut self, addr: u16, value: u8) {
         let mut new_t = ScrollRegister::from(self.registers.t);
         match addr {
-            PPUCTRL => {
-                if self.had_pre_render_scanline {
-                    new_t.set_nametable_select(value & 0b11);
-                    self.registers.t = new_t.into();
-
-                    let old_val = self.registers.ppu_ctrl;
-                    self.registers.ppu_ctrl = PPUCtrl::from_bytes([value]);
-                    if old_val.vblank_nmi_enable() == 0
-                        && self.registers.ppu_ctrl.vblank_nmi_enable() == 1
-                        && self.registers.ppu_status.vblank() == 1
-                    {
-                        self.call_nmi();
-                    }
+            PPUCTRL if self.had_pre_render_scanline => {
+                new_t.set_nametable_select(value & 0b11);
+                self.registers.t = new_t.into();
+
+                let old_val = self.registers.ppu_ctrl;
+                self.registers.ppu_ctrl = PPUCtrl::from_bytes([value]);
+                if old_val.vblank_nmi_enable() == 0
+                    && self.registers.ppu_ctrl.vblank_nmi_enable() == 1
+                    && self.registers.ppu_status.vblank() == 1
+                {
+                    self.call_nmi();
                 }
             }
 
-            PPUMASK => {
-                if self.had_pre_render_scanline {
-                    self.registers.ppu_mask = PPUMask::from_bytes([value]);
+            PPUMASK if self.had_pre_render_scanline => {
+                let was_rendering = self.rendering_enabled();
+                self.registers.ppu_mask = PPUMask::from_bytes([value]);
+                if was_rendering && !self.rendering_enabled() && self.is_render_line() {
+                    self.flag_oam_corruption();
                 }
             }
 
-            OAMADDR => {
-                // Sets the OAM address for subsequent OAMDATA writes
-                if !self.is_rendering() {
-                    self.oam_addr = value;
-                }
+            // Sets the OAM address for subsequent OAMDATA writes
+            OAMADDR if !self.is_rendering() => {
+                self.oam_addr = value;
             }
 
-            OAMDATA => {
-                // Writes a byte to OAM at the current OAM address, then increments the OAM address
-                if !self.is_rendering() {
-                    self.oam_data[self.oam_addr as usize] = value;
-                    self.oam_addr = self.oam_addr.wrapping_add(1);
-                }
+            // Writes a byte to OAM at the current OAM address, then increments the OAM address
+            OAMDATA if !self.is_rendering() => {
+                self.write_oam(self.oam_addr, value);
+                self.oam_addr = self.oam_addr.wrapping_add(1);
             }
 
             PPUSCROLL => {
@@ -327,6 +396,7 @@ impl Ppu {
             PPUDATA => {
                 let addr = self.registers.v;
                 self.ppu_bus.write_u8(addr, value);
+                self.watch.record(AccessKind::Write, addr & 0x3fff, value);
 
                 let increment = self.registers.ppu_ctrl.get_increment_value();
                 self.registers.v += increment;
@@ -344,17 +414,68 @@ impl Ppu {
             && self.registers.ppu_status.vblank() == 0
     }
 
+    fn rendering_enabled(&self) -> bool {
+        self.registers.ppu_mask.show_background() == 1
+            || self.registers.ppu_mask.show_sprites() == 1
+    }
+
+    /// The visible scanlines and the pre-render scanline, which fetch when rendering.
+    fn is_render_line(&self) -> bool {
+        self.current_scanline < 240 || self.current_scanline == 261
+    }
+
     pub fn tick(&mut self) {
-        let rendering_enabled = self.registers.ppu_mask.show_background() == 1
-            || self.registers.ppu_mask.show_sprites() == 1;
+        self.ppu_bus.tick();
+        let rendering_enabled = self.rendering_enabled();
         match self.current_scanline {
             0..=239 | 261 => {
-                if self.current_scanline == 0 && self.current_cycle == 0 {
+                // odd frames are a dot shorter while rendering
+                if self.current_scanline == 0
+                    && self.current_cycle == 0
+                    && self.odd_frame
+                    && rendering_enabled
+                {
                     self.current_cycle = 1;
                 }
 
-                if self.registers.ppu_mask.show_sprites() == 1 && self.current_cycle == 320 {
-                    self.do_sprite_evaluation();
+                if rendering_enabled {
+                    if self.oam_corrupt_rows != 0 {
+                        self.corrupt_oam();
+                    }
+                    // nothing is evaluated on the pre-render scanline
+                    match self.current_cycle {
+                        1..=64 if self.current_scanline < 240 => self.clear_secondary_oam(),
+                        65..=256 if self.current_scanline < 240 => self.evaluate_sprites(),
+                        257..=320 => self.oam_addr = 0,
+                        _ => self.oam_latch = self.secondary_oam[0],
+                    }
+                }
+
+                if let 257..=320 = self.current_cycle
+                    && rendering_enabled
+                {
+                    // sprite fetches for the next scanline, 8 dots per sprite slot
+                    let slot = ((self.current_cycle - 257) / 8) as usize;
+                    let byte = ((self.current_cycle - 257) & 0b111).min(3) as usize;
+                    self.oam_latch = self.secondary_oam[slot * 4 + byte];
+                    match (self.current_cycle - 257) & 0b111 {
+                        0 | 2 => {
+                            // the background's nametable fetches carry on, unused
+                            self.ppu_bus.read_u8(0x2000 | (self.registers.v & 0x0FFF));
+                        }
+                        3 => self.load_sprite_latches(slot),
+                        4 => {
+                            let addr = self.get_sprite_pattern_address(slot);
+                            let data = self.ppu_bus.read_u8(addr);
+                            self.sprite_shifter_lobytes[slot] = self.sprite_pattern_row(slot, data);
+                        }
+                        6 => {
+                            let addr = self.get_sprite_pattern_address(slot) + 8;
+                            let data = self.ppu_bus.read_u8(addr);
+                            self.sprite_shifter_hibytes[slot] = self.sprite_pattern_row(slot, data);
+                        }
+                        _ => (),
+                    }
                 }
 
                 if self.current_scanline == 261 {
@@ -398,16 +519,14 @@ impl Ppu {
                                 self.bg_shifter_attribute_lobyte =
                                     (self.bg_shifter_attribute_lobyte & 0xff00) | 0xff;
                             } else {
-                                self.bg_shifter_attribute_lobyte =
-                                    (self.bg_shifter_attribute_lobyte & 0xff00) | 0x00;
+                                self.bg_shifter_attribute_lobyte &= 0xff00;
                             }
 
                             if self.bg_attribute_byte & 0b10 != 0 {
                                 self.bg_shifter_attribute_hibyte =
                                     (self.bg_shifter_attribute_hibyte & 0xff00) | 0xff;
                             } else {
-                                self.bg_shifter_attribute_hibyte =
-                                    (self.bg_shifter_attribute_hibyte & 0xff00) | 0x00;
+                                self.bg_shifter_attribute_hibyte &= 0xff00;
                             }
 
                             // find next nametable byte
@@ -428,7 +547,7 @@ impl Ppu {
                                 .registers
                                 .ppu_ctrl
                                 .get_background_pattern_table_address()
-                                + (self.bg_nametable_byte as u16 * 16 + 0)
+                                + self.bg_nametable_byte as u16 * 16
                                 + parsed_v.fine_y() as u16;
                             self.bg_pattern_lsbits = self.ppu_bus.read_u8(addr);
                         }
@@ -496,15 +615,13 @@ impl Ppu {
                 }
             }
 
-            241 => {
-                // vblank scanlines
-                if self.current_cycle == 1 {
-                    // Entering VBlank
-                    self.registers.ppu_status.set_vblank(1);
+            // vblank scanlines
+            241 if self.current_cycle == 1 => {
+                // Entering VBlank
+                self.registers.ppu_status.set_vblank(1);
 
-                    if self.registers.ppu_ctrl.vblank_nmi_enable() == 1 {
-                        self.call_nmi();
-                    }
+                if self.registers.ppu_ctrl.vblank_nmi_enable() == 1 {
+                    self.call_nmi();
                 }
             }
 
@@ -512,6 +629,8 @@ impl Ppu {
         }
 
         if self.current_cycle >= 1 && self.current_scanline < 240 && self.current_cycle <= 256 {
+            self.opaque_bg_pixel_table[self.current_scanline as usize]
+                [(self.current_cycle - 1) as usize] = false;
             if self.registers.ppu_mask.show_background() == 1 {
 
                 let bit_selector = 0x8000 >> (self.registers.x as u16);
@@ -556,7 +675,7 @@ impl Ppu {
                     color,
                 );
             }
-            if self.registers.ppu_mask.show_sprites() == 1 && self.current_scanline >= 1 {
+            if rendering_enabled {
                 self.render_sprites(
                     (self.current_cycle - 1) as u16,
                     self.current_scanline as u16,
@@ -571,105 +690,268 @@ impl Ppu {
 
             if self.current_scanline > 261 {
                 self.current_scanline = 0;
+                self.odd_frame = !self.odd_frame;
                 self.informed_frame_ready = false;
             }
         }
     }
 
     fn render_sprites(&mut self, current_pixel_x: u16, current_pixel_y: u16) {
-        for i in 0..self.scanline_sprites_count {
-            let sprite = self.scanline_sprites[i];
-            let x = sprite.get_x() as u16;
-            if current_pixel_x >= x && current_pixel_x < x + 8 {
-                self.render_sprite(current_pixel_x, current_pixel_y, sprite);
-            }
+        let Some((pixel_color, attributes, is_sprite_0)) = self.next_sprite_pixel() else {
+            return;
+        };
+        if self.registers.ppu_mask.show_sprites() == 0 {
+            return;
+        }
+
+        let bg_opaque =
+            self.opaque_bg_pixel_table[current_pixel_y as usize][current_pixel_x as usize];
+        if is_sprite_0 && bg_opaque {
+            self.registers.ppu_status.set_sprite_zero_hit(1);
+        }
+
+        if attributes.priority() == 0 || !bg_opaque {
+            let pallette_table = PALLETTE_TABLE_START + 0x10 + (attributes.pallette() as u16 * 4);
+            let pallette_value = self.get_pallette_value(pallette_table, pixel_color as u16);
+            self.draw_pixel(current_pixel_x, current_pixel_y, pallette_value as usize);
         }
     }
 
-    fn do_sprite_evaluation(&mut self) {
-        self.scanline_sprites_count = 0;
-        let mut oam_reader = BufReader::new(Cursor::new(&self.oam_data));
+    /*
+    Clocks the sprite output units for one pixel. A unit counts its X down to 0 and then
+    shifts out its pattern. The first opaque unit wins, so the sprite earliest in OAM is in
+    front, whatever its background priority.
+    */
+    fn next_sprite_pixel(&mut self) -> Option<(u8, OAMSpriteAttributes, bool)> {
+        let mut pixel = None;
+        for slot in 0..8 {
+            if self.sprite_x_counters[slot] > 0 {
+                self.sprite_x_counters[slot] -= 1;
+                continue;
+            }
+
+            let pixel_color = ((self.sprite_shifter_hibytes[slot] >> 7) << 1)
+                | (self.sprite_shifter_lobytes[slot] >> 7);
+            self.sprite_shifter_lobytes[slot] <<= 1;
+            self.sprite_shifter_hibytes[slot] <<= 1;
 
-        let mut bytes = [0u8; 4];
+            if pixel_color != 0 && pixel.is_none() {
+                let is_sprite_0 = slot == 0 && self.sprite_zero_loaded;
+                pixel = Some((pixel_color, self.sprite_attributes[slot], is_sprite_0));
+            }
+        }
+        pixel
+    }
 
-        for i in 0..64 {
-            oam_reader.read_exact(&mut bytes).unwrap();
-            let sprite = OAMSprite::from_bytes(&bytes, i == 0);
+    fn read_oam(&mut self, addr: u8) -> u8 {
+        self.refresh_oam_row(addr);
+        self.oam_data[addr as usize]
+    }
 
-            let next_scanline = ((self.current_scanline + 1) % 262) as u16; // it's aight to cast because of scanline range
-            let sprite_height = self.registers.ppu_ctrl.get_sprite_height() as u16;
+    fn write_oam(&mut self, addr: u8, data: u8) {
+        self.refresh_oam_row(addr);
+        self.oam_data[addr as usize] = data;
+    }
 
-            if self.scanline_sprites_count >= 8 {
-                self.registers.ppu_status.set_sprite_overflow(1);
+    /*
+    A row nothing has touched for too long has lost its contents. What decayed DRAM reads
+    back as varies between consoles, $FF here, which also keeps its sprites off screen.
+    */
+    fn refresh_oam_row(&mut self, addr: u8) {
+        let row = addr as usize / 8;
+        let now = self.ppu_bus.cycle();
+        if now - self.oam_refreshed[row] > OAM_DECAY_DOTS {
+            self.oam_data[row * 8..row * 8 + 8].fill(0xff);
+        }
+        self.oam_refreshed[row] = now;
+    }
+
+    /*
+    Turning rendering off in the middle of a scanline leaves the OAM row at the secondary
+    OAM address the PPU was using to be overwritten with row 0, which happens once rendering
+    is back on.
+    */
+    fn flag_oam_corruption(&mut self) {
+        let row = match self.current_cycle {
+            1..=256 => self.secondary_oam_addr & 0x1f,
+            257..=320 => {
+                let dot = self.current_cycle - 257;
+                (dot / 8 * 4 + (dot & 0b111).min(3)) as u8
             }
-            if sprite.get_rendered_y() <= next_scanline
-                && next_scanline < sprite.get_rendered_y() + sprite_height
-                && self.scanline_sprites_count < 8
-                && sprite.get_y() > 1
-            {
-                self.scanline_sprites[self.scanline_sprites_count] = sprite;
-                self.scanline_sprites_count += 1;
+            _ => return,
+        };
+        self.oam_corrupt_rows |= 1 << row;
+    }
+
+    fn corrupt_oam(&mut self) {
+        for row in 1..OAM_ROW_COUNT {
+            if self.oam_corrupt_rows & (1 << row) != 0 {
+                self.oam_data.copy_within(0..8, row * 8);
             }
         }
+        self.oam_corrupt_rows = 0;
     }
 
-    fn render_sprite(&mut self, current_pixel_x: u16, current_pixel_y: u16, sprite: OAMSprite) {
-        // reverse this to give the first sprite most priority
-        let mut current_sprite_line = self.current_scanline as u8 - sprite.get_rendered_y() as u8;
-        if sprite.get_attributes().flip_vertical() == 1 {
-            current_sprite_line =
-                self.registers.ppu_ctrl.get_sprite_height() - 1 - current_sprite_line;
+    /// Dots 1-64 fill secondary OAM with $FF, and OAMDATA reads $FF meanwhile.
+    fn clear_secondary_oam(&mut self) {
+        self.oam_latch = 0xff;
+        if self.current_cycle.is_multiple_of(2) {
+            self.secondary_oam_addr = (self.current_cycle / 2 - 1) as u8;
+            self.secondary_oam[self.secondary_oam_addr as usize] = self.oam_latch;
         }
+    }
 
-        let mut current_sprite_x = current_pixel_x as u8 - sprite.get_x();
-        if sprite.get_attributes().flip_horizontal() == 1 {
-            current_sprite_x = 7 - current_sprite_x
+    /*
+    Sprite evaluation
+
+    On dots 65-256 the PPU reads a byte of OAM on odd dots and writes it to secondary OAM on
+    even dots. OAMADDR is the index, sprite n in bits 2-7 and byte m in bits 0-1, so
+    evaluation starts at whatever OAMADDR held at dot 65, and the first sprite it looks at
+    is the one sprite 0 hits are checked for.
+
+    A byte read as a Y coordinate that puts the sprite on this scanline gets the sprite's
+    other three bytes copied after it. Once eight sprites are in, writes to secondary OAM turn
+    into reads of it, and the search for a ninth sprite increments m along with n, so it
+    reads tiles, attributes and X positions as Y coordinates. That is where the overflow
+    flag's false positives and negatives come from. After all 64 sprites, or a ninth found,
+    evaluation keeps stepping n without doing anything.
+    */
+    fn evaluate_sprites(&mut self) {
+        if self.current_cycle == 65 {
+            self.secondary_oam_addr = 0;
+            self.sprite_in_range = false;
+            self.sprite_evaluation_done = false;
+            self.overflow_bytes_left = 0;
+            self.sprite_zero_in_range = false;
         }
 
-        let pallette_table =
-            PALLETTE_TABLE_START + 0x10 + (sprite.get_attributes().pallette() as u16 * 4);
+        if !self.current_cycle.is_multiple_of(2) {
+            self.oam_latch = self.read_oam(self.oam_addr);
+            return;
+        }
 
-        let pattern_table = if self.registers.ppu_ctrl.get_sprite_height() == 8 {
-            self.registers.ppu_ctrl.get_sprite_pattern_table_address()
+        let secondary_oam_full = self.secondary_oam_addr as usize >= self.secondary_oam.len();
+        if self.sprite_evaluation_done {
+            self.oam_addr = self.oam_addr.wrapping_add(4);
+            if secondary_oam_full {
+                self.oam_latch = self.secondary_oam[self.secondary_oam_addr as usize & 0x1f];
+            }
+            return;
+        }
+
+        let y = self.oam_latch as i16;
+        let sprite_height = self.registers.ppu_ctrl.get_sprite_height() as i16;
+        if !self.sprite_in_range && (y..y + sprite_height).contains(&self.current_scanline) {
+            self.sprite_in_range = true;
+            if self.current_cycle == 66 {
+                self.sprite_zero_in_range = true;
+            }
+        }
+
+        if !secondary_oam_full {
+            self.secondary_oam[self.secondary_oam_addr as usize] = self.oam_latch;
+            if self.sprite_in_range {
+                self.secondary_oam_addr += 1;
+                let (addr, wrapped) = self.oam_addr.overflowing_add(1);
+                self.oam_addr = addr;
+                if addr & 0x03 == 0 {
+                    self.sprite_in_range = false;
+                    self.sprite_evaluation_done = wrapped;
+                }
+            } else {
+                let (addr, wrapped) = (self.oam_addr & 0xfc).overflowing_add(4);
+                self.oam_addr = addr;
+                self.sprite_evaluation_done = wrapped;
+            }
         } else {
-            // == 16
-            if sprite.get_tile_index() & 1 == 1 {
-                0x1000
+            self.oam_latch = self.secondary_oam[self.secondary_oam_addr as usize & 0x1f];
+            if self.sprite_in_range {
+                // a ninth sprite, read the rest of it and stop
+                self.registers.ppu_status.set_sprite_overflow(1);
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+                if self.overflow_bytes_left == 0 {
+                    self.overflow_bytes_left = 3;
+                } else {
+                    self.overflow_bytes_left -= 1;
+                    if self.overflow_bytes_left == 0 {
+                        self.oam_addr &= 0xfc;
+                        self.sprite_evaluation_done = true;
+                    }
+                }
             } else {
-                0
+                let (addr, wrapped) = (self.oam_addr & 0xfc).overflowing_add(4);
+                self.oam_addr = addr | (self.oam_addr.wrapping_add(1) & 0x03);
+                self.sprite_evaluation_done = wrapped;
             }
-        };
+        }
+    }
 
-        let tile_index = if self.registers.ppu_ctrl.get_sprite_height() == 16
-            && ((current_sprite_line >= 8 && sprite.get_attributes().flip_vertical() == 0)
-                || (current_sprite_line < 8 && sprite.get_attributes().flip_vertical() == 1))
-        {
-            sprite.get_tile_index() & 0xfe + 1
-        } else {
-            sprite.get_tile_index()
-        } as u16;
+    /*
+    Which row of the sprite in a secondary OAM slot shows on the next scanline, if any.
+    Empty slots hold $FF, which is never in range. The pre-render line evaluates nothing, so
+    what it fetches for scanline 0 is whatever scanline 239 left in secondary OAM, and the
+    hardware draws no sprites there. That's also why sprites show from the line after their Y.
+    */
+    fn sprite_row(&self, slot: usize) -> Option<u8> {
+        if self.current_scanline >= 240 {
+            return None;
+        }
+        let y = self.secondary_oam[slot * 4] as i16;
+        let sprite_height = self.registers.ppu_ctrl.get_sprite_height() as i16;
+        let row = self.current_scanline - y;
+        (0..sprite_height).contains(&row).then_some(row as u8)
+    }
 
-        let current_tile_y = current_sprite_line as u16 % 8;
-        let current_tile_x = current_sprite_x as u16;
+    /*
+    Address of the low pattern plane row fetched for a sprite slot on the next scanline.
+    Empty slots still fetch tile $FF, which cartridges watching the PPU address bus rely on.
+    */
+    fn get_sprite_pattern_address(&self, slot: usize) -> u16 {
+        let sprite_height = self.registers.ppu_ctrl.get_sprite_height();
+        let Some(mut current_sprite_line) = self.sprite_row(slot) else {
+            return if sprite_height == 8 {
+                self.registers.ppu_ctrl.get_sprite_pattern_table_address() + 0xff * 16
+            } else {
+                0x1000 + 0xfe * 16
+            };
+        };
 
-        let pixel_color =
-            self.get_pattern_pixel(pattern_table, tile_index, current_tile_y, current_tile_x);
+        let attributes = OAMSpriteAttributes::from_bytes([self.secondary_oam[slot * 4 + 2]]);
+        if attributes.flip_vertical() == 1 {
+            current_sprite_line = sprite_height - 1 - current_sprite_line;
+        }
 
-        if sprite.is_sprite_0()
-            && self.opaque_bg_pixel_table[current_pixel_y as usize][current_pixel_x as usize]
-            && pixel_color != 0
-        {
-            self.registers.ppu_status.set_sprite_zero_hit(1);
+        let tile_index = self.secondary_oam[slot * 4 + 1] as u16;
+        if sprite_height == 8 {
+            self.registers.ppu_ctrl.get_sprite_pattern_table_address()
+                + tile_index * 16
+                + current_sprite_line as u16
+        } else {
+            // bit 0 of the index picks the pattern table, the top half is the even tile
+            let pattern_table = (tile_index & 1) * 0x1000;
+            let tile_index = (tile_index & 0xfe) + (current_sprite_line >= 8) as u16;
+            pattern_table + tile_index * 16 + (current_sprite_line & 0x07) as u16
         }
+    }
 
-        if pixel_color != 0
-            && (sprite.get_attributes().priority() == 0
-                || (sprite.get_attributes().priority() == 1
-                    && !self.opaque_bg_pixel_table[current_pixel_y as usize]
-                        [current_pixel_x as usize]))
-        {
-            let pallette_value = self.get_pallette_value(pallette_table, pixel_color as u16);
-            self.draw_pixel(current_pixel_x, current_pixel_y, pallette_value as usize);
+    /// The attribute byte and X position are read from secondary OAM during the fetches.
+    fn load_sprite_latches(&mut self, slot: usize) {
+        self.sprite_attributes[slot] =
+            OAMSpriteAttributes::from_bytes([self.secondary_oam[slot * 4 + 2]]);
+        self.sprite_x_counters[slot] = self.secondary_oam[slot * 4 + 3];
+        if slot == 0 {
+            self.sprite_zero_loaded = self.sprite_zero_in_range;
+        }
+    }
+
+    /// A fetched pattern byte as it goes into a sprite shifter, leftmost pixel first.
+    fn sprite_pattern_row(&self, slot: usize, data: u8) -> u8 {
+        if self.sprite_row(slot).is_none() {
+            0
+        } else if self.sprite_attributes[slot].flip_horizontal() == 1 {
+            data.reverse_bits()
+        } else {
+            data
         }
     }
 
@@ -682,40 +964,145 @@ impl Ppu {
         }
     }
 
-    fn get_pattern_pixel(
-        &mut self,
-        pattern_table: u16,
-        tile_index: u16,
-        fine_y: u16,
-        fine_x: u16,
-    ) -> u8 {
-        let pattern_lsb_address = pattern_table + (tile_index * 16 + 0) + fine_y;
-        let pattern_msb_address = pattern_table + (tile_index * 16 + 8) + fine_y;
-
-        let pattern_byte_lsb = self.ppu_bus.read_u8(pattern_lsb_address);
-        let pattern_byte_msb = self.ppu_bus.read_u8(pattern_msb_address);
-
-        let current_pixel_color_lsb = select_bit_n(pattern_byte_lsb, fine_x as u8);
-        let current_pixel_color_msb = select_bit_n(pattern_byte_msb, fine_x as u8);
-        let pixel_color = current_pixel_color_lsb + (current_pixel_color_msb << 1);
-        pixel_color
-    }
-
     fn draw_pixel(&mut self, current_pixel_x: u16, current_pixel_y: u16, mut color: usize) {
         if self.registers.ppu_mask.grayscale() == 1 {
             color &= 0x30;
         }
 
-        let pixel_color = COLORS[color];
+        self.set_pixel(current_pixel_y as usize * 256 + current_pixel_x as usize, color);
+    }
 
-        let current_pixel_index = current_pixel_y as usize * 256 * 4 + current_pixel_x as usize * 4;
-        self.screen_pixelbuffer[current_pixel_index + 0] = pixel_color.0;
-        self.screen_pixelbuffer[current_pixel_index + 1] = pixel_color.1;
-        self.screen_pixelbuffer[current_pixel_index + 2] = pixel_color.2;
-        self.screen_pixelbuffer[current_pixel_index + 3] = 0xff;
+    fn set_pixel(&mut self, index: usize, color: usize) {
+        let pixel_color = COLORS[color];
+        self.screen_colors[index] = color as u8;
+        self.screen_pixelbuffer[index * 4] = pixel_color.0;
+        self.screen_pixelbuffer[index * 4 + 1] = pixel_color.1;
+        self.screen_pixelbuffer[index * 4 + 2] = pixel_color.2;
+        self.screen_pixelbuffer[index * 4 + 3] = 0xff;
     }
 }
 
-fn select_bit_n(x: u8, n: u8) -> u8 {
-    (x >> (7 - n)) & 1
+/*
+The pixel buffer is display output rather than machine state and is left out; a restored
+state shows black until its next frame is drawn. The opaque background table is packed to
+one bit per pixel.
+*/
+impl Snapshot for Ppu {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.registers.save_state(state);
+        self.ppu_bus.save_state(state);
+
+        state.write_u8(self.read_buffer);
+        state.write_u16(self.current_scanline as u16);
+        state.write_u64(self.current_cycle);
+        state.write_bool(self.had_pre_render_scanline);
+        state.write_bool(self.odd_frame);
+        state.write_bool(self.informed_frame_ready);
+        state.write_bool(self.should_nmi);
+
+        state.write_bytes(&self.oam_data);
+        state.write_u8(self.oam_addr);
+        for refreshed in self.oam_refreshed {
+            state.write_u64(refreshed);
+        }
+        state.write_u32(self.oam_corrupt_rows);
+
+        state.write_bytes(&self.secondary_oam);
+        state.write_u8(self.secondary_oam_addr);
+        state.write_u8(self.oam_latch);
+        state.write_bool(self.sprite_in_range);
+        state.write_bool(self.sprite_evaluation_done);
+        state.write_u8(self.overflow_bytes_left);
+        state.write_bool(self.sprite_zero_in_range);
+
+        state.write_u8(self.bg_nametable_byte);
+        state.write_u8(self.bg_attribute_byte);
+        state.write_u8(self.bg_pattern_lsbits);
+        state.write_u8(self.bg_pattern_msbits);
+        state.write_u16(self.bg_shifter_pattern_lobyte);
+        state.write_u16(self.bg_shifter_pattern_hibyte);
+        state.write_u16(self.bg_shifter_attribute_lobyte);
+        state.write_u16(self.bg_shifter_attribute_hibyte);
+
+        state.write_bytes(&self.sprite_shifter_lobytes);
+        state.write_bytes(&self.sprite_shifter_hibytes);
+        for attributes in &self.sprite_attributes {
+            state.write_u8(attributes.into_bytes()[0]);
+        }
+        state.write_bytes(&self.sprite_x_counters);
+        state.write_bool(self.sprite_zero_loaded);
+
+        for row in &self.opaque_bg_pixel_table {
+            for pixels in row.chunks(8) {
+                let packed = pixels
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |packed, (i, &opaque)| packed | ((opaque as u8) << i));
+                state.write_u8(packed);
+            }
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.registers.load_state(state)?;
+        self.ppu_bus.load_state(state)?;
+
+        self.read_buffer = state.read_u8()?;
+        self.current_scanline = state.read_u16()? as i16;
+        self.current_cycle = state.read_u64()?;
+        self.had_pre_render_scanline = state.read_bool()?;
+        self.odd_frame = state.read_bool()?;
+        self.informed_frame_ready = state.read_bool()?;
+        self.should_nmi = state.read_bool()?;
+
+        state.read_bytes(&mut self.oam_data)?;
+        self.oam_addr = state.read_u8()?;
+        for refreshed in &mut self.oam_refreshed {
+            *refreshed = state.read_u64()?;
+        }
+        self.oam_corrupt_rows = state.read_u32()?;
+
+        state.read_bytes(&mut self.secondary_oam)?;
+        self.secondary_oam_addr = state.read_u8()?;
+        self.oam_latch = state.read_u8()?;
+        self.sprite_in_range = state.read_bool()?;
+        self.sprite_evaluation_done = state.read_bool()?;
+        self.overflow_bytes_left = state.read_u8()?;
+        self.sprite_zero_in_range = state.read_bool()?;
+
+        self.bg_nametable_byte = state.read_u8()?;
+        self.bg_attribute_byte = state.read_u8()?;
+        self.bg_pattern_lsbits = state.read_u8()?;
+        self.bg_pattern_msbits = state.read_u8()?;
+        self.bg_shifter_pattern_lobyte = state.read_u16()?;
+        self.bg_shifter_pattern_hibyte = state.read_u16()?;
+        self.bg_shifter_attribute_lobyte = state.read_u16()?;
+        self.bg_shifter_attribute_hibyte = state.read_u16()?;
+
+        state.read_bytes(&mut self.sprite_shifter_lobytes)?;
+        state.read_bytes(&mut self.sprite_shifter_hibytes)?;
+        for attributes in &mut self.sprite_attributes {
+            *attributes = OAMSpriteAttributes::from_bytes([state.read_u8()?]);
+        }
+        state.read_bytes(&mut self.sprite_x_counters)?;
+        self.sprite_zero_loaded = state.read_bool()?;
+
+        for row in &mut self.opaque_bg_pixel_table {
+            for pixels in row.chunks_mut(8) {
+                let packed = state.read_u8()?;
+                for (i, opaque) in pixels.iter_mut().enumerate() {
+                    *opaque = packed & (1 << i) != 0;
+                }
+            }
+        }
+
+        if !(0..=261).contains(&self.current_scanline)
+            || self.current_cycle > 340
+            || self.secondary_oam_addr as usize > self.secondary_oam.len()
+            || self.oam_refreshed.iter().any(|&refreshed| refreshed > self.ppu_bus.cycle())
+        {
+            return Err(SaveStateError::Corrupt);
+        }
+        Ok(())
+    }
 }
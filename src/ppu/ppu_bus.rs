@@ -1,9 +1,30 @@
-use crate::memory::mapper::SharedMapper;
+use crate::{
+    memory::mapper::SharedMapper,
+    savestate::{SaveStateError, Snapshot, StateReader, StateWriter},
+};
 
-#[derive(Clone, Copy)]
+const PALLETTE_RAM_START: u16 = 0x3f00;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NametableArrangement {
     Vertical,
     Horizontal,
+    SingleScreenA, // all four nametables show the first 1K of CIRAM
+    SingleScreenB, // all four nametables show the second 1K of CIRAM
+    FourScreen,    // the cartridge's extra 2K of VRAM gives every nametable its own 1K
+    Mapped([NametableSource; 4]),
+}
+
+/*
+Where one of the four 1K nametables at $2000-$2FFF is read from and written to. `Vram`
+pages 0 and 1 are CIRAM, 2 and 3 the extra VRAM of four-screen boards. `Cartridge` hands
+the access to the mapper's `ppu_map_read`/`ppu_map_write`, for boards that put CHR ROM or
+RAM of their own there.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NametableSource {
+    Vram(u8),
+    Cartridge,
 }
 
 pub struct PPUBus {
@@ -11,6 +32,7 @@ pub struct PPUBus {
     nametable_ram: [u8; 0x1000],
     pallette_ram: [u8; 0x20],
     nametable_arrangement: NametableArrangement,
+    cycle: u64, // PPU dots since power on, lets the mapper time address bus activity
 }
 
 impl PPUBus {
@@ -20,9 +42,18 @@ impl PPUBus {
             nametable_ram: [0; 0x1000],
             pallette_ram: [0; 0x20],
             nametable_arrangement,
+            cycle: 0,
         }
     }
 
+    pub fn tick(&mut self) {
+        self.cycle = self.cycle.wrapping_add(1);
+    }
+
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
     pub fn set_mapper(&mut self, mapper: SharedMapper) {
         self.mapper = Some(mapper);
     }
@@ -31,22 +62,40 @@ impl PPUBus {
         self.nametable_arrangement = mode;
     }
     
+    /*
+    The arrangement from the header applies unless the mapper controls mirroring itself.
+    Four-screen boards don't connect the mapper's mirroring output, so the header wins there.
+    */
     pub fn get_nametable_arrangement(&self) -> NametableArrangement {
-        self.nametable_arrangement
+        if self.nametable_arrangement == NametableArrangement::FourScreen {
+            return NametableArrangement::FourScreen;
+        }
+        self.mapper
+            .as_ref()
+            .and_then(|mapper| mapper.borrow().nametable_arrangement())
+            .unwrap_or(self.nametable_arrangement)
     }
+
     pub fn read_u8(&self, addr: u16) -> u8 {
-        if self.mapper.is_none() {
-            panic!("Attempted to read from PPU bus before loading ROM");
-        }
-        let mapper = self.mapper.as_ref().unwrap().borrow();
+        self.notify_mapper(addr);
+        self.peek_u8(addr)
+    }
 
-        
+    /// Reads without the mapper seeing the access, for debugging tools.
+    pub fn peek_u8(&self, addr: u16) -> u8 {
         match addr {
-            0..=0x1fff => mapper.ppu_map_read(addr),
-            0x2000..=0x2fff => {
-                let nametable_addr = addr as usize & 0x0fff;
-                self.nametable_ram[self.apply_nametable_arrangement(nametable_addr)]
-            }
+            0..=0x1fff => self
+                .mapper
+                .as_ref()
+                .map_or(0, |mapper| mapper.borrow().ppu_map_read(addr)),
+            0x2000..=0x3eff => match self.nametable_source(addr) {
+                NametableSource::Vram(page) => {
+                    self.nametable_ram[Self::nametable_ram_offset(page, addr)]
+                }
+                NametableSource::Cartridge => self.mapper.as_ref().map_or(0, |mapper| {
+                    mapper.borrow().ppu_map_read(0x2000 | (addr & 0x0fff))
+                }),
+            },
             0x3f00..=0x3fff => {
                 let mut pallette_addr = addr as usize & 0x1f;
 
@@ -64,34 +113,57 @@ impl PPUBus {
         }
     }
 
-    fn apply_nametable_arrangement(&self, mut nametable_addr: usize) -> usize {
-        match self.nametable_arrangement {
-            NametableArrangement::Vertical => {
-                match nametable_addr {
-                    ..0x800 => nametable_addr & 0x3ff,
-                    0x800.. => (nametable_addr & 0x3ff) + 0x400
-                }
-            }
-            NametableArrangement::Horizontal => {
-                nametable_addr & 0x7ff
-            }
+    /*
+    Palette accesses are internal to the PPU and never reach the cartridge
+    */
+    fn notify_mapper(&self, addr: u16) {
+        if addr < PALLETTE_RAM_START
+            && let Some(mapper) = &self.mapper
+        {
+            mapper.borrow_mut().ppu_bus_activity(addr, self.cycle);
         }
     }
-    
-    pub fn write_u8(&mut self, addr: u16, data: u8) {
-        if self.mapper.is_none() {
-            panic!("Attempted to write to PPU bus before loading ROM");
+
+    /*
+    $3000-$3EFF mirrors $2000-$2EFF, so only bits 10-11 pick the nametable
+    */
+    fn nametable_source(&self, addr: u16) -> NametableSource {
+        let nametable = (addr as usize >> 10) & 0x03;
+        match self.get_nametable_arrangement() {
+            NametableArrangement::Vertical => NametableSource::Vram(nametable as u8 >> 1),
+            NametableArrangement::Horizontal => NametableSource::Vram(nametable as u8 & 0x01),
+            NametableArrangement::SingleScreenA => NametableSource::Vram(0),
+            NametableArrangement::SingleScreenB => NametableSource::Vram(1),
+            NametableArrangement::FourScreen => NametableSource::Vram(nametable as u8),
+            NametableArrangement::Mapped(sources) => sources[nametable],
         }
-        let mut mapper = self.mapper.as_ref().unwrap().borrow_mut();
+    }
+
+    fn nametable_ram_offset(page: u8, addr: u16) -> usize {
+        (page as usize & 0x03) * 0x400 + (addr as usize & 0x3ff)
+    }
+
+    pub fn write_u8(&mut self, addr: u16, data: u8) {
+        self.notify_mapper(addr);
 
         match addr {
             0..=0x1fff => {
-                mapper.ppu_map_write(addr, data);
-            }
-            0x2000..=0x2fff => {
-                let nametable_addr = addr as usize & 0x0fff;
-                self.nametable_ram[self.apply_nametable_arrangement(nametable_addr)] = data;
+                if let Some(mapper) = &self.mapper {
+                    mapper.borrow_mut().ppu_map_write(addr, data);
+                }
             }
+            0x2000..=0x3eff => match self.nametable_source(addr) {
+                NametableSource::Vram(page) => {
+                    self.nametable_ram[Self::nametable_ram_offset(page, addr)] = data;
+                }
+                NametableSource::Cartridge => {
+                    if let Some(mapper) = &self.mapper {
+                        mapper
+                            .borrow_mut()
+                            .ppu_map_write(0x2000 | (addr & 0x0fff), data);
+                    }
+                }
+            },
             0x3f00..=0x3fff => {
                 let mut pallette_addr = addr as usize & 0x1f;
                 if pallette_addr == 0x10
@@ -108,3 +180,22 @@ impl PPUBus {
         }
     }
 }
+
+/*
+The cartridge and the header arrangement come from the ROM, only the console's own RAM is
+saved here.
+*/
+impl Snapshot for PPUBus {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.nametable_ram);
+        state.write_bytes(&self.pallette_ram);
+        state.write_u64(self.cycle);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        state.read_bytes(&mut self.nametable_ram)?;
+        state.read_bytes(&mut self.pallette_ram)?;
+        self.cycle = state.read_u64()?;
+        Ok(())
+    }
+}
@@ -1,8 +1,11 @@
 use modular_bitfield::prelude::*;
 
-use crate::ppu::{
-    address_register::AddressRegister, ppu_ctrl::PPUCtrl, ppu_mask::PPUMask,
-    ppu_status::PPUStatus,
+use crate::{
+    ppu::{
+        address_register::AddressRegister, ppu_ctrl::PPUCtrl, ppu_mask::PPUMask,
+        ppu_status::PPUStatus,
+    },
+    savestate::{SaveStateError, Snapshot, StateReader, StateWriter},
 };
 
 #[derive(Clone, Copy)]
@@ -12,6 +15,7 @@ pub struct ScrollRegister {
     pub coarse_y: B5,
     pub nametable_select: B2,
     pub fine_y: B3,
+    #[skip]
     unused: B1,
 }
 
@@ -31,6 +35,7 @@ pub struct PpuRegisters {
     pub ppu_ctrl: PPUCtrl,
     pub ppu_mask: PPUMask,
     pub ppu_status: PPUStatus,
+    #[allow(dead_code)]
     pub ppu_addr: AddressRegister,
     pub w: bool,
     pub v: u16,
@@ -53,7 +58,31 @@ impl PpuRegisters {
         }
     }
 
+    #[allow(dead_code)]
     pub fn get_nametable_address(&self) -> u16 {
         0x2000 + 0x400 * (ScrollRegister::from(self.v).nametable_select() as u16)
     }
 }
+
+impl Snapshot for PpuRegisters {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.ppu_ctrl.into_bytes()[0]);
+        state.write_u8(self.ppu_mask.into_bytes()[0]);
+        state.write_u8(self.ppu_status.into_bytes()[0]);
+        state.write_bool(self.w);
+        state.write_u16(self.v);
+        state.write_u16(self.t);
+        state.write_u8(self.x);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ppu_ctrl = PPUCtrl::from_bytes([state.read_u8()?]);
+        self.ppu_mask = PPUMask::from_bytes([state.read_u8()?]);
+        self.ppu_status = PPUStatus::from_bytes([state.read_u8()?]);
+        self.w = state.read_bool()?;
+        self.v = state.read_u16()? & 0x7fff;
+        self.t = state.read_u16()? & 0x7fff;
+        self.x = state.read_u8()? & 0b111;
+        Ok(())
+    }
+}
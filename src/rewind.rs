@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+
+/*
+Keeps recent save states so play can be stepped backwards frame by frame.
+
+Consecutive states are nearly identical, so only the newest one is kept whole. Each older
+state is stored as the XOR against the state after it, run length encoded. Rewinding XORs
+the newest state with the last delta, and so on backwards. The oldest deltas are dropped
+once the frame or byte budget is exceeded.
+*/
+pub struct RewindBuffer {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+
+    max_frames: usize,
+    max_bytes: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(max_frames: usize, max_bytes: usize) -> Self {
+        Self {
+            latest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+            max_frames,
+            max_bytes,
+        }
+    }
+
+    /// Records the state of the frame that just finished.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(latest) = &self.latest {
+            if latest.len() != state.len() {
+                // a different ROM, nothing before this point applies anymore
+                self.clear();
+            } else {
+                let delta = encode_delta(latest, &state);
+                self.delta_bytes += delta.len();
+                self.deltas.push_back(delta);
+            }
+        }
+        self.latest = Some(state);
+
+        while self.deltas.len() > self.max_frames || self.memory_usage() > self.max_bytes {
+            match self.deltas.pop_front() {
+                Some(delta) => self.delta_bytes -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Steps back one frame and returns that state, or `None` once the history runs out.
+    pub fn pop(&mut self) -> Option<&[u8]> {
+        let delta = self.deltas.pop_back()?;
+        self.delta_bytes -= delta.len();
+
+        let latest = self.latest.as_mut()?;
+        apply_delta(latest, &delta);
+        Some(latest)
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+    }
+
+    /// Number of frames that can still be stepped back.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.delta_bytes + self.latest.as_ref().map_or(0, Vec::len)
+    }
+}
+
+/*
+A delta is a list of (zero run, literal run) pairs over the XOR of both states, with the
+lengths written as LEB128 varints and the literal bytes following their length.
+*/
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut i = 0;
+
+    while i < old.len() {
+        let zeros_start = i;
+        while i < old.len() && old[i] == new[i] {
+            i += 1;
+        }
+        let literals_start = i;
+        while i < old.len() && old[i] != new[i] {
+            i += 1;
+        }
+
+        write_varint(&mut delta, literals_start - zeros_start);
+        write_varint(&mut delta, i - literals_start);
+        delta.extend((literals_start..i).map(|j| old[j] ^ new[j]));
+    }
+
+    delta
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut position = 0;
+    let mut delta = delta;
+
+    while !delta.is_empty() {
+        position += read_varint(&mut delta);
+        let literal_count = read_varint(&mut delta);
+
+        let (literals, rest) = delta.split_at(literal_count);
+        for (byte, literal) in state[position..position + literal_count]
+            .iter_mut()
+            .zip(literals)
+        {
+            *byte ^= literal;
+        }
+        position += literal_count;
+        delta = rest;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = input.split_first() {
+        *input = rest;
+        value |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    value
+}
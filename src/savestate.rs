@@ -0,0 +1,178 @@
+use std::{error::Error, fmt};
+
+pub const SAVE_STATE_VERSION: u16 = 10;
+const SAVE_STATE_MAGIC: &[u8; 4] = b"SNSS";
+
+/*
+A save state is a header followed by the state of every component, written in a fixed
+order by `Snapshot` implementations. The header carries the format version and the CRC32
+of the ROM file, so a state is never loaded into a different game.
+
+Bump SAVE_STATE_VERSION whenever a component changes what it writes.
+*/
+pub trait Snapshot {
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveStateError {
+    NoRom,
+    NotASaveState,
+    UnsupportedVersion(u16),
+    RomMismatch,
+    Truncated,
+    Corrupt,
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::NoRom => write!(f, "no ROM is loaded"),
+            SaveStateError::NotASaveState => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {version}")
+            }
+            SaveStateError::RomMismatch => write!(f, "save state was taken with a different ROM"),
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl Error for SaveStateError {}
+
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_header(&mut self, rom_checksum: u32) {
+        self.write_bytes(SAVE_STATE_MAGIC);
+        self.write_u16(SAVE_STATE_VERSION);
+        self.write_u32(rom_checksum);
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn read_header(&mut self, rom_checksum: u32) -> Result<(), SaveStateError> {
+        let mut magic = [0u8; 4];
+        self.read_bytes(&mut magic)
+            .map_err(|_| SaveStateError::NotASaveState)?;
+        if &magic != SAVE_STATE_MAGIC {
+            return Err(SaveStateError::NotASaveState);
+        }
+
+        let version = self.read_u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+
+        if self.read_u32()? != rom_checksum {
+            return Err(SaveStateError::RomMismatch);
+        }
+        Ok(())
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        let (bytes, rest) = self
+            .data
+            .split_first_chunk::<N>()
+            .ok_or(SaveStateError::Truncated)?;
+        self.data = rest;
+        Ok(*bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Corrupt),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    /// Fills `bytes` completely; buffers keep the size they were created with.
+    pub fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<(), SaveStateError> {
+        if self.data.len() < bytes.len() {
+            return Err(SaveStateError::Truncated);
+        }
+        let (head, rest) = self.data.split_at(bytes.len());
+        bytes.copy_from_slice(head);
+        self.data = rest;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// CRC32 (IEEE), the checksum ROM databases use to identify dumps.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
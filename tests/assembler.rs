@@ -0,0 +1,146 @@
+use simpleness::Nes;
+use simpleness::cpu::assembler::{assemble, assemble_nrom};
+use simpleness::cpu::disassembler::disassemble_one;
+use simpleness::cpu::instructions::OPCODE_MAP;
+
+fn assemble_ok(source: &str, origin: u16) -> Vec<u8> {
+    assemble(source, origin).unwrap_or_else(|err| panic!("{err}"))
+}
+
+#[test]
+fn documented_opcodes_round_trip_through_the_disassembler() {
+    for code in 0..=u8::MAX {
+        if OPCODE_MAP[&code].is_unofficial() {
+            continue;
+        }
+
+        let instruction = disassemble_one(&[code, 0x34, 0x12], 0xc000).unwrap();
+        assert_eq!(
+            assemble_ok(&instruction.to_string(), 0xc000),
+            instruction.bytes,
+            "{instruction}"
+        );
+    }
+}
+
+#[test]
+fn operand_sizes() {
+    assert_eq!(assemble_ok("LDA $10", 0), [0xa5, 0x10]);
+    assert_eq!(assemble_ok("LDA a:$10", 0), [0xad, 0x10, 0x00]);
+    assert_eq!(assemble_ok("LDA $0110", 0), [0xad, 0x10, 0x01]);
+    assert_eq!(assemble_ok("lda $10,x", 0), [0xb5, 0x10]);
+    // LDA has no zero page,Y form
+    assert_eq!(assemble_ok("LDA $10,Y", 0), [0xb9, 0x10, 0x00]);
+    assert_eq!(assemble_ok("STX $10,Y", 0), [0x96, 0x10]);
+    assert_eq!(assemble_ok("LDA ( $10 ) , y", 0), [0xb1, 0x10]);
+    assert_eq!(assemble_ok("LDA ($10,X)", 0), [0xa1, 0x10]);
+    assert_eq!(assemble_ok("LDA (2+3)*2", 0), [0xa5, 0x0a]);
+    assert_eq!(assemble_ok("ASL", 0), [0x0a]);
+    assert_eq!(assemble_ok("ASL A", 0), [0x0a]);
+    assert_eq!(assemble_ok("BRK", 0), [0x00, 0x00]);
+    assert_eq!(assemble_ok("NOP", 0), [0xea]);
+    assert_eq!(assemble_ok("SBC #1", 0), [0xe9, 0x01]);
+    assert_eq!(assemble_ok("LAX $10", 0), [0xa7, 0x10]);
+}
+
+#[test]
+fn labels_expressions_and_directives() {
+    let source = "
+        ptr = $20
+        .org $8000
+        start:  LDA #<table         ; forward references are absolute sized
+                STA ptr
+                LDA #>table
+                STA ptr+1
+        loop:   DEX
+                BNE loop
+                JMP (vector)
+        .org $8010
+        table:  .byte 1, -1, %101, 'A', \"hi\"
+        vector: .word start, * + 2, $1234 >> 4 | 1
+    ";
+
+    assert_eq!(
+        assemble_ok(source, 0x8000),
+        [
+            0xa9, 0x10, // LDA #<table
+            0x85, 0x20, // STA ptr
+            0xa9, 0x80, // LDA #>table
+            0x85, 0x21, // STA ptr+1
+            0xca, // DEX
+            0xd0, 0xfd, // BNE loop
+            0x6c, 0x16, 0x80, // JMP (vector)
+            0x00, 0x00, // .org padding
+            0x01, 0xff, 0x05, 0x41, 0x68, 0x69, // table
+            0x00, 0x80, 0x1a, 0x80, 0x23, 0x01, // vector
+        ]
+    );
+}
+
+#[test]
+fn errors_name_the_line() {
+    let error = |source| assemble(source, 0x8000).unwrap_err().to_string();
+
+    assert_eq!(error("NOP\nFOO"), "line 2: Unknown instruction: FOO");
+    assert_eq!(error("JMP nowhere"), "line 1: Unknown label: nowhere");
+    assert_eq!(error("a: NOP\na: NOP"), "line 2: a is defined twice");
+    assert_eq!(error("LDA #256"), "line 1: 256 doesn't fit in a byte");
+    assert_eq!(
+        error("STX $1234,X"),
+        "line 1: STX doesn't support this addressing mode"
+    );
+    assert_eq!(
+        error("BNE far\n.org $9000\nfar:"),
+        "line 1: Branch to $9000 is out of range"
+    );
+    assert_eq!(
+        error(".org $9000\n.org $8000"),
+        "line 2: .org $8000 would move back from $9000"
+    );
+}
+
+#[test]
+fn assembled_program_runs() {
+    let rom = assemble_nrom(
+        "
+        reset:  LDX #0
+                LDA #1
+        double: STA $0200,X     ; powers of two up to $80
+                ASL A
+                INX
+                BCC double
+                STX $10
+        done:   JMP done
+        ",
+    )
+    .unwrap();
+
+    let mut nes = Nes::new();
+    nes.load_rom(rom).unwrap();
+    nes.run_frame();
+
+    let bus = &nes.cpu().bus;
+    let powers: Vec<u8> = (0x0200..0x0208)
+        .map(|address| bus.peek_u8(address))
+        .collect();
+    assert_eq!(powers, [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80]);
+    assert_eq!(bus.peek_u8(0x10), 8);
+}
+
+#[test]
+fn nrom_images_point_the_vectors_at_the_labels() {
+    let rom = assemble_nrom("reset: NOP\nnmi: RTI").unwrap();
+    assert_eq!(rom.len(), 16 + 0x4000);
+    assert_eq!(rom[..6], [b'N', b'E', b'S', 0x1a, 1, 0]);
+    assert_eq!(rom[16..18], [0xea, 0x40]);
+    // NMI, reset, and IRQ falling back to reset
+    assert_eq!(rom[rom.len() - 6..], [0x01, 0xc0, 0x00, 0xc0, 0x00, 0xc0]);
+
+    let error = |source| assemble_nrom(source).unwrap_err().to_string();
+    assert_eq!(error("start: NOP"), "no reset label");
+    assert_eq!(
+        error("reset: NOP\n.org $FFFA\n.word reset"),
+        "the program runs into the vectors at $FFFA"
+    );
+    assert_eq!(error("reset: FOO"), "line 1: Unknown instruction: FOO");
+}
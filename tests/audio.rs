@@ -0,0 +1,102 @@
+use std::fs;
+
+use simpleness::audio::{AudioSink, Resampler, RingBuffer, WavSink};
+
+const CPU_CLOCK: f64 = 1_789_773.0;
+
+fn resample(clocks: usize, sample_rate: u32, rate_adjust: f64) -> usize {
+    let mut resampler = Resampler::new(CPU_CLOCK, sample_rate);
+    resampler.set_rate_adjust(rate_adjust);
+    for clock in 0..clocks {
+        // a square wave, so there are steps to band-limit
+        resampler.add_sample(if clock / 1000 % 2 == 0 { 0.5 } else { -0.5 });
+    }
+    resampler.take_samples().len()
+}
+
+#[test]
+fn the_resampler_outputs_the_sample_rate() {
+    assert!(resample(CPU_CLOCK as usize, 44_100, 1.0).abs_diff(44_100) <= 1);
+    assert!(resample(CPU_CLOCK as usize, 48_000, 1.0).abs_diff(48_000) <= 1);
+    // one NTSC frame
+    assert!(resample(29_781, 44_100, 1.0).abs_diff(734) <= 1);
+}
+
+#[test]
+fn the_resampler_follows_the_rate_adjustment() {
+    assert!(resample(CPU_CLOCK as usize, 44_100, 1.005).abs_diff(44_320) <= 1);
+    assert!(resample(CPU_CLOCK as usize, 44_100, 0.995).abs_diff(43_880) <= 1);
+}
+
+#[test]
+fn samples_are_only_handed_over_once() {
+    let mut resampler = Resampler::new(CPU_CLOCK, 44_100);
+    for _ in 0..1000 {
+        resampler.add_sample(0.0);
+    }
+    assert_eq!(resampler.take_samples().len(), 24);
+    assert!(resampler.take_samples().is_empty());
+}
+
+#[test]
+fn wav_sink_writes_a_16_bit_mono_file() {
+    let path = std::env::temp_dir().join(format!("simpleness-{}.wav", std::process::id()));
+    let mut sink = WavSink::create(&path, 44_100).unwrap();
+    sink.write(&[0.0, 1.0, -1.0]);
+    sink.write(&[0.5, 2.0]);
+    drop(sink);
+
+    let wav = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let mut expected = b"RIFF".to_vec();
+    expected.extend(46u32.to_le_bytes());
+    expected.extend(b"WAVEfmt ");
+    expected.extend(16u32.to_le_bytes());
+    expected.extend(1u16.to_le_bytes()); // PCM
+    expected.extend(1u16.to_le_bytes()); // mono
+    expected.extend(44_100u32.to_le_bytes());
+    expected.extend(88_200u32.to_le_bytes());
+    expected.extend(2u16.to_le_bytes());
+    expected.extend(16u16.to_le_bytes());
+    expected.extend(b"data");
+    expected.extend(10u32.to_le_bytes());
+    // out of range samples are clipped
+    for sample in [0, i16::MAX, -i16::MAX, 0x3fff, i16::MAX] {
+        expected.extend(sample.to_le_bytes());
+    }
+    assert_eq!(wav, expected);
+}
+
+#[test]
+fn ring_buffer_drops_what_doesnt_fit() {
+    let mut buffer = RingBuffer::new(4);
+    assert_eq!(buffer.push_slice(&[1.0, 2.0, 3.0]), 3);
+    assert_eq!(buffer.push_slice(&[4.0, 5.0]), 1);
+    assert_eq!(buffer.len(), 4);
+
+    let popped: Vec<f32> = (0..4).map(|_| buffer.pop()).collect();
+    assert_eq!(popped, [1.0, 2.0, 3.0, 4.0]);
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn ring_buffer_wraps_around() {
+    let mut buffer = RingBuffer::new(3);
+    buffer.push_slice(&[1.0, 2.0]);
+    buffer.pop();
+    assert_eq!(buffer.push_slice(&[3.0, 4.0]), 2);
+
+    let popped: Vec<f32> = (0..3).map(|_| buffer.pop()).collect();
+    assert_eq!(popped, [2.0, 3.0, 4.0]);
+}
+
+#[test]
+fn ring_buffer_repeats_the_last_sample_when_empty() {
+    let mut buffer = RingBuffer::new(4);
+    assert_eq!(buffer.pop(), 0.0);
+
+    buffer.push_slice(&[0.25]);
+    assert_eq!(buffer.pop(), 0.25);
+    assert_eq!(buffer.pop(), 0.25);
+}
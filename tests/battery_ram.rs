@@ -0,0 +1,78 @@
+use simpleness::cpu::assembler::assemble_nrom;
+use simpleness::{Nes, SimplenessError};
+
+const BATTERY: u8 = 0x02;
+
+/// An NROM image whose program increments $6000 once and stops.
+fn nrom(flag6: u8) -> Vec<u8> {
+    let mut rom = assemble_nrom(
+        "
+        reset:  INC $6000
+        done:   JMP done
+        ",
+    )
+    .unwrap();
+    rom[6] = flag6;
+    rom
+}
+
+fn load(flag6: u8) -> Nes {
+    let mut nes = Nes::new();
+    nes.load_rom(nrom(flag6)).unwrap();
+    nes
+}
+
+#[test]
+fn changes_are_reported_once() {
+    let mut nes = load(BATTERY);
+    assert_eq!(nes.take_battery_ram_changes(), None);
+
+    nes.run_frame();
+    let saved = nes.take_battery_ram_changes().unwrap();
+    assert_eq!(saved.len(), 0x2000);
+    assert_eq!(saved[0], 1);
+    assert_eq!(nes.take_battery_ram_changes(), None);
+}
+
+#[test]
+fn save_file_is_restored() {
+    let mut save = vec![0; 0x2000];
+    save[0] = 41;
+
+    let mut nes = load(BATTERY);
+    nes.load_battery_ram(&save).unwrap();
+    nes.run_frame();
+    assert_eq!(nes.battery_ram().unwrap()[0], 42);
+
+    assert_eq!(
+        nes.load_battery_ram(&[0; 100]),
+        Err(SimplenessError::SaveRamSizeMismatch {
+            expected: 0x2000,
+            actual: 100
+        })
+    );
+}
+
+#[test]
+fn power_cycle_keeps_battery_ram() {
+    let mut nes = load(BATTERY);
+    nes.run_frame();
+    nes.power_cycle();
+    nes.run_frame();
+    assert_eq!(nes.battery_ram().unwrap()[0], 2);
+    // the first increment was never flushed
+    assert!(nes.take_battery_ram_changes().is_some());
+}
+
+#[test]
+fn work_ram_without_a_battery_isnt_saved() {
+    let mut nes = load(0);
+    nes.run_frame();
+    assert_eq!(nes.cpu().bus.peek_u8(0x6000), 1);
+    assert_eq!(nes.battery_ram(), None);
+    assert_eq!(nes.take_battery_ram_changes(), None);
+    assert_eq!(nes.load_battery_ram(&[0; 100]), Ok(()));
+
+    nes.power_cycle();
+    assert_eq!(nes.cpu().bus.peek_u8(0x6000), 0);
+}
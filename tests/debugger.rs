@@ -0,0 +1,88 @@
+use simpleness::Nes;
+use simpleness::cpu::assembler::assemble_nrom;
+use simpleness::debugger::{Breakpoint, Debugger, StopReason};
+
+/// Counts up in X and $0200 forever.
+fn test_rom() -> Vec<u8> {
+    assemble_nrom(
+        "
+        reset:  LDX #$00        ; $C000
+        loop:   INX             ; $C002
+                STX $0200       ; $C003
+                JMP loop        ; $C006
+        ",
+    )
+    .unwrap()
+}
+
+fn setup(breakpoints: &[u16]) -> (Nes, Debugger, Vec<usize>) {
+    let mut nes = Nes::new();
+    nes.load_rom(test_rom()).unwrap();
+    let mut debugger = Debugger::new();
+    let ids = breakpoints
+        .iter()
+        .map(|&address| {
+            debugger.add_breakpoint(Breakpoint {
+                address: Some(address),
+                condition: None,
+            })
+        })
+        .collect();
+    (nes, debugger, ids)
+}
+
+fn pc(nes: &Nes) -> u16 {
+    nes.cpu().registers().pc
+}
+
+fn x(nes: &Nes) -> u8 {
+    nes.cpu().registers().x
+}
+
+#[test]
+fn breakpoints_stop_before_the_instruction() {
+    let (mut nes, mut debugger, ids) = setup(&[0xc003]);
+    debugger.resume();
+    assert_eq!(
+        debugger.run_frame(&mut nes),
+        Some(StopReason::Breakpoint(ids[0]))
+    );
+    assert_eq!((pc(&nes), x(&nes)), (0xc003, 1));
+
+    // continuing doesn't stop on the same instruction again, only the next time around
+    debugger.resume();
+    assert_eq!(
+        debugger.run_frame(&mut nes),
+        Some(StopReason::Breakpoint(ids[0]))
+    );
+    assert_eq!((pc(&nes), x(&nes)), (0xc003, 2));
+    assert_eq!(nes.cpu().bus.peek_u8(0x0200), 1);
+}
+
+#[test]
+fn only_the_instruction_resumed_from_is_skipped() {
+    let (mut nes, mut debugger, _) = setup(&[]);
+    debugger.step_into();
+    assert_eq!(debugger.run_frame(&mut nes), Some(StopReason::Step));
+    assert_eq!(pc(&nes), 0xc002);
+
+    let id = debugger.add_breakpoint(Breakpoint {
+        address: Some(0xc002),
+        condition: None,
+    });
+    debugger.resume();
+    assert_eq!(
+        debugger.run_frame(&mut nes),
+        Some(StopReason::Breakpoint(id))
+    );
+    assert_eq!((pc(&nes), x(&nes)), (0xc002, 1));
+
+    // stepping onto a breakpoint is reported as the step
+    debugger.step_into();
+    debugger.run_frame(&mut nes);
+    debugger.step_into();
+    debugger.run_frame(&mut nes);
+    debugger.step_into();
+    assert_eq!(debugger.run_frame(&mut nes), Some(StopReason::Step));
+    assert_eq!((pc(&nes), x(&nes)), (0xc002, 2));
+}
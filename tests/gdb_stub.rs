@@ -0,0 +1,132 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use simpleness::Nes;
+use simpleness::cpu::assembler::assemble_nrom;
+use simpleness::debugger::gdb;
+
+/// Counts up in X and $0200 forever.
+fn test_rom() -> Vec<u8> {
+    assemble_nrom(
+        "
+        reset:  LDX #$00        ; $C000
+        loop:   INX             ; $C002
+                STX $0200       ; $C003
+                JMP loop        ; $C006
+        ",
+    )
+    .unwrap()
+}
+
+/// A stand-in for a gdb frontend.
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${data}#{checksum:02x}").unwrap();
+    }
+
+    fn receive(&mut self) -> String {
+        let mut byte = [0];
+        loop {
+            self.stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+
+        let mut data = Vec::new();
+        loop {
+            self.stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+
+        let mut checksum = [0; 2];
+        self.stream.read_exact(&mut checksum).unwrap();
+        let expected = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        assert_eq!(
+            u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap(),
+            expected
+        );
+
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.receive()
+    }
+}
+
+fn connect() -> (Client, thread::JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let server = thread::spawn(move || {
+        let mut nes = Nes::new();
+        nes.load_rom(test_rom()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        gdb::serve(&mut nes, stream).unwrap();
+    });
+
+    let stream = TcpStream::connect(address).unwrap();
+    (Client { stream }, server)
+}
+
+#[test]
+fn registers_memory_and_breakpoints() {
+    let (mut client, server) = connect();
+
+    assert_eq!(client.request("?"), "S05");
+    // a, x, y, p, s, then pc in little endian, as left by the reset
+    assert_eq!(client.request("g"), "00000024fd00c0");
+
+    assert_eq!(client.request("Z0,c006,1"), "OK");
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("p5"), "06c0");
+    assert_eq!(client.request("p1"), "01");
+    assert_eq!(client.request("m200,1"), "01");
+
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("p5"), "02c0");
+
+    assert_eq!(client.request("M10,2:abcd"), "OK");
+    assert_eq!(client.request("m10,2"), "abcd");
+    assert_eq!(client.request("P0=7f"), "OK");
+    assert_eq!(client.request("p0"), "7f");
+    assert_eq!(client.request("G01020324fd00c0"), "OK");
+    assert_eq!(client.request("g"), "01020324fd00c0");
+
+    assert_eq!(client.request("z0,c006,1"), "OK");
+    assert_eq!(client.request("z0,c006,1"), "E01");
+    assert_eq!(client.request("vCont?"), "");
+
+    assert_eq!(client.request("D"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn watchpoints_and_interrupt() {
+    let (mut client, server) = connect();
+
+    assert_eq!(client.request("Z2,200,1"), "OK");
+    assert_eq!(client.request("c"), "T05watch:0200;");
+    // the watchpoint stops after the STX that hit it
+    assert_eq!(client.request("p5"), "06c0");
+    assert_eq!(client.request("z2,200,1"), "OK");
+
+    client.send("c");
+    client.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(client.receive(), "S02");
+
+    client.send("k");
+    server.join().unwrap();
+}
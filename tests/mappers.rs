@@ -0,0 +1,272 @@
+use std::{cell::RefCell, rc::Rc};
+
+use simpleness::{
+    Nes, SimplenessError,
+    memory::mapper::Mapper,
+    ppu::{NametableArrangement, NametableSource, Ppu},
+};
+
+const PRG_BANK: usize = 0x4000;
+const CHR_BANK: usize = 0x2000;
+
+/*
+Builds an image where every byte of each 16K PRG bank and 8K CHR bank holds the bank's
+number, so reading anywhere tells which bank is mapped. The reset vector of every bank
+points to a JMP to itself at $FFF0.
+*/
+fn rom(mapper: u8, prg_banks: usize, chr_banks: usize, nes20_submapper: Option<u8>) -> Vec<u8> {
+    let mut rom = b"NES\x1a".to_vec();
+    rom.extend([prg_banks as u8, chr_banks as u8, mapper << 4, mapper & 0xf0]);
+    if let Some(submapper) = nes20_submapper {
+        rom[7] |= 0x08;
+        rom.push(submapper << 4);
+        rom.extend([0, 0, 0x07]); // 8K of CHR RAM when there is no CHR ROM
+    }
+    rom.resize(16, 0);
+
+    for bank in 0..prg_banks {
+        let mut data = vec![bank as u8; PRG_BANK];
+        data[0x3ff0..0x3ff3].copy_from_slice(&[0x4c, 0xf0, 0xff]);
+        data[0x3ffa..].copy_from_slice(&[0xf0, 0xff, 0xf0, 0xff, 0xf0, 0xff]);
+        rom.extend(data);
+    }
+    for bank in 0..chr_banks {
+        rom.extend(vec![bank as u8; CHR_BANK]);
+    }
+    rom
+}
+
+fn load(rom: Vec<u8>) -> Nes {
+    let mut nes = Nes::new();
+    nes.load_rom(rom).unwrap();
+    nes
+}
+
+fn write(nes: &mut Nes, addr: u16, data: u8) {
+    nes.cpu_mut().bus.write_u8(addr, data);
+}
+
+fn peek(nes: &Nes, addr: u16) -> u8 {
+    nes.cpu().bus.peek_u8(addr)
+}
+
+fn peek_chr(nes: &Nes, addr: u16) -> u8 {
+    nes.cpu().bus.ppu.peek_u8(addr)
+}
+
+fn write_vram(nes: &mut Nes, addr: u16, data: u8) {
+    write_ppu(&mut nes.cpu_mut().bus.ppu, addr, data);
+}
+
+fn write_ppu(ppu: &mut Ppu, addr: u16, data: u8) {
+    let [high, low] = addr.to_be_bytes();
+    ppu.write_register(0x2006, high);
+    ppu.write_register(0x2006, low);
+    ppu.write_register(0x2007, data);
+}
+
+#[test]
+fn uxrom_switches_the_first_16k_with_bus_conflicts() {
+    let mut nes = load(rom(2, 4, 0, None));
+    assert_eq!((peek(&nes, 0x8000), peek(&nes, 0xc000)), (0, 3));
+
+    // the ROM drives 0 at $8000, so nothing gets through
+    write(&mut nes, 0x8000, 2);
+    assert_eq!(peek(&nes, 0x8000), 0);
+    // and 3 at $C000
+    write(&mut nes, 0xc000, 2);
+    assert_eq!((peek(&nes, 0x8000), peek(&nes, 0xc000)), (2, 3));
+
+    // CHR RAM
+    write_vram(&mut nes, 0x0123, 0x5a);
+    assert_eq!(peek_chr(&nes, 0x0123), 0x5a);
+}
+
+#[test]
+fn uxrom_without_bus_conflicts() {
+    let mut nes = load(rom(2, 4, 0, Some(1)));
+    write(&mut nes, 0x8000, 1);
+    assert_eq!(peek(&nes, 0x8000), 1);
+}
+
+#[test]
+fn cnrom_switches_chr() {
+    let mut nes = load(rom(3, 2, 4, None));
+    assert_eq!(peek_chr(&nes, 0x1000), 0);
+
+    write(&mut nes, 0xfff0, 3); // over the JMP opcode, $4C
+    assert_eq!(peek_chr(&nes, 0x1000), 0);
+    write(&mut nes, 0xc000, 3);
+    assert_eq!(peek_chr(&nes, 0x1000), 1);
+
+    // CHR ROM can't be written
+    write_vram(&mut nes, 0x1000, 0xff);
+    assert_eq!(peek_chr(&nes, 0x1000), 1);
+}
+
+#[test]
+fn axrom_switches_32k_and_the_single_screen() {
+    let mut nes = load(rom(7, 8, 0, None));
+    assert_eq!((peek(&nes, 0x8000), peek(&nes, 0xc000)), (0, 1));
+
+    write(&mut nes, 0x8000, 0x02);
+    assert_eq!((peek(&nes, 0x8000), peek(&nes, 0xc000)), (4, 5));
+
+    write_vram(&mut nes, 0x2000, 0xaa);
+    for nametable in [0x2000, 0x2400, 0x2800, 0x2c00] {
+        assert_eq!(peek_chr(&nes, nametable), 0xaa);
+    }
+
+    write(&mut nes, 0x8000, 0x12);
+    assert_eq!(peek(&nes, 0x8000), 4);
+    assert_eq!(peek_chr(&nes, 0x2400), 0);
+    write_vram(&mut nes, 0x2c00, 0x55);
+    assert_eq!(peek_chr(&nes, 0x2000), 0x55);
+
+    write(&mut nes, 0x8000, 0x02);
+    assert_eq!(peek_chr(&nes, 0x2800), 0xaa);
+}
+
+#[test]
+fn gxrom_switches_prg_and_chr() {
+    let mut nes = load(rom(66, 8, 4, None));
+
+    // $FFF0 holds $4C, so the bank bits that get through are 4-5 = 0 and 0-1 = 0 ...
+    write(&mut nes, 0xfff0, 0x33);
+    assert_eq!((peek(&nes, 0x8000), peek_chr(&nes, 0)), (0, 0));
+    // ... while the bank numbers filling the rest pass through
+    write(&mut nes, 0xfff3, 0x33);
+    assert_eq!((peek(&nes, 0x8000), peek_chr(&nes, 0)), (0, 1));
+}
+
+#[test]
+fn bnrom_switches_32k_with_bus_conflicts() {
+    let mut nes = load(rom(34, 8, 0, None));
+    write(&mut nes, 0x8000, 3);
+    assert_eq!(peek(&nes, 0x8000), 0);
+    write(&mut nes, 0xc000, 3);
+    assert_eq!(peek(&nes, 0x8000), 2);
+    write(&mut nes, 0xc000, 3);
+    assert_eq!(peek(&nes, 0x8000), 6);
+}
+
+#[test]
+fn submapper_2_is_bnrom_even_with_chr_rom() {
+    let mut nes = load(rom(34, 4, 2, Some(2)));
+    write(&mut nes, 0x7ffd, 1);
+    assert_eq!(peek(&nes, 0x8000), 0);
+}
+
+#[test]
+fn nina_001_has_registers_over_its_work_ram() {
+    let mut nes = load(rom(34, 4, 4, None));
+    assert_eq!((peek_chr(&nes, 0x0000), peek_chr(&nes, 0x1000)), (0, 0));
+
+    write(&mut nes, 0x7ffd, 1);
+    write(&mut nes, 0x7ffe, 5);
+    write(&mut nes, 0x7fff, 2);
+    assert_eq!(peek(&nes, 0x8000), 2);
+    assert_eq!((peek_chr(&nes, 0x0000), peek_chr(&nes, 0x1000)), (2, 1));
+    assert_eq!(peek(&nes, 0x7ffe), 5);
+
+    write(&mut nes, 0x6000, 0x42);
+    assert_eq!(peek(&nes, 0x6000), 0x42);
+}
+
+#[test]
+fn four_screen_gives_each_nametable_its_own_ram() {
+    let mut rom = rom(0, 1, 1, None);
+    rom[6] |= 0x08;
+    let mut nes = load(rom);
+
+    for (i, nametable) in [0x2000, 0x2400, 0x2800, 0x2c00].into_iter().enumerate() {
+        write_vram(&mut nes, nametable + 0x10, i as u8 + 1);
+    }
+    for (i, nametable) in [0x2000, 0x2400, 0x2800, 0x2c00].into_iter().enumerate() {
+        assert_eq!(peek_chr(&nes, nametable + 0x10), i as u8 + 1);
+        // $3000-$3EFF mirrors the nametables
+        assert_eq!(peek_chr(&nes, nametable + 0x1010), i as u8 + 1);
+    }
+}
+
+#[test]
+fn four_screen_ignores_the_mappers_mirroring() {
+    let mut rom = rom(4, 2, 1, None);
+    rom[6] |= 0x08;
+    let mut nes = load(rom);
+
+    write(&mut nes, 0xa000, 0x01);
+    write_vram(&mut nes, 0x2000, 0x11);
+    write_vram(&mut nes, 0x2400, 0x22);
+    write_vram(&mut nes, 0x2800, 0x33);
+    assert_eq!(peek_chr(&nes, 0x2000), 0x11);
+    assert_eq!(peek_chr(&nes, 0x2400), 0x22);
+    assert_eq!(peek_chr(&nes, 0x2800), 0x33);
+}
+
+#[test]
+fn mmc3_needs_two_prg_banks() {
+    // NES 2.0 exponent-multiplier size of 2^13 * 1, a single 8K bank
+    let mut rom = rom(4, 0, 1, Some(0));
+    rom[4] = 13 << 2;
+    rom[9] |= 0x0f;
+    rom.splice(16..16, vec![0; 0x2000]);
+
+    assert_eq!(
+        Nes::new().load_rom(rom).unwrap_err(),
+        SimplenessError::InvalidPrgRomSize(0x2000)
+    );
+}
+
+/*
+Puts 1K of its own RAM at the second and fourth nametable, like the CHR ROM and RAM
+nametables of boards such as Sunsoft-4 and MMC5
+*/
+struct CartNametables {
+    ram: [u8; 0x400],
+}
+
+impl Mapper for CartNametables {
+    fn cpu_map_read(&self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn cpu_map_write(&mut self, _addr: u16, _data: u8) {}
+
+    fn ppu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x2000..=0x2fff => self.ram[addr as usize & 0x3ff],
+            _ => 0,
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x2000..=0x2fff = addr {
+            self.ram[addr as usize & 0x3ff] = data;
+        }
+    }
+
+    fn nametable_arrangement(&self) -> Option<NametableArrangement> {
+        Some(NametableArrangement::Mapped([
+            NametableSource::Vram(1),
+            NametableSource::Cartridge,
+            NametableSource::Vram(0),
+            NametableSource::Cartridge,
+        ]))
+    }
+}
+
+#[test]
+fn mapper_can_put_its_own_memory_in_the_nametables() {
+    let mapper = CartNametables { ram: [0; 0x400] };
+    let mut ppu = Ppu::new(NametableArrangement::Vertical);
+    ppu.set_mapper(Rc::new(RefCell::new(Box::new(mapper))));
+
+    write_ppu(&mut ppu, 0x2000, 0x11);
+    write_ppu(&mut ppu, 0x2800, 0x33);
+    write_ppu(&mut ppu, 0x2c05, 0x44);
+    assert_eq!(ppu.peek_u8(0x2000), 0x11);
+    assert_eq!(ppu.peek_u8(0x2800), 0x33);
+    assert_eq!(ppu.peek_u8(0x2405), 0x44);
+    assert_eq!(ppu.peek_u8(0x2c05), 0x44);
+}
@@ -0,0 +1,184 @@
+use simpleness::cpu::assembler::assemble_nrom;
+use simpleness::{
+    ConsoleType, HeaderFormat, NametableArrangement, Nes, RomInfo, SimplenessError, Timing,
+};
+
+fn parse(header: [u8; 16]) -> RomInfo {
+    RomInfo::parse(&header).unwrap()
+}
+
+#[test]
+fn ines_header() {
+    let info = parse([
+        b'N', b'E', b'S', 0x1a, 8, 0, 0x43, 0x10, 0, 0, 0, 0, 0, 0, 0, 0,
+    ]);
+
+    assert_eq!(info.format, HeaderFormat::INes);
+    assert_eq!(info.mapper, 0x14);
+    assert_eq!(info.prg_rom_size, 128 * 1024);
+    assert_eq!(info.chr_rom_size, 0);
+    assert_eq!(info.chr_ram_size, 8 * 1024);
+    assert!(info.battery && !info.trainer);
+    // byte 8 being 0 still means 8K
+    assert_eq!((info.prg_ram_size, info.prg_nvram_size), (0, 8 * 1024));
+    assert_eq!(info.nametable_arrangement, NametableArrangement::Horizontal);
+    assert_eq!(info.timing, Timing::Ntsc);
+    assert_eq!(info.console_type, ConsoleType::Nes);
+}
+
+#[test]
+fn ines_header_with_a_dumper_signature() {
+    let info = parse(*b"NES\x1a\x02\x01\x10DiskDude!");
+
+    assert_eq!(info.format, HeaderFormat::INes);
+    assert_eq!(info.mapper, 1);
+    assert_eq!(info.console_type, ConsoleType::Nes);
+}
+
+#[test]
+fn nes20_header() {
+    let info = parse([
+        b'N', b'E', b'S', 0x1a, 0x02, 0x01, 0x4a, 0x19, 0x51, 0x10, 0x70, 0x07, 0x01, 0x32, 0x01,
+        0x02,
+    ]);
+
+    assert_eq!(info.format, HeaderFormat::Nes20);
+    assert_eq!(info.mapper, 0x114);
+    assert_eq!(info.submapper, 5);
+    assert_eq!(info.prg_rom_size, 2 * 16 * 1024);
+    assert_eq!(info.chr_rom_size, 0x101 * 8 * 1024);
+    assert_eq!((info.prg_ram_size, info.prg_nvram_size), (0, 8 * 1024));
+    assert_eq!((info.chr_ram_size, info.chr_nvram_size), (8 * 1024, 0));
+    assert!(info.battery && !info.trainer && info.four_screen);
+    assert_eq!(info.timing, Timing::Pal);
+    assert_eq!(
+        info.console_type,
+        ConsoleType::VsSystem {
+            ppu: 2,
+            hardware: 3
+        }
+    );
+    assert_eq!(info.misc_roms, 1);
+    assert_eq!(info.expansion_device, 2);
+}
+
+#[test]
+fn nes20_exponent_multiplier_sizes() {
+    // 2^10 * 3 bytes of PRG ROM, 2^7 * 1 of CHR ROM
+    let info = parse([
+        b'N', b'E', b'S', 0x1a, 0x29, 0x1c, 0, 0x08, 0, 0xff, 0, 0, 0, 0, 0, 0,
+    ]);
+
+    assert_eq!(info.prg_rom_size, 3 * 1024);
+    assert_eq!(info.chr_rom_size, 128);
+}
+
+#[test]
+fn missing_magic() {
+    assert_eq!(RomInfo::parse(&[0; 16]), None);
+}
+
+#[test]
+fn trainer_is_skipped_and_loaded_at_7000() {
+    let mut rom = vec![b'N', b'E', b'S', 0x1a, 1, 1, 0x04, 0];
+    rom.resize(16, 0);
+    rom.extend((0..512).map(|i| i as u8));
+    let mut prg = vec![0xea; 0x4000];
+    prg[0x3ffc..].copy_from_slice(&[0x00, 0xc0, 0x00, 0xc0]);
+    rom.extend(prg);
+    rom.extend(vec![0; 0x2000]);
+
+    let mut nes = Nes::new();
+    nes.load_rom(rom).unwrap();
+
+    assert!(nes.rom_info().unwrap().trainer);
+    let bus = &nes.cpu().bus;
+    assert_eq!(bus.peek_u8(0x7000), 0);
+    assert_eq!(bus.peek_u8(0x71ff), 0xff);
+    assert_eq!(bus.peek_u8(0xfffd), 0xc0);
+    assert_eq!(nes.cpu().pc, 0xc000);
+}
+
+/// An image with the given header bytes 4-7 and as much zeroed ROM as they ask for.
+fn rom_with_header(prg_banks: u8, chr_banks: u8, flag6: u8, flag7: u8) -> Vec<u8> {
+    let mut rom = vec![b'N', b'E', b'S', 0x1a, prg_banks, chr_banks, flag6, flag7];
+    rom.resize(
+        16 + prg_banks as usize * 0x4000 + chr_banks as usize * 0x2000,
+        0,
+    );
+    rom
+}
+
+#[test]
+fn bad_roms_are_rejected() {
+    let mut nes = Nes::new();
+    let mut error = |rom| nes.load_rom(rom).unwrap_err();
+
+    assert_eq!(
+        error(b"PK\x03\x04".to_vec()),
+        SimplenessError::NotAnINesFile
+    );
+    assert_eq!(
+        error(rom_with_header(1, 1, 0, 0)[..0x1000].to_vec()),
+        SimplenessError::TruncatedRom {
+            expected: 0x6010,
+            actual: 0x1000
+        }
+    );
+    assert_eq!(
+        error(rom_with_header(1, 1, 0x50, 0x40)),
+        SimplenessError::UnsupportedMapper(0x45)
+    );
+    assert_eq!(
+        error(rom_with_header(3, 1, 0, 0)),
+        SimplenessError::InvalidPrgRomSize(0xc000)
+    );
+    assert_eq!(
+        error(rom_with_header(1, 2, 0, 0)),
+        SimplenessError::InvalidChrRomSize(0x4000)
+    );
+    assert!(!nes.rom_loaded());
+}
+
+#[test]
+fn sizes_beyond_memory_are_rejected() {
+    // NES 2.0 exponent-multiplier sizes of 2^63 * 7 bytes of PRG ROM
+    let mut rom = rom_with_header(1, 1, 0, 0x08);
+    (rom[4], rom[9]) = (0xff, 0x0f);
+    assert_eq!(
+        Nes::new().load_rom(rom.clone()).unwrap_err(),
+        SimplenessError::InvalidRom
+    );
+
+    // 2^63 bytes each of PRG and CHR ROM, which only overflow together
+    (rom[4], rom[5], rom[9]) = (0xfc, 0xfc, 0xff);
+    assert_eq!(
+        Nes::new().load_rom(rom).unwrap_err(),
+        SimplenessError::InvalidRom
+    );
+}
+
+#[test]
+fn a_bad_rom_keeps_the_previous_game() {
+    let mut nes = Nes::new();
+    nes.load_rom(assemble_nrom("reset: JMP reset").unwrap())
+        .unwrap();
+    nes.run_frame();
+    let cycles = nes.cpu().cycles();
+
+    assert!(nes.load_rom(b"not a rom".to_vec()).is_err());
+    assert_eq!(nes.cpu().cycles(), cycles);
+    nes.run_frame();
+    assert!(nes.cpu().cycles() > cycles);
+}
+
+#[test]
+fn the_bus_works_without_a_cartridge() {
+    let mut nes = Nes::new();
+    let bus = &mut nes.cpu_mut().bus;
+
+    bus.write_u8(0x8000, 0x12);
+    assert_eq!(bus.read_u8(0x8000), 0);
+    bus.write_u8(0x0010, 0x34);
+    assert_eq!(bus.read_u8(0x0010), 0x34);
+}
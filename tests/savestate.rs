@@ -0,0 +1,69 @@
+use simpleness::Nes;
+use simpleness::cpu::assembler::assemble_nrom;
+
+/// An NROM image that counts frames in $00 and shows them as the backdrop color.
+fn rom() -> Vec<u8> {
+    assemble_nrom(
+        "
+        reset:  BIT $2002
+                BPL reset
+        wait:   BIT $2002
+                BPL wait
+                LDA #$80
+                STA $2000
+                LDA #$08
+                STA $2001
+        done:   JMP done
+        nmi:    INC $00
+                LDA #$3F
+                STA $2006
+                LDA #$00
+                STA $2006
+                LDA $00
+                AND #$3F
+                STA $2007
+                RTI
+        ",
+    )
+    .unwrap()
+}
+
+#[test]
+fn states_restore_the_machine_but_not_the_picture() {
+    let mut nes = Nes::new();
+    nes.load_rom(rom()).unwrap();
+    for _ in 0..10 {
+        nes.run_frame();
+    }
+
+    let state = nes.save_state().unwrap();
+    assert!(state.len() < nes.framebuffer().len());
+    nes.run_frame();
+    let next_frame = nes.framebuffer().to_vec();
+    nes.run_frame();
+    assert_ne!(nes.framebuffer(), next_frame);
+
+    nes.load_state(&state).unwrap();
+    assert!(nes.framebuffer().iter().all(|&byte| byte == 0));
+    nes.run_frame();
+    assert_eq!(nes.framebuffer(), next_frame);
+}
+
+#[test]
+fn a_frame_is_redrawn_from_its_palette_indices() {
+    let mut nes = Nes::new();
+    nes.load_rom(rom()).unwrap();
+    for _ in 0..10 {
+        nes.run_frame();
+    }
+
+    let state = nes.save_state().unwrap();
+    let colors = nes.frame_colors().to_vec();
+    let frame = nes.framebuffer().to_vec();
+    assert_eq!(colors.len() * 4, frame.len());
+
+    nes.run_frame();
+    nes.load_state(&state).unwrap();
+    nes.restore_frame(&colors);
+    assert_eq!(nes.framebuffer(), frame);
+}
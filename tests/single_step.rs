@@ -0,0 +1,216 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use simpleness::cpu::bus::{AccessKind, BusAccess, FlatBus};
+use simpleness::cpu::olc6502::{Olc6502, Registers};
+
+/*
+Runs single instruction tests in the SingleStepTests (formerly ProcessorTests) JSON format
+on a CPU sitting on a flat 64K RAM bus. Each test gives the registers and RAM before and
+after one instruction, and the bus access of every cycle in between:
+
+    {"name": "b1 28 b5",
+     "initial": {"pc": 59082, "s": 39, "a": 57, "x": 33, "y": 174, "p": 96,
+                 "ram": [[59082, 177], ...]},
+     "final": {...},
+     "cycles": [[59082, 177, "read"], ...]}
+
+The nes6502 set, one file per opcode, goes in tests/single_step or the directory named by
+SIMPLENESS_SINGLE_STEP; see the README there. The suite is ignored by default.
+*/
+
+const SUITE_VARIABLE: &str = "SIMPLENESS_SINGLE_STEP";
+
+/// Hand checked cases for the dummy accesses, so the runner is exercised without the suite.
+const SAMPLE_TESTS: &str = r#"[
+    {"name": "LDA $12FF,X crossing a page",
+     "initial": {"pc": 512, "s": 253, "a": 0, "x": 1, "y": 0, "p": 36,
+                 "ram": [[512, 189], [513, 255], [514, 18], [4608, 85], [4864, 66]]},
+     "final": {"pc": 515, "s": 253, "a": 66, "x": 1, "y": 0, "p": 36,
+               "ram": [[4608, 85], [4864, 66]]},
+     "cycles": [[512, 189, "read"], [513, 255, "read"], [514, 18, "read"],
+                [4608, 85, "read"], [4864, 66, "read"]]},
+    {"name": "INC $10 writing back the old value first",
+     "initial": {"pc": 768, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[768, 230], [769, 16], [16, 127]]},
+     "final": {"pc": 770, "s": 253, "a": 0, "x": 0, "y": 0, "p": 164,
+               "ram": [[16, 128]]},
+     "cycles": [[768, 230, "read"], [769, 16, "read"], [16, 127, "read"],
+                [16, 127, "write"], [16, 128, "write"]]},
+    {"name": "JSR $1234 reading the stack before pushing",
+     "initial": {"pc": 1024, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[1024, 32], [1025, 52], [1026, 18], [509, 170]]},
+     "final": {"pc": 4660, "s": 251, "a": 0, "x": 0, "y": 0, "p": 36,
+               "ram": [[509, 4], [508, 2]]},
+     "cycles": [[1024, 32, "read"], [1025, 52, "read"], [509, 170, "read"],
+                [509, 4, "write"], [508, 2, "write"], [1026, 18, "read"]]},
+    {"name": "BRK pushing P with B set",
+     "initial": {"pc": 1280, "s": 253, "a": 0, "x": 0, "y": 0, "p": 32,
+                 "ram": [[1280, 0], [1281, 7], [65534, 0], [65535, 144]]},
+     "final": {"pc": 36864, "s": 250, "a": 0, "x": 0, "y": 0, "p": 36,
+               "ram": [[509, 5], [508, 2], [507, 48]]},
+     "cycles": [[1280, 0, "read"], [1281, 7, "read"], [509, 5, "write"], [508, 2, "write"],
+                [507, 48, "write"], [65534, 0, "read"], [65535, 144, "read"]]},
+    {"name": "BNE taken across a page",
+     "initial": {"pc": 1776, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[1776, 208], [1777, 16], [1778, 234], [1538, 96]]},
+     "final": {"pc": 1794, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+               "ram": []},
+     "cycles": [[1776, 208, "read"], [1777, 16, "read"], [1778, 234, "read"],
+                [1538, 96, "read"]]}
+]"#;
+
+fn suite_dir() -> PathBuf {
+    std::env::var_os(SUITE_VARIABLE)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/single_step"))
+}
+
+fn field(state: &Value, name: &str) -> u16 {
+    state[name]
+        .as_u64()
+        .unwrap_or_else(|| panic!("missing {name}")) as u16
+}
+
+fn registers(state: &Value) -> Registers {
+    Registers {
+        a: field(state, "a") as u8,
+        x: field(state, "x") as u8,
+        y: field(state, "y") as u8,
+        p: field(state, "p") as u8,
+        s: field(state, "s") as u8,
+        pc: field(state, "pc"),
+    }
+}
+
+/// `[address, value]` pairs.
+fn ram(state: &Value) -> Vec<(u16, u8)> {
+    state["ram"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            (
+                entry[0].as_u64().unwrap() as u16,
+                entry[1].as_u64().unwrap() as u8,
+            )
+        })
+        .collect()
+}
+
+fn cycles(test: &Value) -> Vec<BusAccess> {
+    test["cycles"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|cycle| BusAccess {
+            kind: match cycle[2].as_str().unwrap() {
+                "read" => AccessKind::Read,
+                _ => AccessKind::Write,
+            },
+            address: cycle[0].as_u64().unwrap() as u16,
+            value: cycle[1].as_u64().unwrap() as u8,
+        })
+        .collect()
+}
+
+/// Runs one test, telling what differs from its final state.
+fn run_test(test: &Value) -> Result<(), String> {
+    let mut bus = FlatBus::new();
+    for (address, value) in ram(&test["initial"]) {
+        bus.ram[address as usize] = value;
+    }
+    let mut cpu = Olc6502::new(bus);
+    cpu.set_registers(registers(&test["initial"]));
+
+    cpu.execute_instruction();
+
+    let expected = registers(&test["final"]);
+    if cpu.registers() != expected {
+        return Err(format!(
+            "registers {:X?}, expected {expected:X?}",
+            cpu.registers()
+        ));
+    }
+
+    for (address, value) in ram(&test["final"]) {
+        let actual = cpu.bus.ram[address as usize];
+        if actual != value {
+            return Err(format!(
+                "${address:04X} = {actual:02X}, expected {value:02X}"
+            ));
+        }
+    }
+
+    let expected = cycles(test);
+    if cpu.bus.accesses != expected {
+        return Err(format!(
+            "bus accesses {:X?}\nexpected {expected:X?}",
+            cpu.bus.accesses
+        ));
+    }
+    Ok(())
+}
+
+/// Runs every test of a file, reporting how many failed and the first failure.
+fn run_tests(tests: &Value) -> Result<(), String> {
+    let tests = tests.as_array().unwrap();
+    let mut failures = tests
+        .iter()
+        .filter_map(|test| run_test(test).err().map(|err| (test["name"].clone(), err)));
+
+    match failures.next() {
+        None => Ok(()),
+        Some((name, err)) => Err(format!(
+            "{} of {} failed, first {name}: {err}",
+            failures.count() + 1,
+            tests.len()
+        )),
+    }
+}
+
+#[test]
+fn samples() {
+    let tests = serde_json::from_str(SAMPLE_TESTS).unwrap();
+    if let Err(err) = run_tests(&tests) {
+        panic!("{err}");
+    }
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests files, see tests/single_step/README.md"]
+fn suite() {
+    let dir = suite_dir();
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.extension()
+                        .is_some_and(|extension| extension == "json")
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    assert!(!files.is_empty(), "no tests in {}", dir.display());
+
+    let failures: Vec<String> = files
+        .iter()
+        .filter_map(|file| {
+            let tests = serde_json::from_slice(&fs::read(file).unwrap()).unwrap();
+            run_tests(&tests)
+                .err()
+                .map(|err| format!("{}: {err}", file.file_name().unwrap().display()))
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} of {} opcodes failed\n{}",
+        failures.len(),
+        files.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,262 @@
+use std::{cell::RefCell, rc::Rc};
+
+use simpleness::{
+    memory::mapper::Mapper,
+    ppu::{NametableArrangement, Ppu},
+};
+
+const DOTS_PER_FRAME: usize = 262 * 341;
+
+const RED: (u8, u8, u8) = (152, 34, 32); // $16
+const GREEN: (u8, u8, u8) = (76, 208, 32); // $2A
+const BLUE: (u8, u8, u8) = (8, 16, 144); // $02
+const BLACK: (u8, u8, u8) = (0, 0, 0); // $0F
+
+/// 8K of CHR RAM and nothing else.
+struct Chr {
+    chr: [u8; 0x2000],
+}
+
+impl Mapper for Chr {
+    fn cpu_map_read(&self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn cpu_map_write(&mut self, _addr: u16, _data: u8) {}
+
+    fn ppu_map_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1fff => self.chr[addr as usize],
+            _ => 0,
+        }
+    }
+
+    fn ppu_map_write(&mut self, addr: u16, data: u8) {
+        if let 0x0000..=0x1fff = addr {
+            self.chr[addr as usize] = data;
+        }
+    }
+}
+
+/*
+A PPU showing a blank background, with sprite palette 0 red and sprite palette 1 green,
+past the frame it ignores PPUCTRL and PPUMASK for. Every tile listed has its low plane
+filled, so all its pixels are color 1.
+*/
+fn ppu(opaque_tiles: &[u16]) -> Ppu {
+    let mut chr = Chr { chr: [0; 0x2000] };
+    for &tile in opaque_tiles {
+        let start = tile as usize * 16;
+        chr.chr[start..start + 8].fill(0xff);
+    }
+
+    let mut ppu = Ppu::new(NametableArrangement::Vertical);
+    ppu.set_mapper(Rc::new(RefCell::new(Box::new(chr))));
+    for (addr, color) in [
+        (0x3f00, 0x0f),
+        (0x3f01, 0x02),
+        (0x3f11, 0x16),
+        (0x3f15, 0x2a),
+    ] {
+        write_vram(&mut ppu, addr, color);
+    }
+    for _ in 0..DOTS_PER_FRAME {
+        ppu.tick();
+    }
+    ppu
+}
+
+fn write_vram(ppu: &mut Ppu, addr: u16, data: u8) {
+    let [high, low] = addr.to_be_bytes();
+    ppu.write_register(0x2006, high);
+    ppu.write_register(0x2006, low);
+    ppu.write_register(0x2007, data);
+}
+
+fn write_oam(ppu: &mut Ppu, sprites: &[[u8; 4]]) {
+    ppu.write_register(0x2003, 0);
+    for byte in sprites.iter().flatten() {
+        ppu.write_register(0x2004, *byte);
+    }
+    // the rest of OAM off screen
+    for _ in sprites.len() * 4..0x100 {
+        ppu.write_register(0x2004, 0xff);
+    }
+}
+
+fn render(ppu: &mut Ppu, ctrl: u8) {
+    ppu.write_register(0x2000, ctrl);
+    ppu.write_register(0x2001, 0x18);
+    for _ in 0..2 * DOTS_PER_FRAME {
+        ppu.tick();
+    }
+}
+
+/// Runs until the PPU is about to render the given dot.
+fn run_to(ppu: &mut Ppu, scanline: u16, dot: u16) {
+    while (ppu.scanline(), ppu.dot()) != (scanline, dot) {
+        ppu.tick();
+    }
+}
+
+fn read_oam(ppu: &mut Ppu, addr: u8) -> u8 {
+    ppu.write_register(0x2003, addr);
+    ppu.read_register(0x2004)
+}
+
+/// Whether a frame showing the sprites sets the sprite overflow flag.
+fn overflows(sprites: &[[u8; 4]]) -> bool {
+    let mut ppu = ppu(&[]);
+    write_oam(&mut ppu, sprites);
+    render(&mut ppu, 0);
+    run_to(&mut ppu, 241, 0);
+    ppu.read_register(0x2002) & 0x20 != 0
+}
+
+fn pixel(ppu: &Ppu, x: usize, y: usize) -> (u8, u8, u8) {
+    let i = (y * 256 + x) * 4;
+    let buffer = ppu.get_pixel_buffer();
+    (buffer[i], buffer[i + 1], buffer[i + 2])
+}
+
+#[test]
+fn the_sprite_earliest_in_oam_is_in_front() {
+    let mut ppu = ppu(&[1]);
+    write_oam(&mut ppu, &[[10, 1, 0x00, 20], [10, 1, 0x01, 24]]);
+    render(&mut ppu, 0);
+
+    // sprites show from the line after their Y
+    assert_eq!(pixel(&ppu, 20, 10), BLACK);
+    assert_eq!(pixel(&ppu, 20, 11), RED);
+    assert_eq!(pixel(&ppu, 24, 11), RED);
+    assert_eq!(pixel(&ppu, 28, 18), GREEN);
+    assert_eq!(pixel(&ppu, 32, 18), BLACK);
+}
+
+#[test]
+fn no_sprites_show_on_the_first_scanline() {
+    let mut ppu = ppu(&[1]);
+    // the second sprite is still in secondary OAM from scanline 239 during the pre-render line
+    write_oam(&mut ppu, &[[0, 1, 0x00, 20], [232, 1, 0x00, 40]]);
+    render(&mut ppu, 0);
+
+    assert_eq!(pixel(&ppu, 20, 0), BLACK);
+    assert_eq!(pixel(&ppu, 20, 1), RED);
+    assert_eq!(pixel(&ppu, 40, 0), BLACK);
+    assert_eq!(pixel(&ppu, 40, 239), RED);
+}
+
+#[test]
+fn a_sprite_behind_the_background_still_hides_later_sprites() {
+    // tile 0 is the background everywhere
+    let mut ppu = ppu(&[0, 0x101]);
+    write_oam(
+        &mut ppu,
+        &[[10, 1, 0x20, 20], [10, 1, 0x01, 20], [10, 1, 0x01, 40]],
+    );
+    render(&mut ppu, 0x08);
+
+    assert_eq!(pixel(&ppu, 20, 11), BLUE);
+    assert_eq!(pixel(&ppu, 40, 11), GREEN);
+}
+
+#[test]
+fn tall_sprites_take_the_odd_tile_for_their_bottom_half() {
+    // tile 3 is in the table at $1000, its top half is tile 2 there
+    let mut ppu = ppu(&[0x103]);
+    write_oam(&mut ppu, &[[10, 3, 0x00, 20], [40, 3, 0x80, 20]]);
+    render(&mut ppu, 0x20);
+
+    assert_eq!(pixel(&ppu, 20, 18), BLACK);
+    assert_eq!(pixel(&ppu, 20, 19), RED);
+    assert_eq!(pixel(&ppu, 20, 26), RED);
+    assert_eq!(pixel(&ppu, 20, 27), BLACK);
+
+    // flipped vertically, the odd tile is on top
+    assert_eq!(pixel(&ppu, 20, 41), RED);
+    assert_eq!(pixel(&ppu, 20, 48), RED);
+    assert_eq!(pixel(&ppu, 20, 49), BLACK);
+}
+
+#[test]
+fn overflow_needs_a_ninth_sprite() {
+    assert!(!overflows(&[[10, 0, 0, 0]; 8]));
+    assert!(overflows(&[[10, 0, 0, 0]; 9]));
+}
+
+#[test]
+fn the_overflow_search_reads_the_wrong_bytes() {
+    // after sprite 8 misses, sprite 9's tile is taken for its Y
+    let mut sprites = vec![[10, 0, 0, 0]; 8];
+    sprites.extend([[200, 0, 0, 0], [0xff, 10, 0xff, 0xff]]);
+    assert!(overflows(&sprites));
+
+    // so a ninth sprite on the line right after a miss goes unnoticed
+    sprites[9] = [10, 0xff, 0xff, 0xff];
+    assert!(!overflows(&sprites));
+}
+
+#[test]
+fn evaluation_starts_at_oamaddr() {
+    let mut ppu = ppu(&[1]);
+    write_oam(&mut ppu, &[[10, 1, 0x00, 20], [10, 1, 0x01, 40]]);
+    ppu.write_register(0x2003, 4);
+
+    // rendering starts late, so OAMADDR hasn't been reset on the pre-render scanline
+    run_to(&mut ppu, 10, 0);
+    ppu.write_register(0x2001, 0x18);
+    run_to(&mut ppu, 240, 0);
+
+    assert_eq!(pixel(&ppu, 20, 11), BLACK);
+    assert_eq!(pixel(&ppu, 40, 11), GREEN);
+    assert_eq!(pixel(&ppu, 20, 12), RED);
+}
+
+#[test]
+fn oamdata_reads_follow_evaluation_while_rendering() {
+    let mut ppu = ppu(&[]);
+    write_oam(&mut ppu, &[[10, 0x42, 0x01, 0x33]]);
+    render(&mut ppu, 0);
+
+    run_to(&mut ppu, 10, 30);
+    assert_eq!(ppu.read_register(0x2004), 0xff); // secondary OAM being cleared
+    run_to(&mut ppu, 10, 259);
+    assert_eq!(ppu.read_register(0x2004), 0x42); // the tile fetch
+    run_to(&mut ppu, 10, 263);
+    assert_eq!(ppu.read_register(0x2004), 0x33); // the X position
+    run_to(&mut ppu, 10, 330);
+    assert_eq!(ppu.read_register(0x2004), 10);
+
+    run_to(&mut ppu, 241, 0);
+    assert_eq!(read_oam(&mut ppu, 1), 0x42);
+}
+
+#[test]
+fn oam_decays_without_rendering() {
+    let mut ppu = ppu(&[]);
+    write_oam(&mut ppu, &[[10, 0x42, 0x01, 0x33]]);
+    assert_eq!(read_oam(&mut ppu, 1), 0x42);
+
+    run_to(&mut ppu, 100, 0);
+    assert_eq!(read_oam(&mut ppu, 1), 0xff);
+}
+
+#[test]
+fn turning_rendering_off_mid_scanline_corrupts_oam() {
+    let mut sprites = vec![[0xf0, 1, 2, 3], [0xf0, 4, 5, 6]];
+    sprites.resize(20, [0xff; 4]);
+    sprites.extend([[0xf0, 7, 7, 7], [0xf0, 8, 8, 8]]);
+    let mut ppu = ppu(&[]);
+    write_oam(&mut ppu, &sprites);
+    render(&mut ppu, 0);
+
+    // while the third sprite slot's attributes are read, secondary OAM byte 10
+    run_to(&mut ppu, 20, 275);
+    ppu.write_register(0x2001, 0);
+    run_to(&mut ppu, 22, 0);
+    ppu.write_register(0x2001, 0x18);
+
+    run_to(&mut ppu, 241, 0);
+    let row: Vec<u8> = (80..88).map(|addr| read_oam(&mut ppu, addr)).collect();
+    assert_eq!(row, [0xf0, 1, 2, 3, 0xf0, 4, 5, 6]);
+}
@@ -0,0 +1,270 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use simpleness::Nes;
+use simpleness::cpu::olc6502::Registers;
+use simpleness::cpu::trace::TraceLogger;
+use simpleness::savestate::crc32;
+
+/*
+Runs the test ROMs found under tests/roms, or the directory named by SIMPLENESS_TEST_ROMS,
+and checks their results the way each suite reports them. The ROMs aren't part of the
+repository; see tests/roms/README.md for where each kind goes. The suites are ignored by
+default, and fail when asked for without their ROMs.
+*/
+
+const FRAME_RATE: u64 = 60;
+const BLARGG_TIMEOUT_FRAMES: u64 = 120 * FRAME_RATE;
+const BLARGG_RESET_DELAY_FRAMES: u64 = 10; // the ROM asks for at least 100ms
+
+const BLARGG_STATUS: u16 = 0x6000;
+const BLARGG_SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+const BLARGG_RUNNING: u8 = 0x80;
+const BLARGG_NEEDS_RESET: u8 = 0x81;
+
+const NESTEST_AUTOMATION_START: u16 = 0xc000;
+
+const ROMS_VARIABLE: &str = "SIMPLENESS_TEST_ROMS";
+const BLESS_VARIABLE: &str = "SIMPLENESS_BLESS";
+const DEFAULT_SCREEN_FRAMES: u64 = 5 * FRAME_RATE;
+
+fn roms_dir(suite: &str) -> PathBuf {
+    std::env::var_os(ROMS_VARIABLE)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms"))
+        .join(suite)
+}
+
+/// Every .nes file under `dir`, in a stable order.
+fn find_roms(dir: &Path) -> Vec<PathBuf> {
+    let mut roms = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return roms;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            roms.extend(find_roms(&path));
+        } else if path.extension().is_some_and(|extension| extension == "nes") {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+    roms
+}
+
+fn load(rom: &Path) -> Result<Nes, String> {
+    let mut nes = Nes::new();
+    nes.load_rom(fs::read(rom).unwrap())
+        .map_err(|err| err.to_string())?;
+    Ok(nes)
+}
+
+/// Runs `check` on every ROM of a suite and fails the test if any of them failed.
+fn run_suite(suite: &str, check: impl Fn(&Path) -> Result<(), String>) {
+    let dir = roms_dir(suite);
+    let roms = find_roms(&dir);
+    assert!(!roms.is_empty(), "no ROMs in {}", dir.display());
+
+    let failures: Vec<String> = roms
+        .iter()
+        .filter_map(|rom| {
+            check(rom)
+                .err()
+                .map(|reason| format!("{}: {reason}", rom.strip_prefix(&dir).unwrap().display()))
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} of {} ROMs failed\n{}",
+        failures.len(),
+        roms.len(),
+        failures.join("\n")
+    );
+}
+
+/*
+blargg's tests write $80 to $6000 while running and their result code once done, 0 being
+a pass, with DE B0 61 at $6001 to tell the protocol is in use and a zero terminated
+message from $6004. $81 asks for the reset button to be pressed.
+*/
+fn run_blargg(rom: &Path) -> Result<(), String> {
+    let mut nes = load(rom)?;
+    let mut reset_at = None;
+
+    for frame in 0..BLARGG_TIMEOUT_FRAMES {
+        nes.run_frame();
+        if nes.cpu().is_jammed() {
+            return Err(format!("CPU jammed at ${:04X}", nes.cpu().pc));
+        }
+
+        let bus = &nes.cpu().bus;
+        let signature = [0x6001, 0x6002, 0x6003].map(|address| bus.peek_u8(address));
+        if signature != BLARGG_SIGNATURE {
+            continue;
+        }
+
+        match bus.peek_u8(BLARGG_STATUS) {
+            BLARGG_RUNNING => reset_at = None,
+            BLARGG_NEEDS_RESET => match reset_at {
+                None => reset_at = Some(frame + BLARGG_RESET_DELAY_FRAMES),
+                Some(reset_frame) if frame >= reset_frame => {
+                    nes.reset();
+                    // the ROM keeps $81 around until it gets going again
+                    reset_at = Some(u64::MAX);
+                }
+                Some(_) => {}
+            },
+            0 => return Ok(()),
+            code => return Err(format!("result {code}: {}", blargg_message(&nes))),
+        }
+    }
+    Err(String::from("timed out without a result"))
+}
+
+fn blargg_message(nes: &Nes) -> String {
+    let bus = &nes.cpu().bus;
+    let message: Vec<u8> = (0x6004..0x7fff)
+        .map(|address| bus.peek_u8(address))
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&message)
+        .trim()
+        .replace('\n', " / ")
+}
+
+/// A trace destination the test can still read once the CPU owns the logger.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/*
+nestest runs every test without a screen when started at $C000, and its log from
+Nintendulator holds the state before each instruction. A nestest.log next to the ROM is
+compared line by line; the results at $02 and $03 are checked either way.
+*/
+fn run_nestest(rom: &Path) -> Result<(), String> {
+    let log = fs::read_to_string(rom.with_extension("log")).ok();
+    let instructions = log.as_ref().map_or(10_000, |log| log.lines().count());
+
+    let mut nes = load(rom)?;
+    let registers = nes.cpu().registers();
+    nes.cpu_mut().set_registers(Registers {
+        pc: NESTEST_AUTOMATION_START,
+        ..registers
+    });
+
+    let buffer = SharedBuffer::default();
+    nes.cpu_mut()
+        .start_trace(TraceLogger::new(Box::new(buffer.clone())));
+    for _ in 0..instructions {
+        if nes.cpu().is_jammed() {
+            break;
+        }
+        nes.cpu_mut().execute_instruction();
+    }
+    nes.cpu_mut().stop_trace().unwrap().finish().unwrap();
+
+    if let Some(log) = log {
+        let trace = String::from_utf8(buffer.0.take()).unwrap();
+        let mut trace_lines = trace.lines();
+        for (number, expected) in log.lines().enumerate() {
+            let actual = trace_lines.next().unwrap_or("<no more instructions>");
+            if actual.trim_end() != expected.trim_end() {
+                return Err(format!(
+                    "trace differs at line {}\nexpected: {expected}\nactual:   {actual}",
+                    number + 1
+                ));
+            }
+        }
+    }
+
+    let bus = &nes.cpu().bus;
+    match (bus.peek_u8(0x02), bus.peek_u8(0x03)) {
+        (0, 0) => Ok(()),
+        (official, unofficial) => Err(format!(
+            "error codes ${official:02X} (official) ${unofficial:02X} (unofficial)"
+        )),
+    }
+}
+
+/*
+Visual tests are compared by the CRC32 of their picture after a number of frames, kept
+next to the ROM as `<frames> <crc32>` in a .hash file. With SIMPLENESS_BLESS=1 set, the
+hash files are written from the current output instead, after checking it by eye.
+*/
+fn run_screen(rom: &Path) -> Result<(), String> {
+    let hash_path = rom.with_extension("hash");
+    let expected = fs::read_to_string(&hash_path).ok();
+    let bless = std::env::var_os(BLESS_VARIABLE).is_some();
+
+    let (frames, expected_hash) = match expected.as_deref().map(str::split_whitespace) {
+        Some(mut fields) => {
+            let frames = fields.next().and_then(|frames| frames.parse().ok());
+            let hash = fields
+                .next()
+                .and_then(|hash| u32::from_str_radix(hash, 16).ok());
+            match frames {
+                Some(frames) => (frames, hash),
+                None => return Err(format!("{} is malformed", hash_path.display())),
+            }
+        }
+        None if bless => (DEFAULT_SCREEN_FRAMES, None),
+        None => {
+            return Err(format!(
+                "no {}, run with {BLESS_VARIABLE}=1 to record one",
+                hash_path.display()
+            ));
+        }
+    };
+
+    let mut nes = load(rom)?;
+    for _ in 0..frames {
+        nes.run_frame();
+    }
+    let hash = crc32(nes.framebuffer());
+
+    if bless {
+        fs::write(&hash_path, format!("{frames} {hash:08x}\n")).unwrap();
+        return Ok(());
+    }
+    match expected_hash {
+        Some(expected) if expected == hash => Ok(()),
+        Some(expected) => Err(format!(
+            "screen after {frames} frames hashes to {hash:08x}, expected {expected:08x}"
+        )),
+        None => Err(format!("{} is malformed", hash_path.display())),
+    }
+}
+
+#[test]
+#[ignore = "needs blargg's test ROMs, see tests/roms/README.md"]
+fn blargg() {
+    run_suite("blargg", run_blargg);
+}
+
+#[test]
+#[ignore = "needs nestest.nes, see tests/roms/README.md"]
+fn nestest() {
+    run_suite("nestest", run_nestest);
+}
+
+#[test]
+#[ignore = "needs the ROMs for the screen hashes, see tests/roms/README.md"]
+fn screens() {
+    run_suite("screens", run_screen);
+}